@@ -0,0 +1,542 @@
+//! The allocator interface that the rest of this crate is written against.
+//!
+//! By default, this re-exports the unstable `allocator_api` items from `std`,
+//! which requires a nightly toolchain.
+//!
+//! With the `allocator-shim` feature enabled, this module instead defines its
+//! own `Allocator` trait and `AllocError` type. These mirror the `std` items
+//! method-for-method, so the rest of the crate behaves the same way on stable.
+//!
+//! `System` and `Global` are only available with the `std` feature. `Global`
+//! is the allocator that `std` collections use, so blocks from it can be
+//! handed to them.
+//!
+//! `DynAlloc` is a type-erased allocator handle, for callers who want a single
+//! concrete handle type regardless of which allocator sits behind it.
+//!
+//! `AvailableBytes` is implemented by allocators that can report how much
+//! room they have left, such as `LinearAlloc`.
+
+pub use core::alloc::{
+    Layout,
+    LayoutError,
+};
+
+#[cfg(feature = "std")]
+pub use std::alloc::System;
+
+#[cfg(all(feature = "std", not(feature = "allocator-shim")))]
+pub use std::alloc::Global;
+
+#[cfg(all(feature = "std", feature = "allocator-shim"))]
+pub use self::shim::Global;
+
+#[cfg(not(feature = "allocator-shim"))]
+pub use core::alloc::{
+    AllocError,
+    Allocator,
+};
+
+#[cfg(feature = "allocator-shim")]
+pub use self::shim::{
+    AllocError,
+    Allocator,
+};
+
+use core::{
+    fmt,
+    ptr::NonNull,
+    result,
+};
+
+/// A type-erased handle to an allocator.
+///
+/// Every `DynAlloc` is the same type, no matter which allocator it refers to.
+/// Containers using it go through a vtable for each allocator call.
+///
+/// `Allocator` methods only need `&self`, so a `DynAlloc` is just a shared
+/// reference. Any number of handles may refer to the same allocator, while
+/// callers still interact with it directly.
+#[derive(Copy, Clone)]
+pub struct DynAlloc<'a> {
+    alloc: &'a (dyn Allocator + 'a),
+}
+
+impl <'a> DynAlloc<'a> {
+    /// Erase the type of `alloc`.
+    pub fn new(alloc: &'a (dyn Allocator + 'a)) -> Self {
+        DynAlloc { alloc }
+    }
+
+    fn get(&self) -> &(dyn Allocator + 'a) {
+        self.alloc
+    }
+}
+
+impl <'a> fmt::Debug for DynAlloc<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("DynAlloc")
+         .field(&(self.alloc as *const (dyn Allocator + 'a)))
+         .finish()
+    }
+}
+
+unsafe impl <'a> Allocator for DynAlloc<'a> {
+    fn allocate(&self, layout: Layout)
+        -> result::Result<NonNull<[u8]>, AllocError>
+    {
+        self.get().allocate(layout)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout)
+        -> result::Result<NonNull<[u8]>, AllocError>
+    {
+        self.get().allocate_zeroed(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.get().deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(&self,
+                   ptr:        NonNull<u8>,
+                   old_layout: Layout,
+                   new_layout: Layout)
+        -> result::Result<NonNull<[u8]>, AllocError>
+    {
+        self.get().grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(&self,
+                          ptr:        NonNull<u8>,
+                          old_layout: Layout,
+                          new_layout: Layout)
+        -> result::Result<NonNull<[u8]>, AllocError>
+    {
+        self.get().grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(&self,
+                     ptr:        NonNull<u8>,
+                     old_layout: Layout,
+                     new_layout: Layout)
+        -> result::Result<NonNull<[u8]>, AllocError>
+    {
+        self.get().shrink(ptr, old_layout, new_layout)
+    }
+}
+
+/// An allocator that knows how many bytes it has left to hand out.
+///
+/// This is a hint for callers sizing their requests, such as
+/// `growth::FillAvailable`. Alignment padding may mean that a request for all
+/// of the available bytes still fails.
+pub trait AvailableBytes {
+    /// Gets the number of bytes that can still be allocated.
+    fn available_bytes(&self) -> usize;
+}
+
+impl <A: AvailableBytes + ?Sized> AvailableBytes for &A {
+    fn available_bytes(&self) -> usize {
+        (**self).available_bytes()
+    }
+}
+
+#[cfg(feature = "allocator-shim")]
+mod shim {
+    use core::{
+        alloc::Layout,
+        fmt,
+        ptr::{self, NonNull},
+        result,
+    };
+
+    #[cfg(feature = "std")]
+    use std::alloc::{self, GlobalAlloc, System};
+
+    /// The error returned when an allocator cannot satisfy a request.
+    ///
+    /// This mirrors `std::alloc::AllocError`.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct AllocError;
+
+    impl fmt::Display for AllocError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("memory allocation failed")
+        }
+    }
+
+    /// An allocator which hands out blocks described by a `Layout`.
+    ///
+    /// This mirrors `std::alloc::Allocator`, and carries the same contract.
+    /// Only `allocate` and `deallocate` need to be implemented. The remaining
+    /// methods have default implementations in terms of those two.
+    ///
+    /// # Safety
+    ///
+    /// Implementors must uphold the contract of `std::alloc::Allocator`:
+    /// returned blocks must be valid for reads and writes of their full length,
+    /// must satisfy the requested layout, and must stay valid until they are
+    /// passed to `deallocate`, `grow` or `shrink`.
+    pub unsafe trait Allocator {
+        /// Attempts to allocate a block of memory that fits `layout`.
+        fn allocate(&self, layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>;
+
+        /// Behaves like `allocate`, but also zeroes the returned block.
+        fn allocate_zeroed(&self, layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            let block = self.allocate(layout)?;
+            unsafe {
+                ptr::write_bytes(block.cast::<u8>().as_ptr(), 0, block.len());
+            }
+            Ok(block)
+        }
+
+        /// Deallocates the block referenced by `ptr`.
+        ///
+        /// # Safety
+        ///
+        /// `ptr` must denote a block currently allocated via this allocator,
+        /// and `layout` must fit that block.
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+
+        /// Attempts to extend the block referenced by `ptr`.
+        ///
+        /// # Safety
+        ///
+        /// `ptr` must denote a block currently allocated via this allocator,
+        /// `old_layout` must fit that block, and `new_layout.size()` must not
+        /// be smaller than `old_layout.size()`.
+        unsafe fn grow(&self,
+                       ptr:        NonNull<u8>,
+                       old_layout: Layout,
+                       new_layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            debug_assert!(new_layout.size() >= old_layout.size());
+
+            let new_block = self.allocate(new_layout)?;
+            ptr::copy_nonoverlapping(ptr.as_ptr(),
+                                     new_block.cast::<u8>().as_ptr(),
+                                     old_layout.size());
+            self.deallocate(ptr, old_layout);
+            Ok(new_block)
+        }
+
+        /// Behaves like `grow`, but also zeroes the new part of the block.
+        ///
+        /// # Safety
+        ///
+        /// See `grow`.
+        unsafe fn grow_zeroed(&self,
+                              ptr:        NonNull<u8>,
+                              old_layout: Layout,
+                              new_layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            let new_block = self.grow(ptr, old_layout, new_layout)?;
+            ptr::write_bytes(new_block.cast::<u8>().as_ptr()
+                                      .add(old_layout.size()),
+                             0,
+                             new_block.len() - old_layout.size());
+            Ok(new_block)
+        }
+
+        /// Attempts to shrink the block referenced by `ptr`.
+        ///
+        /// # Safety
+        ///
+        /// `ptr` must denote a block currently allocated via this allocator,
+        /// `old_layout` must fit that block, and `new_layout.size()` must not
+        /// be larger than `old_layout.size()`.
+        unsafe fn shrink(&self,
+                         ptr:        NonNull<u8>,
+                         old_layout: Layout,
+                         new_layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            debug_assert!(new_layout.size() <= old_layout.size());
+
+            let new_block = self.allocate(new_layout)?;
+            ptr::copy_nonoverlapping(ptr.as_ptr(),
+                                     new_block.cast::<u8>().as_ptr(),
+                                     new_layout.size());
+            self.deallocate(ptr, old_layout);
+            Ok(new_block)
+        }
+
+        /// Borrows this allocator, so it can be used where an `Allocator` is
+        /// taken by value.
+        fn by_ref(&self) -> &Self
+            where Self: Sized
+        {
+            self
+        }
+    }
+
+    unsafe impl <A> Allocator for &A
+        where A: Allocator + ?Sized
+    {
+        fn allocate(&self, layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            (**self).allocate(layout)
+        }
+
+        fn allocate_zeroed(&self, layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            (**self).allocate_zeroed(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            (**self).deallocate(ptr, layout)
+        }
+
+        unsafe fn grow(&self,
+                       ptr:        NonNull<u8>,
+                       old_layout: Layout,
+                       new_layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            (**self).grow(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn grow_zeroed(&self,
+                              ptr:        NonNull<u8>,
+                              old_layout: Layout,
+                              new_layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            (**self).grow_zeroed(ptr, old_layout, new_layout)
+        }
+
+        unsafe fn shrink(&self,
+                         ptr:        NonNull<u8>,
+                         old_layout: Layout,
+                         new_layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            (**self).shrink(ptr, old_layout, new_layout)
+        }
+    }
+
+    // A well aligned, non-null pointer for zero sized blocks.
+    // These never touch the system allocator.
+    #[cfg(feature = "std")]
+    fn dangling(layout: Layout) -> NonNull<[u8]> {
+        let ptr = ptr::null_mut::<u8>().wrapping_add(layout.align());
+        unsafe {
+            NonNull::slice_from_raw_parts(NonNull::new_unchecked(ptr), 0)
+        }
+    }
+
+    // Implements `Allocator` for a `GlobalAlloc`, like `std` does.
+    #[cfg(feature = "std")]
+    macro_rules! impl_allocator {
+        ($alloc:ty) => {
+            unsafe impl Allocator for $alloc {
+                fn allocate(&self, layout: Layout)
+                    -> result::Result<NonNull<[u8]>, AllocError>
+                {
+                    if layout.size() == 0 {
+                        return Ok(dangling(layout));
+                    }
+                    let ptr = unsafe { GlobalAlloc::alloc(self, layout) };
+                    NonNull::new(ptr)
+                        .map(|ptr| {
+                            NonNull::slice_from_raw_parts(ptr, layout.size())
+                        })
+                        .ok_or(AllocError)
+                }
+
+                fn allocate_zeroed(&self, layout: Layout)
+                    -> result::Result<NonNull<[u8]>, AllocError>
+                {
+                    if layout.size() == 0 {
+                        return Ok(dangling(layout));
+                    }
+                    let ptr = unsafe { GlobalAlloc::alloc_zeroed(self, layout) };
+                    NonNull::new(ptr)
+                        .map(|ptr| {
+                            NonNull::slice_from_raw_parts(ptr, layout.size())
+                        })
+                        .ok_or(AllocError)
+                }
+
+                unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                    if layout.size() != 0 {
+                        GlobalAlloc::dealloc(self, ptr.as_ptr(), layout);
+                    }
+                }
+
+                unsafe fn grow(&self,
+                               ptr:        NonNull<u8>,
+                               old_layout: Layout,
+                               new_layout: Layout)
+                    -> result::Result<NonNull<[u8]>, AllocError>
+                {
+                    debug_assert!(new_layout.size() >= old_layout.size());
+
+                    // `GlobalAlloc::realloc` can only keep the old alignment, and
+                    // cannot be handed zero sized blocks.
+                    if old_layout.size() == 0 ||
+                       old_layout.align() != new_layout.align()
+                    {
+                        let new_block = self.allocate(new_layout)?;
+                        ptr::copy_nonoverlapping(ptr.as_ptr(),
+                                                 new_block.cast::<u8>().as_ptr(),
+                                                 old_layout.size());
+                        self.deallocate(ptr, old_layout);
+                        return Ok(new_block);
+                    }
+
+                    let new_ptr = GlobalAlloc::realloc(self,
+                                                       ptr.as_ptr(),
+                                                       old_layout,
+                                                       new_layout.size());
+                    NonNull::new(new_ptr)
+                        .map(|ptr| {
+                            NonNull::slice_from_raw_parts(ptr, new_layout.size())
+                        })
+                        .ok_or(AllocError)
+                }
+
+                unsafe fn shrink(&self,
+                                 ptr:        NonNull<u8>,
+                                 old_layout: Layout,
+                                 new_layout: Layout)
+                    -> result::Result<NonNull<[u8]>, AllocError>
+                {
+                    debug_assert!(new_layout.size() <= old_layout.size());
+
+                    if new_layout.size() == 0 ||
+                       old_layout.align() != new_layout.align()
+                    {
+                        let new_block = self.allocate(new_layout)?;
+                        ptr::copy_nonoverlapping(ptr.as_ptr(),
+                                                 new_block.cast::<u8>().as_ptr(),
+                                                 new_layout.size());
+                        self.deallocate(ptr, old_layout);
+                        return Ok(new_block);
+                    }
+
+                    let new_ptr = GlobalAlloc::realloc(self,
+                                                       ptr.as_ptr(),
+                                                       old_layout,
+                                                       new_layout.size());
+                    NonNull::new(new_ptr)
+                        .map(|ptr| {
+                            NonNull::slice_from_raw_parts(ptr, new_layout.size())
+                        })
+                        .ok_or(AllocError)
+                }
+            }
+        };
+    }
+
+    /// The allocator registered with `#[global_allocator]`, which `std`
+    /// collections use.
+    ///
+    /// This mirrors `std::alloc::Global`, forwarding to `std::alloc::alloc`
+    /// and friends.
+    #[cfg(feature = "std")]
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct Global;
+
+    #[cfg(feature = "std")]
+    unsafe impl GlobalAlloc for Global {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            alloc::alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            alloc::alloc_zeroed(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            alloc::dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize)
+            -> *mut u8
+        {
+            alloc::realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl_allocator!(System);
+    #[cfg(feature = "std")]
+    impl_allocator!(Global);
+}
+
+#[cfg(all(test, feature = "std"))]
+mod t {
+    use super::*;
+    use std::ptr::NonNull;
+
+    #[test]
+    fn check_system_grow_keeps_data() {
+        let layout     = Layout::array::<u32>(2).unwrap();
+        let new_layout = Layout::array::<u32>(64).unwrap();
+
+        // Unsafe due to dereferencing and freeing pointers.
+        unsafe {
+            let block = System.allocate(layout).expect("allocate failed.");
+            assert_eq!(block.len(), layout.size());
+
+            let ptr: NonNull<u32> = block.cast();
+            ptr.as_ptr().write(23);
+            ptr.as_ptr().add(1).write(45);
+
+            let block = System.grow(ptr.cast(), layout, new_layout)
+                              .expect("grow failed.");
+            assert_eq!(block.len(), new_layout.size());
+
+            let ptr: NonNull<u32> = block.cast();
+            assert_eq!(*ptr.as_ptr(), 23);
+            assert_eq!(*ptr.as_ptr().add(1), 45);
+
+            System.deallocate(ptr.cast(), new_layout);
+        }
+    }
+
+    #[test]
+    fn check_system_zero_sized() {
+        let layout = Layout::from_size_align(0, 16).unwrap();
+        let block  = System.allocate(layout).expect("allocate failed.");
+
+        assert_eq!(block.len(), 0);
+        assert_eq!(block.cast::<u8>().as_ptr() as usize % 16, 0);
+        unsafe {
+            System.deallocate(block.cast(), layout);
+        }
+    }
+
+    #[test]
+    fn check_global_shrink_keeps_data() {
+        let layout     = Layout::array::<u32>(64).unwrap();
+        let new_layout = Layout::array::<u32>(2).unwrap();
+
+        // Unsafe due to dereferencing and freeing pointers.
+        unsafe {
+            let block = Global.allocate(layout).expect("allocate failed.");
+            let ptr: NonNull<u32> = block.cast();
+            ptr.as_ptr().write(23);
+            ptr.as_ptr().add(1).write(45);
+
+            let block = Global.shrink(ptr.cast(), layout, new_layout)
+                              .expect("shrink failed.");
+            assert_eq!(block.len(), new_layout.size());
+
+            let ptr: NonNull<u32> = block.cast();
+            assert_eq!(*ptr.as_ptr(), 23);
+            assert_eq!(*ptr.as_ptr().add(1), 45);
+
+            Global.deallocate(ptr.cast(), new_layout);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! A vec with a fixed capacity, which holds its items inline and never
+//! allocates.
+
+use core::{
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr,
+    result,
+};
+
+use storage_vec::{Storage, StorageVec};
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- ArrayVec Impl ----------------------------------------------------------
+
+/// A vec that holds up to `N` items inline, with no allocator at all.
+///
+/// It is a `StorageVec` over an array, so it has the same push/insert/drain
+/// API as `vec2::Vec`. Rather than growing when full, operations that need
+/// more room return a `CapacityErr`. See `slice_vec::SliceVec` for the same
+/// over a borrowed buffer.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{array_vec::ArrayVec, Error};
+/// #
+/// let mut v = ArrayVec::<u32, 3>::new();
+/// v.extend_from_slice(&[1, 2]).unwrap();
+/// v.insert(0, 0).unwrap();
+/// assert!(v.is_full());
+///
+/// match v.push(3) {
+///     Err(Error::CapacityErr) => {},
+///     res => panic!("expected CapacityErr, got {:?}", res),
+/// }
+///
+/// let drained: std::vec::Vec<_> = v.drain(1..).collect();
+/// assert_eq!(drained, [1, 2]);
+/// assert_eq!(v, [0]);
+/// ```
+pub type ArrayVec<T, const N: usize> = StorageVec<T, [MaybeUninit<T>; N]>;
+
+// Arrays never grow, so they keep the `CapacityErr` returning `try_reserve`.
+unsafe impl <T, const N: usize> Storage<T> for [MaybeUninit<T>; N] {
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    fn as_ptr(&self) -> *const T {
+        self[..].as_ptr() as *const T
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self[..].as_mut_ptr() as *mut T
+    }
+}
+
+impl <T, const N: usize> ArrayVec<T, N> {
+    /// Construct a new, empty ArrayVec.
+    pub const fn new() -> Self {
+        StorageVec::from_storage([const { MaybeUninit::uninit() }; N])
+    }
+
+    /// Construct a new ArrayVec holding the items of `iter`, returning a
+    /// `CapacityErr` if there are more than `N`.
+    pub fn try_from_iter<I>(iter: I) -> VecResult<Self>
+        where I: IntoIterator<Item = T>
+    {
+        let mut v = ArrayVec::new();
+        v.try_extend(iter)?;
+        Ok(v)
+    }
+
+    /// Converts a full ArrayVec into the array of its items.
+    ///
+    /// Returns the ArrayVec back if it isn't full.
+    pub fn into_inner(self) -> result::Result<[T; N], Self> {
+        if !self.is_full() {
+            return Err(self);
+        }
+        let me = ManuallyDrop::new(self);
+        unsafe {
+            // Every item is initialized, and the ArrayVec won't drop them.
+            Ok(ptr::read(me.as_ptr() as *const [T; N]))
+        }
+    }
+}
+
+impl <T, const N: usize> Default for ArrayVec<T, N> {
+    fn default() -> Self {
+        ArrayVec::new()
+    }
+}
+
+// ArrayVecs never allocate, so cloning can't fail.
+impl <T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut clone: Self = ArrayVec::new();
+        for elem in self {
+            unsafe {
+                ptr::write(clone.as_mut_ptr().add(clone.len), elem.clone());
+            }
+            clone.len += 1;
+        }
+        clone
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use test_support::{DropCounter, Liar, prop_config};
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        mem,
+        vec,
+    };
+
+    #[test]
+    fn check_push_insert_remove() {
+        let mut v = ArrayVec::<u32, 4>::new();
+        assert_eq!(v.capacity(), 4);
+        assert!(v.is_empty());
+
+        v.push(1).expect("v.push(..) failed.");
+        v.push(3).expect("v.push(..) failed.");
+        v.insert(1, 2).expect("v.insert(..) failed.");
+        v.insert(0, 0).expect("v.insert(..) failed.");
+        assert_eq!(v, [0, 1, 2, 3]);
+        assert!(v.is_full());
+        assert_eq!(v.remaining_capacity(), 0);
+
+        assert_eq!(v.remove(1), 1);
+        assert_eq!(v.swap_remove(0), 0);
+        assert_eq!(v.pop(), Some(2));
+        assert_eq!(v, [3]);
+        v[0] = 7;
+        assert_eq!(&v[..], &[7]);
+        assert_eq!(format!("{:?}", v), "[7]");
+    }
+
+    #[test]
+    fn check_reports_capacity_errors() {
+        let mut v = ArrayVec::<u8, 3>::new();
+        v.extend_from_slice(b"ab").expect("v.extend_from_slice(..) failed.");
+
+        match v.extend_from_slice(b"cd") {
+            Err(Error::CapacityErr) => {},
+            res => panic!("expected CapacityErr, got {:?}", res),
+        }
+        assert_eq!(v, *b"ab");
+        match v.resize(4, 0) {
+            Err(Error::CapacityErr) => {},
+            res => panic!("expected CapacityErr, got {:?}", res),
+        }
+        v.push(b'c').expect("v.push(..) failed.");
+        match v.insert(0, b'x') {
+            Err(Error::CapacityErr) => {},
+            res => panic!("expected CapacityErr, got {:?}", res),
+        }
+        assert_eq!(v, *b"abc");
+
+        // Iterators that don't size hint fill up as far as they can.
+        let mut w = ArrayVec::<u32, 3>::new();
+        match w.try_extend((0..).filter(|i| i % 2 == 0)) {
+            Err(Error::CapacityErr) => {},
+            res => panic!("expected CapacityErr, got {:?}", res),
+        }
+        assert_eq!(w, [0, 2, 4]);
+    }
+
+    #[test]
+    fn check_splice_reports_capacity_errors() {
+        let mut v = ArrayVec::<u32, 5>::try_from_iter([1, 2, 3, 4])
+                                       .expect("ArrayVec::try_from_iter(..) failed.");
+
+        match v.splice(..1, [0, 0, 0]) {
+            Err(Error::CapacityErr) => {},
+            Err(err) => panic!("expected CapacityErr, got {:?}", err),
+            Ok(_) => panic!("expected CapacityErr"),
+        }
+        assert_eq!(v, [1, 2, 3, 4]);
+
+        // Only two of the three items fit, and the last is dropped.
+        mem::drop(v.splice(1..2, Liar(3)).expect("v.splice(..) failed."));
+        assert_eq!(v, [1, 2, 1, 3, 4]);
+    }
+
+    #[test]
+    fn check_drain_and_into_iter() {
+        let mut v = ArrayVec::<u32, 8>::try_from_iter(0..8)
+                                       .expect("ArrayVec::try_from_iter(..) failed.");
+
+        let drained: vec::Vec<_> = v.drain(2..5).rev().collect();
+        assert_eq!(drained, [4, 3, 2]);
+        assert_eq!(v, [0, 1, 5, 6, 7]);
+
+        // A partly used Drain still closes the gap.
+        let mut drain = v.drain(1..3);
+        assert_eq!(drain.next(), Some(1));
+        mem::drop(drain);
+        assert_eq!(v, [0, 6, 7]);
+
+        let mut iter = v.into_iter();
+        assert_eq!(iter.next_back(), Some(7));
+        assert_eq!(iter.as_slice(), &[0, 6]);
+        assert!(iter.eq([0, 6]));
+    }
+
+    #[test]
+    fn check_retain_dedup_resize() {
+        let mut v = ArrayVec::<u32, 8>::new();
+        v.extend_from_slice(&[1, 1, 2, 3, 3, 4]).expect("v.extend_from_slice(..) failed.");
+
+        v.dedup();
+        assert_eq!(v, [1, 2, 3, 4]);
+        v.retain(|&i| i % 2 == 0);
+        assert_eq!(v, [2, 4]);
+        v.resize(4, 9).expect("v.resize(..) failed.");
+        assert_eq!(v, [2, 4, 9, 9]);
+        v.extend_from_within(..2).expect("v.extend_from_within(..) failed.");
+        assert_eq!(v, [2, 4, 9, 9, 2, 4]);
+
+        let clone = v.clone();
+        assert_eq!(clone, v);
+        assert!(v.into_inner().is_err());
+
+        let full = ArrayVec::<u32, 2>::try_from_iter([5, 6])
+                                     .expect("ArrayVec::try_from_iter(..) failed.");
+        assert_eq!(full.into_inner().ok(), Some([5, 6]));
+    }
+
+    #[test]
+    fn check_drops() {
+        let count = Cell::new(0);
+
+        let mut v = ArrayVec::<_, 4>::new();
+        for _ in 0..4 {
+            v.push(DropCounter(&count)).expect("v.push(..) failed.");
+        }
+        // Pushing onto a full ArrayVec drops the new item.
+        assert!(v.push(DropCounter(&count)).is_err());
+        assert_eq!(count.get(), 1);
+
+        v.drain(1..3);
+        assert_eq!(count.get(), 3);
+        let mut iter = v.into_iter();
+        mem::drop(iter.next());
+        assert_eq!(count.get(), 4);
+        mem::drop(iter);
+        assert_eq!(count.get(), 5);
+    }
+
+    #[test]
+    fn check_zero_sized() {
+        let mut v = ArrayVec::<(), 100>::new();
+        v.resize(100, ()).expect("v.resize(..) failed.");
+        assert!(v.push(()).is_err());
+        assert_eq!(v.drain(10..).count(), 90);
+        assert_eq!(v.into_iter().count(), 10);
+    }
+
+    // A step of the model test below.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(u8),
+        Pop,
+        Insert(usize, u8),
+        Remove(usize),
+        Drain(usize, usize),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => any::<u8>().prop_map(Op::Push),
+            2 => Just(Op::Pop),
+            2 => (any::<usize>(), any::<u8>()).prop_map(|(i, x)| Op::Insert(i, x)),
+            2 => any::<usize>().prop_map(Op::Remove),
+            1 => (any::<usize>(), any::<usize>()).prop_map(|(a, b)| Op::Drain(a, b)),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_matches_std_vec(ops in proptest::collection::vec(arb_op(), 0..128)) {
+            let mut v = ArrayVec::<u8, 8>::new();
+            let mut model = vec::Vec::new();
+
+            for op in ops {
+                match op {
+                    Op::Push(x) => {
+                        let res = v.push(x);
+                        prop_assert_eq!(res.is_ok(), model.len() < 8);
+                        if res.is_ok() {
+                            model.push(x);
+                        }
+                    },
+                    Op::Pop => prop_assert_eq!(v.pop(), model.pop()),
+                    Op::Insert(i, x) => {
+                        let i = i % (model.len() + 1);
+                        let res = v.insert(i, x);
+                        prop_assert_eq!(res.is_ok(), model.len() < 8);
+                        if res.is_ok() {
+                            model.insert(i, x);
+                        }
+                    },
+                    Op::Remove(i) if !model.is_empty() => {
+                        let i = i % model.len();
+                        prop_assert_eq!(v.remove(i), model.remove(i));
+                    },
+                    Op::Remove(_) => {},
+                    Op::Drain(a, b) => {
+                        let a = a % (model.len() + 1);
+                        let b = a + b % (model.len() - a + 1);
+                        let drained: vec::Vec<_> = v.drain(a..b).collect();
+                        let expected: vec::Vec<_> = model.drain(a..b).collect();
+                        prop_assert_eq!(drained, expected);
+                    },
+                }
+                prop_assert_eq!(v.as_slice(), model.as_slice());
+            }
+        }
+    }
+}
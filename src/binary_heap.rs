@@ -0,0 +1,665 @@
+//! A priority queue, kept as a binary max-heap in a `vec2::Vec`.
+
+use core::{
+    fmt,
+    iter,
+    mem::{self, ManuallyDrop},
+    ops,
+    ptr,
+    result,
+    slice,
+};
+
+use alloc_api;
+use growth::{
+    Doubling,
+    GrowthPolicy,
+};
+use vec2::{self, Vec};
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- BinaryHeap Impl --------------------------------------------------------
+
+/// A priority queue with a customizable memory allocator, which pops its
+/// greatest item first.
+///
+/// The items are kept in a `vec2::Vec`, so the heap uses the same allocator
+/// handle `A` and growth policy `G` as the Vec it is made from, and every
+/// operation that may allocate returns allocation errors rather than
+/// panicking.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{binary_heap::BinaryHeap, linear_alloc::LinearAlloc};
+/// #
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut h = BinaryHeap::new(&alloc);
+/// for &i in &[3u32, 1, 4, 1, 5] {
+///     h.push(i).unwrap();
+/// }
+/// assert_eq!(h.peek(), Some(&5));
+/// assert_eq!(h.pop(), Some(5));
+/// assert_eq!(h.pop(), Some(4));
+/// assert_eq!(h.into_sorted_vec().as_slice(), &[1, 1, 3]);
+/// ```
+pub struct BinaryHeap<T, A, G = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    data: Vec<T, A, G>, // The items, each no greater than its parent.
+}
+
+impl <T, A: alloc_api::Allocator> BinaryHeap<T, A> {
+    /// Construct a new BinaryHeap using the allocator handle `alloc`.
+    pub fn new(alloc: A) -> Self {
+        BinaryHeap {
+            data: Vec::new(alloc),
+        }
+    }
+
+    /// Construct a new BinaryHeap with room for at least `capacity` items,
+    /// using the allocator handle `alloc`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        Ok(BinaryHeap {
+            data: Vec::with_capacity(alloc, capacity)?,
+        })
+    }
+}
+
+impl <T, A, G> BinaryHeap<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Construct a new BinaryHeap using the allocator handle `alloc`, which
+    /// grows according to the policy `growth`.
+    pub fn with_growth(alloc: A, growth: G) -> Self {
+        BinaryHeap {
+            data: Vec::with_growth(alloc, growth),
+        }
+    }
+
+    /// Returns the allocator handle that the BinaryHeap is using.
+    pub fn alloc(&self) -> &A {
+        self.data.alloc()
+    }
+
+    /// The number of items the BinaryHeap can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// The number of items currently in the BinaryHeap.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if the BinaryHeap holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Reserve room for at least `additional` more items, returning any
+    /// allocation errors.
+    pub fn try_reserve(&mut self, additional: usize) -> VecResult<()> {
+        self.data.try_reserve(additional)
+    }
+
+    /// Returns the greatest item.
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    /// Returns the items, in heap order.
+    pub fn as_slice(&self) -> &[T] {
+        self.data.as_slice()
+    }
+
+    /// Returns an iterator over the items, in heap order.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Removes all items, and returns an iterator over them in heap order.
+    pub fn drain(&mut self) -> vec2::Drain<'_, T, A, G> {
+        self.data.drain(..)
+    }
+
+    /// Drops all items.
+    pub fn clear(&mut self) {
+        self.data.clear()
+    }
+
+    /// Returns the Vec of items, in heap order.
+    pub fn into_vec(self) -> Vec<T, A, G> {
+        self.data
+    }
+}
+
+impl <T, A, G> BinaryHeap<T, A, G>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Turns `vec` into a BinaryHeap, reordering its items in place.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{binary_heap::BinaryHeap, linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut v = Vec::new(&alloc);
+    /// v.extend_from_slice(&[2u32, 7, 1, 8]).unwrap();
+    ///
+    /// let h = BinaryHeap::from_vec(v);
+    /// assert_eq!(h.peek(), Some(&8));
+    /// ```
+    pub fn from_vec(vec: Vec<T, A, G>) -> Self {
+        let mut heap = BinaryHeap {
+            data: vec,
+        };
+        heap.rebuild();
+        heap
+    }
+
+    /// Push `item`, returning any allocation errors.
+    pub fn push(&mut self, item: T) -> VecResult<()> {
+        let old_len = self.len();
+        self.data.push(item)?;
+        self.sift_up(0, old_len);
+        Ok(())
+    }
+
+    /// Removes the greatest item, and returns it.
+    pub fn pop(&mut self) -> Option<T> {
+        self.data.pop().map(|mut item| {
+            if !self.is_empty() {
+                mem::swap(&mut item, &mut self.data[0]);
+                self.sift_down(0);
+            }
+            item
+        })
+    }
+
+    /// Returns the greatest item mutably. The heap is reordered once the
+    /// `PeekMut` is dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{binary_heap::BinaryHeap, linear_alloc::LinearAlloc};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut h = BinaryHeap::new(&alloc);
+    /// h.try_extend([1u32, 5, 2]).unwrap();
+    ///
+    /// *h.peek_mut().unwrap() = 0;
+    /// assert_eq!(h.peek(), Some(&2));
+    /// ```
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, T, A, G>> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(PeekMut {
+                heap: self,
+            })
+        }
+    }
+
+    /// Extend the BinaryHeap with the items of `iter`, returning any
+    /// allocation errors. The items before the failing one are kept.
+    pub fn try_extend<I>(&mut self, iter: I) -> VecResult<()>
+        where I: IntoIterator<Item = T>
+    {
+        let iter = iter.into_iter();
+        self.try_reserve(iter.size_hint().0)?;
+        for item in iter {
+            self.push(item)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the Vec of items, sorted from least to greatest.
+    pub fn into_sorted_vec(mut self) -> Vec<T, A, G> {
+        let mut end = self.len();
+        while end > 1 {
+            end -= 1;
+            self.data.swap(0, end);
+            self.sift_down_range(0, end);
+        }
+        self.into_vec()
+    }
+
+    // Reorders all items into a heap.
+    fn rebuild(&mut self) {
+        let mut n = self.len() / 2;
+        while n > 0 {
+            n -= 1;
+            self.sift_down(n);
+        }
+    }
+
+    // Moves the item at `pos` up towards `start` until it is no greater than
+    // its parent.
+    fn sift_up(&mut self, start: usize, pos: usize) {
+        unsafe {
+            let mut hole = Hole::new(&mut self.data, pos);
+            while hole.pos() > start {
+                let parent = (hole.pos() - 1) / 2;
+                if hole.element() <= hole.get(parent) {
+                    break;
+                }
+                hole.move_to(parent);
+            }
+        }
+    }
+
+    // Moves the item at `pos` down until it is no less than its children,
+    // looking only at the items before `end`.
+    fn sift_down_range(&mut self, pos: usize, end: usize) {
+        unsafe {
+            let mut hole = Hole::new(&mut self.data, pos);
+            let mut child = 2 * hole.pos() + 1;
+            while child < end {
+                // Pick the greater child.
+                if child + 1 < end && hole.get(child) <= hole.get(child + 1) {
+                    child += 1;
+                }
+                if hole.element() >= hole.get(child) {
+                    return;
+                }
+                hole.move_to(child);
+                child = 2 * hole.pos() + 1;
+            }
+        }
+    }
+
+    fn sift_down(&mut self, pos: usize) {
+        let len = self.len();
+        self.sift_down_range(pos, len);
+    }
+}
+
+// An item moved out of a slice, leaving a hole for others to be moved into.
+// Dropping it moves the item into the hole, so a panicking comparison can't
+// leave a hole behind.
+struct Hole<'a, T: 'a> {
+    data: &'a mut [T],
+    elem: ManuallyDrop<T>, // The item moved out.
+    pos:  usize,           // The index of the hole.
+}
+
+impl <'a, T> Hole<'a, T> {
+    // `pos` must be in bounds.
+    unsafe fn new(data: &'a mut [T], pos: usize) -> Self {
+        debug_assert!(pos < data.len());
+        let elem = ptr::read(data.get_unchecked(pos));
+        Hole {
+            data,
+            elem: ManuallyDrop::new(elem),
+            pos,
+        }
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn element(&self) -> &T {
+        &self.elem
+    }
+
+    // `index` must be in bounds, and not the hole.
+    unsafe fn get(&self, index: usize) -> &T {
+        debug_assert!(index != self.pos && index < self.data.len());
+        self.data.get_unchecked(index)
+    }
+
+    // Moves the item at `index` into the hole, leaving the hole at `index`.
+    // `index` must be in bounds, and not the hole.
+    unsafe fn move_to(&mut self, index: usize) {
+        debug_assert!(index != self.pos && index < self.data.len());
+        let ptr = self.data.as_mut_ptr();
+        ptr::copy_nonoverlapping(ptr.add(index), ptr.add(self.pos), 1);
+        self.pos = index;
+    }
+}
+
+impl <'a, T> Drop for Hole<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let pos = self.pos;
+            ptr::copy_nonoverlapping(&*self.elem, self.data.get_unchecked_mut(pos), 1);
+        }
+    }
+}
+
+// ----- BinaryHeap Traits ------------------------------------------------------
+
+impl <T, A, G> From<Vec<T, A, G>> for BinaryHeap<T, A, G>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn from(vec: Vec<T, A, G>) -> Self {
+        BinaryHeap::from_vec(vec)
+    }
+}
+
+impl <T, A, G> From<BinaryHeap<T, A, G>> for Vec<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn from(heap: BinaryHeap<T, A, G>) -> Self {
+        heap.into_vec()
+    }
+}
+
+impl <T, A, G> iter::IntoIterator for BinaryHeap<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = T;
+    type IntoIter = vec2::IntoIter<T, A, G>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl <'a, T, A, G> iter::IntoIterator for &'a BinaryHeap<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl <T, A, G> fmt::Debug for BinaryHeap<T, A, G>
+    where T: fmt::Debug,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+// ----- PeekMut & Traits -------------------------------------------------------
+
+// See `BinaryHeap::peek_mut()`
+pub struct PeekMut<'a, T: 'a, A: 'a, G: 'a = Doubling>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    heap: &'a mut BinaryHeap<T, A, G>, // Holds at least one item.
+}
+
+impl <'a, T, A, G> PeekMut<'a, T, A, G>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Removes the peeked item from the heap, and returns it.
+    pub fn pop(this: Self) -> T {
+        let this = ManuallyDrop::new(this);
+        unsafe {
+            // The PeekMut won't be dropped, so its heap is moved out.
+            let heap: &mut BinaryHeap<T, A, G> = ptr::read(&this.heap);
+            heap.pop().expect("PeekMut on an empty heap")
+        }
+    }
+}
+
+impl <'a, T, A, G> Drop for PeekMut<'a, T, A, G>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn drop(&mut self) {
+        self.heap.sift_down(0);
+    }
+}
+
+impl <'a, T, A, G> ops::Deref for PeekMut<'a, T, A, G>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.heap.data[0]
+    }
+}
+
+impl <'a, T, A, G> ops::DerefMut for PeekMut<'a, T, A, G>
+    where T: Ord,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.heap.data[0]
+    }
+}
+
+impl <'a, T, A, G> fmt::Debug for PeekMut<'a, T, A, G>
+    where T: Ord + fmt::Debug,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PeekMut").field(&self.heap.data[0]).finish()
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use test_support::prop_config;
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        cmp,
+        collections,
+        panic::{self, AssertUnwindSafe},
+        vec,
+    };
+
+    // Checks that no item is greater than its parent.
+    fn is_heap<T: Ord, A: alloc_api::Allocator, G: GrowthPolicy<A>>(
+        heap: &BinaryHeap<T, A, G>) -> bool
+    {
+        let items = heap.as_slice();
+        (1..items.len()).all(|i| items[i] <= items[(i - 1) / 2])
+    }
+
+    #[test]
+    fn check_push_pop_peek() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut h = BinaryHeap::new(&alloc);
+        assert_eq!(h.peek(), None);
+        assert_eq!(h.pop(), None);
+
+        for &i in &[5u32, 9, 1, 7, 3, 9, 0] {
+            h.push(i).expect("h.push(..) failed.");
+            assert!(is_heap(&h));
+        }
+        assert_eq!(h.len(), 7);
+        assert_eq!(h.peek(), Some(&9));
+
+        let mut popped = vec::Vec::new();
+        while let Some(i) = h.pop() {
+            assert!(is_heap(&h));
+            popped.push(i);
+        }
+        assert_eq!(popped, [9, 9, 7, 5, 3, 1, 0]);
+        assert!(h.is_empty());
+    }
+
+    #[test]
+    fn check_peek_mut() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut h = BinaryHeap::new(&alloc);
+        h.try_extend(vec![4u32, 8, 2, 6]).expect("h.try_extend(..) failed.");
+
+        {
+            let mut top = h.peek_mut().expect("h.peek_mut() failed.");
+            assert_eq!(*top, 8);
+            *top = 1;
+        }
+        assert!(is_heap(&h));
+        assert_eq!(h.peek(), Some(&6));
+
+        let top = h.peek_mut().expect("h.peek_mut() failed.");
+        assert_eq!(format!("{:?}", top), "PeekMut(6)");
+        assert_eq!(PeekMut::pop(top), 6);
+        assert!(is_heap(&h));
+        assert_eq!(h.len(), 3);
+        assert_eq!(h.into_sorted_vec(), [1, 2, 4]);
+    }
+
+    #[test]
+    fn check_from_vec_and_sorting() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::new(&alloc);
+        v.extend_from_slice(&[3u32, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5])
+         .expect("v.extend_from_slice(..) failed.");
+
+        let h = BinaryHeap::from(v);
+        assert!(is_heap(&h));
+        assert_eq!(h.peek(), Some(&9));
+        assert_eq!(h.iter().count(), 11);
+        assert_eq!(h.into_sorted_vec(), [1, 1, 2, 3, 3, 4, 5, 5, 5, 6, 9]);
+    }
+
+    #[test]
+    fn check_drain_and_into_iter() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut h = BinaryHeap::new(&alloc);
+        h.try_extend(0..10u32).expect("h.try_extend(..) failed.");
+
+        let mut drained: vec::Vec<_> = h.drain().collect();
+        drained.sort();
+        assert_eq!(drained, (0..10).collect::<vec::Vec<_>>());
+        assert!(h.is_empty());
+
+        h.try_extend(0..3u32).expect("h.try_extend(..) failed.");
+        assert_eq!(format!("{:?}", h), "[2, 0, 1]");
+        let mut items: vec::Vec<_> = h.into_iter().collect();
+        items.sort();
+        assert_eq!(items, [0, 1, 2]);
+    }
+
+    #[test]
+    fn check_push_reports_errors() {
+        // Room for four u32s, plus padding to align them.
+        let mut buf = [0u8; 19];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut h = BinaryHeap::with_capacity(&alloc, 4)
+                               .expect("BinaryHeap::with_capacity(..) failed.");
+        h.try_extend(vec![1u32, 2, 3, 4]).expect("h.try_extend(..) failed.");
+
+        let res = h.push(5);
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert!(is_heap(&h));
+        assert_eq!(h.into_sorted_vec(), [1, 2, 3, 4]);
+    }
+
+    // Panics when compared, once its cell counts down to zero.
+    struct PanicOnCmp<'a>(u32, &'a Cell<usize>);
+
+    impl <'a> PartialEq for PanicOnCmp<'a> {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == cmp::Ordering::Equal
+        }
+    }
+
+    impl <'a> Eq for PanicOnCmp<'a> {}
+
+    impl <'a> PartialOrd for PanicOnCmp<'a> {
+        fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl <'a> Ord for PanicOnCmp<'a> {
+        fn cmp(&self, other: &Self) -> cmp::Ordering {
+            let left = self.1.get();
+            if left == 0 {
+                panic!("compared too often");
+            }
+            self.1.set(left - 1);
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn check_panicking_cmp_keeps_items() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+        let cmps = Cell::new(usize::MAX);
+        let mut h = BinaryHeap::new(&alloc);
+        for i in 0..20 {
+            h.push(PanicOnCmp(i, &cmps)).expect("h.push(..) failed.");
+        }
+
+        cmps.set(2);
+        let res = panic::catch_unwind(AssertUnwindSafe(|| h.pop()));
+        assert!(res.is_err());
+
+        // No item was lost or duplicated.
+        cmps.set(usize::MAX);
+        let mut items: vec::Vec<_> = h.into_iter().map(|item| item.0).collect();
+        items.sort();
+        assert_eq!(items, (0..19).collect::<vec::Vec<_>>());
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_matches_std_binary_heap(ops in proptest::collection::vec(any::<Option<u8>>(), 0..256)) {
+            let mut buf = vec![0u8; 4096];
+            let alloc = LinearAlloc::new(&mut buf);
+            let mut h = BinaryHeap::new(&alloc);
+            let mut model = collections::BinaryHeap::new();
+
+            for op in ops {
+                match op {
+                    Some(i) => {
+                        h.push(i).expect("h.push(..) failed.");
+                        model.push(i);
+                    },
+                    None => prop_assert_eq!(h.pop(), model.pop()),
+                }
+                prop_assert_eq!(h.peek(), model.peek());
+            }
+            prop_assert!(is_heap(&h));
+            let (sorted, model_sorted) = (h.into_sorted_vec(), model.into_sorted_vec());
+            prop_assert_eq!(sorted.as_slice(), model_sorted.as_slice());
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use core::{
+    borrow,
+    cmp,
+    convert,
+    fmt,
+    hash,
+    marker::PhantomData,
+    mem,
+    ops,
+    ptr::{self, NonNull},
+    result,
+};
+
+use alloc_api;
+use string::String;
+use vec2::Vec;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- Box Impl ---------------------------------------------------------------
+
+/// A pointer to a value that lives in a customizable memory allocator.
+///
+/// It differs from `std::boxed::Box` by storing its own allocator handle, like
+/// `vec2::Vec` does. Methods are associated functions, such as
+/// `Box::into_raw_with_alloc(b)`, so that they don't shadow methods of `T`.
+///
+/// `T` may be unsized, as in `Box<[T], A>` and `Box<str, A>`.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{boxed::Box, linear_alloc::LinearAlloc};
+/// #
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut b = Box::try_new_in(5u32, &alloc).unwrap();
+/// *b += 1;
+/// assert_eq!(Box::into_inner(b), 6);
+///
+/// let s = Box::<str, _>::try_from_str_in("hello", &alloc).unwrap();
+/// assert_eq!(&*s, "hello");
+/// ```
+pub struct Box<T: ?Sized, A: alloc_api::Allocator> {
+    ptr:   NonNull<T>, // The value, in a block from `alloc`.
+    alloc: A,
+    _own:  PhantomData<T>,
+}
+
+impl <T, A: alloc_api::Allocator> Box<T, A> {
+    /// Moves `value` into a block from the allocator handle `alloc`,
+    /// returning any allocation errors.
+    ///
+    /// Zero sized values don't allocate.
+    pub fn try_new_in(value: T, alloc: A) -> VecResult<Self> {
+        let layout = alloc_api::Layout::new::<T>();
+        let ptr: NonNull<T> = if layout.size() == 0 {
+            NonNull::dangling()
+        } else {
+            alloc.allocate(layout)?.cast()
+        };
+        unsafe {
+            ptr.as_ptr().write(value);
+            Ok(Box::from_raw_in(ptr.as_ptr(), alloc))
+        }
+    }
+
+    /// Moves the value out of the Box, and frees its block.
+    pub fn into_inner(b: Self) -> T {
+        let (ptr, alloc) = Box::into_raw_with_alloc(b);
+        unsafe {
+            let value = ptr::read(ptr);
+            let layout = alloc_api::Layout::new::<T>();
+            if layout.size() != 0 {
+                alloc.deallocate(NonNull::new_unchecked(ptr).cast(), layout);
+            }
+            value
+        }
+    }
+
+    /// Clone the value into a new Box, which shares this Box's allocator,
+    /// returning any allocation errors.
+    pub fn try_clone(b: &Self) -> VecResult<Self>
+        where T: Clone,
+              A: Clone
+    {
+        Box::try_new_in((**b).clone(), b.alloc.clone())
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> Box<T, A> {
+    /// Construct a Box from a pointer to a value, and the allocator handle
+    /// that its block came from.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid `T`, in a block allocated by `alloc` which
+    /// `Layout::for_value(&*ptr)` fits. If that layout has a size of zero,
+    /// `ptr` only needs to be non-null and aligned.
+    pub unsafe fn from_raw_in(ptr: *mut T, alloc: A) -> Self {
+        Box {
+            ptr:   NonNull::new_unchecked(ptr),
+            alloc,
+            _own:  PhantomData,
+        }
+    }
+
+    /// Returns the allocator handle that the Box is using.
+    pub fn alloc(b: &Self) -> &A {
+        &b.alloc
+    }
+
+    /// Gives up ownership of the value, returning its pointer and the
+    /// allocator handle. `Box::from_raw_in` turns these back into a Box.
+    pub fn into_raw_with_alloc(b: Self) -> (*mut T, A) {
+        let ptr = b.ptr.as_ptr();
+        unsafe {
+            // We need to use ptr::read to move the alloc out, since Box
+            // implements Drop (and so we can't destructure it)
+            let alloc = ptr::read(&b.alloc);
+            mem::forget(b);
+            (ptr, alloc)
+        }
+    }
+
+    /// Gives up ownership of the value without ever freeing it, and returns a
+    /// reference that lives as long as the allocator handle's type allows.
+    ///
+    /// The allocator handle is leaked too. With a `&LinearAlloc`, the block
+    /// stays in use until the `LinearAlloc` goes away.
+    pub fn leak<'b>(b: Self) -> &'b mut T
+        where A: 'b
+    {
+        let (ptr, alloc) = Box::into_raw_with_alloc(b);
+        mem::forget(alloc);
+        unsafe {
+            &mut *ptr
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> Box<[T], A> {
+    /// Clones the items of `slice` into a new boxed slice, using the
+    /// allocator handle `alloc`, and returning any allocation errors.
+    pub fn try_from_slice_in(slice: &[T], alloc: A) -> VecResult<Self>
+        where T: Clone
+    {
+        let mut vec = Vec::with_capacity(alloc, slice.len())?;
+        vec.extend_from_slice(slice)?;
+        vec.into_boxed_slice()
+    }
+
+    /// Converts the boxed slice into a Vec, without copying.
+    pub fn into_vec(b: Self) -> Vec<T, A> {
+        let len = b.len();
+        let (ptr, alloc) = Box::into_raw_with_alloc(b);
+        unsafe {
+            Vec::from_raw_parts_in(ptr as *mut T, len, len, alloc)
+        }
+    }
+}
+
+impl <A: alloc_api::Allocator> Box<str, A> {
+    /// Copies `s` into a new boxed `str`, using the allocator handle `alloc`,
+    /// and returning any allocation errors.
+    pub fn try_from_str_in(s: &str, alloc: A) -> VecResult<Self> {
+        let bytes = Box::try_from_slice_in(s.as_bytes(), alloc)?;
+        let (ptr, alloc) = Box::into_raw_with_alloc(bytes);
+        unsafe {
+            Ok(Box::from_raw_in(ptr as *mut str, alloc))
+        }
+    }
+
+    /// Converts the boxed `str` into a String, without copying.
+    pub fn into_string(b: Self) -> String<A> {
+        let (ptr, alloc) = Box::into_raw_with_alloc(b);
+        unsafe {
+            let bytes = Box::from_raw_in(ptr as *mut [u8], alloc);
+            String::from_utf8_unchecked(Box::into_vec(bytes))
+        }
+    }
+}
+
+// ----- Box Traits -------------------------------------------------------------
+
+impl <T: ?Sized, A: alloc_api::Allocator> Drop for Box<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let layout = alloc_api::Layout::for_value(self.ptr.as_ref());
+            ptr::drop_in_place(self.ptr.as_ptr());
+            // Zero sized values never had a block.
+            if layout.size() != 0 {
+                self.alloc.deallocate(self.ptr.cast(), layout);
+            }
+        }
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> ops::Deref for Box<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe {
+            self.ptr.as_ref()
+        }
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> ops::DerefMut for Box<T, A> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe {
+            self.ptr.as_mut()
+        }
+    }
+}
+
+impl <T, A> fmt::Debug for Box<T, A>
+    where T: fmt::Debug + ?Sized,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl <T, A> fmt::Display for Box<T, A>
+    where T: fmt::Display + ?Sized,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl <T, A, U, B> cmp::PartialEq<Box<U, B>> for Box<T, A>
+    where T: cmp::PartialEq<U> + ?Sized,
+          A: alloc_api::Allocator,
+          U: ?Sized,
+          B: alloc_api::Allocator
+{
+    fn eq(&self, other: &Box<U, B>) -> bool {
+        **self == **other
+    }
+}
+
+impl <T, A> cmp::Eq for Box<T, A>
+    where T: cmp::Eq + ?Sized,
+          A: alloc_api::Allocator
+{}
+
+impl <T, A, U, B> cmp::PartialOrd<Box<U, B>> for Box<T, A>
+    where T: cmp::PartialOrd<U> + ?Sized,
+          A: alloc_api::Allocator,
+          U: ?Sized,
+          B: alloc_api::Allocator
+{
+    fn partial_cmp(&self, other: &Box<U, B>) -> Option<cmp::Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl <T, A> cmp::Ord for Box<T, A>
+    where T: cmp::Ord + ?Sized,
+          A: alloc_api::Allocator
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl <T, A> hash::Hash for Box<T, A>
+    where T: hash::Hash + ?Sized,
+          A: alloc_api::Allocator
+{
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> convert::AsRef<T> for Box<T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> convert::AsMut<T> for Box<T, A> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> borrow::Borrow<T> for Box<T, A> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl <T: ?Sized, A: alloc_api::Allocator> borrow::BorrowMut<T> for Box<T, A> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use alloc_api::Allocator;
+    use linear_alloc::LinearAlloc;
+
+    #[test]
+    fn check_raw_round_trip() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let layout = alloc_api::Layout::new::<u64>();
+
+        let b = unsafe {
+            let ptr = alloc.allocate(layout).expect("allocate failed.");
+            let ptr = ptr.cast::<u64>().as_ptr();
+            ptr.write(17);
+            Box::from_raw_in(ptr, &alloc)
+        };
+        assert_eq!(*b, 17);
+        assert_eq!(format!("{:?}", b), "17");
+        assert!(alloc.bytes_in_use() >= 8);
+
+        let (ptr, handle) = Box::into_raw_with_alloc(b);
+        let mut b = unsafe { Box::from_raw_in(ptr, handle) };
+        *b += 1;
+        assert_eq!(*b, 18);
+
+        // Dropping frees the block, which is on top of the stack.
+        let marker = alloc.bytes_in_use() - 8;
+        mem::drop(b);
+        assert_eq!(alloc.bytes_in_use(), marker);
+    }
+
+    #[test]
+    fn check_new_and_into_inner() {
+        // Aligned, so that no padding is counted as in use.
+        #[repr(align(8))] struct Buffer { buf: [u8; 64] }
+        let mut buf = Buffer { buf: [0u8; 64] };
+        let alloc = LinearAlloc::new(&mut buf.buf);
+
+        let mut b = Box::try_new_in([1u32, 2, 3], &alloc)
+                        .expect("Box::try_new_in(..) failed.");
+        b[1] = 5;
+        assert_eq!(*b, [1, 5, 3]);
+        assert_eq!(alloc.bytes_in_use(), 12);
+
+        let c = Box::try_clone(&b).expect("Box::try_clone(..) failed.");
+        assert_eq!(b, c);
+        mem::drop(c);
+
+        assert_eq!(Box::into_inner(b), [1, 5, 3]);
+        assert_eq!(alloc.bytes_in_use(), 0);
+    }
+
+    #[test]
+    fn check_new_reports_errors() {
+        let mut buf = [0u8; 8];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let res = Box::try_new_in([0u64; 2], &alloc);
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn check_zero_sized() {
+        let mut buf = [0u8; 0];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let b = Box::try_new_in((), &alloc).expect("Box::try_new_in(..) failed.");
+        assert_eq!(*b, ());
+        assert_eq!(Box::into_inner(b), ());
+
+        let s = Box::<[u32], _>::try_from_slice_in(&[], &alloc)
+                    .expect("Box::try_from_slice_in(..) failed.");
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn check_drops_value() {
+        use std::rc::Rc;
+
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let rc = Rc::new(());
+
+        let b = Box::try_new_in(rc.clone(), &alloc)
+                    .expect("Box::try_new_in(..) failed.");
+        assert_eq!(Rc::strong_count(&rc), 2);
+        mem::drop(b);
+        assert_eq!(Rc::strong_count(&rc), 1);
+
+        let b = Box::<[Rc<()>], _>::try_from_slice_in(&[rc.clone(), rc.clone()],
+                                                      &alloc)
+                    .expect("Box::try_from_slice_in(..) failed.");
+        assert_eq!(Rc::strong_count(&rc), 3);
+        mem::drop(b);
+        assert_eq!(Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn check_leak() {
+        // Aligned, so that no padding is counted as in use.
+        #[repr(align(8))] struct Buffer { buf: [u8; 64] }
+        let mut buf = Buffer { buf: [0u8; 64] };
+        let alloc = LinearAlloc::new(&mut buf.buf);
+
+        let r: &mut u32 = Box::leak(Box::try_new_in(7u32, &alloc)
+                                        .expect("Box::try_new_in(..) failed."));
+        *r += 1;
+        assert_eq!(*r, 8);
+        assert_eq!(alloc.bytes_in_use(), 4);
+    }
+
+    #[test]
+    fn check_boxed_str() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let b = Box::<str, _>::try_from_str_in("héllo", &alloc)
+                    .expect("Box::try_from_str_in(..) failed.");
+        assert_eq!(&*b, "héllo");
+        assert_eq!(format!("{} {:?}", b, b), "héllo \"héllo\"");
+
+        let mut s = Box::into_string(b);
+        s.push('!').expect("s.push(..) failed.");
+        assert_eq!(s, "héllo!");
+
+        let b = s.into_boxed_str().expect("s.into_boxed_str() failed.");
+        assert_eq!(&*b, "héllo!");
+    }
+}
@@ -0,0 +1,1915 @@
+//! An ordered map whose nodes live in a customizable memory allocator.
+//!
+//! It is a B-tree, like `std`'s. Each node is its own block from the
+//! allocator: nodes are allocated as the tree grows, and handed back as it
+//! shrinks. A `LinearAlloc` can only reuse a handed back block if it was the
+//! last one allocated, so with one, the space of removed nodes is mostly not
+//! reused until the allocator itself is done with.
+//!
+//! Inserting splits full nodes on the way down the tree, so that a failed
+//! node allocation leaves the tree valid, and unchanged apart from its shape.
+//! Removing never allocates.
+
+use core::{
+    borrow::Borrow,
+    cmp,
+    fmt,
+    iter,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    ops::{self, Bound, RangeBounds},
+    ptr::{self, NonNull},
+    result,
+};
+
+use alloc_api;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+const B:        usize = 6;
+const CAPACITY: usize = 2 * B - 1; // The most items a node holds.
+const MIN_LEN:  usize = B - 1;     // The fewest items a non-root node holds.
+
+// ----- Nodes ------------------------------------------------------------------
+
+// A node of the tree. Leaves don't use their edges.
+//
+// The links are `*const`, so that the map is covariant in `K` and `V`, like
+// `std`'s. Nodes are still written through them, as `*mut`.
+struct Node<K, V> {
+    parent:     *const Node<K, V>, // Null for the root.
+    parent_idx: u16,               // Which of the parent's edges this node is.
+    len:        u16,               // Count of keys, and of values.
+    keys:       [MaybeUninit<K>; CAPACITY],
+    vals:       [MaybeUninit<V>; CAPACITY],
+    edges:      [*const Node<K, V>; CAPACITY + 1],
+}
+
+// These work through raw pointers, and never make references to whole
+// arrays, so that references handed out to single items stay valid.
+
+unsafe fn len<K, V>(node: *mut Node<K, V>) -> usize {
+    (*node).len as usize
+}
+
+unsafe fn set_len<K, V>(node: *mut Node<K, V>, len: usize) {
+    (*node).len = len as u16;
+}
+
+unsafe fn key_at<K, V>(node: *mut Node<K, V>, idx: usize) -> *mut K {
+    (ptr::addr_of_mut!((*node).keys) as *mut K).add(idx)
+}
+
+unsafe fn val_at<K, V>(node: *mut Node<K, V>, idx: usize) -> *mut V {
+    (ptr::addr_of_mut!((*node).vals) as *mut V).add(idx)
+}
+
+unsafe fn edge_at<K, V>(node: *mut Node<K, V>, idx: usize) -> *mut *mut Node<K, V> {
+    (ptr::addr_of_mut!((*node).edges) as *mut *mut Node<K, V>).add(idx)
+}
+
+unsafe fn edge<K, V>(node: *mut Node<K, V>, idx: usize) -> *mut Node<K, V> {
+    *edge_at(node, idx)
+}
+
+// Points the children at `idxs` back at their slots in `node`.
+unsafe fn correct_parent_links<K, V>(node: *mut Node<K, V>, idxs: ops::Range<usize>) {
+    for idx in idxs {
+        let child = edge(node, idx);
+        (*child).parent = node;
+        (*child).parent_idx = idx as u16;
+    }
+}
+
+// Inserts `item` at `idx` of the `len` items at `base`, moving the later ones
+// up.
+unsafe fn slice_insert<T>(base: *mut T, len: usize, idx: usize, item: T) {
+    ptr::copy(base.add(idx), base.add(idx + 1), len - idx);
+    ptr::write(base.add(idx), item);
+}
+
+// Removes the item at `idx` of the `len` items at `base`, moving the later
+// ones down.
+unsafe fn slice_remove<T>(base: *mut T, len: usize, idx: usize) -> T {
+    let item = ptr::read(base.add(idx));
+    ptr::copy(base.add(idx + 1), base.add(idx), len - idx - 1);
+    item
+}
+
+// Returns `Ok` with the index of `key` in `node`, or `Err` with the index of
+// the edge to search next.
+unsafe fn search_node<K, V, Q>(node: *mut Node<K, V>, key: &Q) -> result::Result<usize, usize>
+    where K: Borrow<Q>,
+          Q: Ord + ?Sized
+{
+    for idx in 0..len(node) {
+        match key.cmp((*key_at(node, idx)).borrow()) {
+            cmp::Ordering::Greater => {},
+            cmp::Ordering::Equal   => return Ok(idx),
+            cmp::Ordering::Less    => return Err(idx),
+        }
+    }
+    Err(len(node))
+}
+
+// Frees the subtree under `node`, which is `height` levels above the leaves,
+// and drops its items if `drop_items` is set.
+unsafe fn free_tree<K, V, A>(alloc: &A, node: *mut Node<K, V>, height: usize, drop_items: bool)
+    where A: alloc_api::Allocator
+{
+    if drop_items {
+        for idx in 0..len(node) {
+            ptr::drop_in_place(key_at(node, idx));
+            ptr::drop_in_place(val_at(node, idx));
+        }
+    }
+    if height > 0 {
+        for idx in 0..=len(node) {
+            free_tree(alloc, edge(node, idx), height - 1, drop_items);
+        }
+    }
+    alloc.deallocate(NonNull::new_unchecked(node).cast(),
+                     alloc_api::Layout::new::<Node<K, V>>());
+}
+
+// A spot between two items (or at either end) of a leaf.
+struct LeafEdge<K, V> {
+    node: *const Node<K, V>,
+    idx:  usize,
+}
+
+impl <K, V> Clone for LeafEdge<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl <K, V> Copy for LeafEdge<K, V> {}
+
+impl <K, V> PartialEq for LeafEdge<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node && self.idx == other.idx
+    }
+}
+
+unsafe fn first_leaf_edge<K, V>(mut node: *mut Node<K, V>, height: usize) -> LeafEdge<K, V> {
+    for _ in 0..height {
+        node = edge(node, 0);
+    }
+    LeafEdge { node, idx: 0 }
+}
+
+unsafe fn last_leaf_edge<K, V>(mut node: *mut Node<K, V>, height: usize) -> LeafEdge<K, V> {
+    for _ in 0..height {
+        node = edge(node, len(node));
+    }
+    LeafEdge { node, idx: len(node) }
+}
+
+// The items between two leaf edges, in order. Each spot between items is
+// exactly one leaf edge, so the range is empty once its ends meet.
+struct LeafRange<K, V> {
+    front: LeafEdge<K, V>,
+    back:  LeafEdge<K, V>,
+}
+
+impl <K, V> Clone for LeafRange<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl <K, V> Copy for LeafRange<K, V> {}
+
+impl <K, V> LeafRange<K, V> {
+    fn none() -> Self {
+        let edge = LeafEdge { node: ptr::null(), idx: 0 };
+        LeafRange { front: edge, back: edge }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.front == self.back
+    }
+
+    // Steps the front over the next item, and returns its node and index.
+    // The range must not be empty.
+    unsafe fn next_kv(&mut self) -> (*mut Node<K, V>, usize) {
+        let LeafEdge { node, mut idx } = self.front;
+        let mut node = node as *mut Node<K, V>;
+        let mut height = 0;
+        while idx >= len(node) {
+            idx = (*node).parent_idx as usize;
+            node = (*node).parent as *mut Node<K, V>;
+            height += 1;
+        }
+        self.front = if height == 0 {
+            LeafEdge { node, idx: idx + 1 }
+        } else {
+            first_leaf_edge(edge(node, idx + 1), height - 1)
+        };
+        (node, idx)
+    }
+
+    // Steps the back over the previous item, and returns its node and index.
+    // The range must not be empty.
+    unsafe fn next_back_kv(&mut self) -> (*mut Node<K, V>, usize) {
+        let LeafEdge { node, mut idx } = self.back;
+        let mut node = node as *mut Node<K, V>;
+        let mut height = 0;
+        while idx == 0 {
+            idx = (*node).parent_idx as usize;
+            node = (*node).parent as *mut Node<K, V>;
+            height += 1;
+        }
+        self.back = if height == 0 {
+            LeafEdge { node, idx: idx - 1 }
+        } else {
+            last_leaf_edge(edge(node, idx - 1), height - 1)
+        };
+        (node, idx - 1)
+    }
+}
+
+// ----- BTreeMap Impl ----------------------------------------------------------
+
+/// An ordered map with a customizable memory allocator.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{btree_map::BTreeMap, linear_alloc::LinearAlloc};
+/// #
+/// let mut buf = [0u8; 1024];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut m = BTreeMap::new(&alloc);
+/// for &(k, v) in &[(3u32, 'c'), (1, 'a'), (2, 'b'), (4, 'd')] {
+///     m.insert(k, v).unwrap();
+/// }
+/// assert_eq!(m.get(&2), Some(&'b'));
+///
+/// let middle: String = m.range(2..4).map(|(_, &v)| v).collect();
+/// assert_eq!(middle, "bc");
+/// assert_eq!(m.pop_first(), Some((1, 'a')));
+/// ```
+pub struct BTreeMap<K, V, A>
+    where A: alloc_api::Allocator
+{
+    alloc:  A,
+    root:   Option<NonNull<Node<K, V>>>, // None while the map is empty.
+    height: usize,                       // Count of levels below the root.
+    len:    usize,                       // Count of items.
+    _own:   PhantomData<(K, V)>,
+}
+
+impl <K, V, A> BTreeMap<K, V, A>
+    where A: alloc_api::Allocator
+{
+    /// Construct a new BTreeMap using the allocator handle `alloc`. Nodes
+    /// are only allocated once items are inserted.
+    pub fn new(alloc: A) -> Self {
+        BTreeMap {
+            alloc,
+            root:   None,
+            height: 0,
+            len:    0,
+            _own:   PhantomData,
+        }
+    }
+
+    /// Returns the allocator handle that the BTreeMap is using.
+    pub fn alloc(&self) -> &A {
+        &self.alloc
+    }
+
+    /// The number of items currently in the BTreeMap.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the BTreeMap holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drops all items, and frees all nodes.
+    pub fn clear(&mut self) {
+        let height = mem::replace(&mut self.height, 0);
+        self.len = 0;
+        if let Some(root) = self.root.take() {
+            unsafe {
+                free_tree(&self.alloc, root.as_ptr(), height, true);
+            }
+        }
+    }
+
+    /// Returns the first item, which has the smallest key.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// Returns the last item, which has the largest key.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().next_back()
+    }
+
+    /// Removes the first item, which has the smallest key, and returns it.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let root = self.root?;
+        unsafe {
+            let edge = first_leaf_edge(root.as_ptr(), self.height);
+            Some(self.remove_kv(edge.node as *mut Node<K, V>, 0, 0))
+        }
+    }
+
+    /// Removes the last item, which has the largest key, and returns it.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let root = self.root?;
+        unsafe {
+            let edge = last_leaf_edge(root.as_ptr(), self.height);
+            Some(self.remove_kv(edge.node as *mut Node<K, V>, edge.idx - 1, 0))
+        }
+    }
+
+    /// Returns an iterator over the keys and values, in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            range:  self.full_range(),
+            length: self.len,
+            _ref:   PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the keys, and mutable values, in key order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            range:  self.full_range(),
+            length: self.len,
+            _ref:   PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the keys, in order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            iter: self.iter(),
+        }
+    }
+
+    /// Returns an iterator over the values, in key order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            iter: self.iter(),
+        }
+    }
+
+    /// Returns an iterator over the mutable values, in key order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            iter: self.iter_mut(),
+        }
+    }
+
+    fn full_range(&self) -> LeafRange<K, V> {
+        let root = match self.root {
+            Some(root) => root.as_ptr(),
+            None       => return LeafRange::none(),
+        };
+        unsafe {
+            LeafRange {
+                front: first_leaf_edge(root, self.height),
+                back:  last_leaf_edge(root, self.height),
+            }
+        }
+    }
+
+    fn new_node(&self) -> VecResult<*mut Node<K, V>> {
+        let block = self.alloc.allocate(alloc_api::Layout::new::<Node<K, V>>())?;
+        let node = block.cast::<Node<K, V>>().as_ptr();
+        unsafe {
+            ptr::addr_of_mut!((*node).parent).write(ptr::null());
+            ptr::addr_of_mut!((*node).parent_idx).write(0);
+            ptr::addr_of_mut!((*node).len).write(0);
+            ptr::addr_of_mut!((*node).edges).write([ptr::null(); CAPACITY + 1]);
+        }
+        Ok(node)
+    }
+
+    unsafe fn free_node(&self, node: *mut Node<K, V>) {
+        self.alloc.deallocate(NonNull::new_unchecked(node).cast(),
+                              alloc_api::Layout::new::<Node<K, V>>());
+    }
+
+    // Splits the full child at edge `idx` of `parent` in two, moving its
+    // middle item up into `parent`, which must not be full.
+    unsafe fn split_child(&self, parent: *mut Node<K, V>, idx: usize, child_height: usize)
+        -> VecResult<()>
+    {
+        let right = self.new_node()?;
+        let left = edge(parent, idx);
+        debug_assert_eq!(len(left), CAPACITY);
+
+        let key = ptr::read(key_at(left, MIN_LEN));
+        let val = ptr::read(val_at(left, MIN_LEN));
+        ptr::copy_nonoverlapping(key_at(left, B), key_at(right, 0), MIN_LEN);
+        ptr::copy_nonoverlapping(val_at(left, B), val_at(right, 0), MIN_LEN);
+        if child_height > 0 {
+            ptr::copy_nonoverlapping(edge_at(left, B), edge_at(right, 0), B);
+            correct_parent_links(right, 0..B);
+        }
+        set_len(left, MIN_LEN);
+        set_len(right, MIN_LEN);
+
+        let parent_len = len(parent);
+        slice_insert(key_at(parent, 0), parent_len, idx, key);
+        slice_insert(val_at(parent, 0), parent_len, idx, val);
+        slice_insert(edge_at(parent, 0), parent_len + 1, idx + 1, right);
+        set_len(parent, parent_len + 1);
+        correct_parent_links(parent, idx + 1..parent_len + 2);
+        Ok(())
+    }
+
+    // Moves the last item of the child at edge `idx` of `parent` up into
+    // `parent`, and the item there down to the start of the next child.
+    unsafe fn rotate_right(&self, parent: *mut Node<K, V>, idx: usize, child_height: usize) {
+        let left = edge(parent, idx);
+        let right = edge(parent, idx + 1);
+        let (left_len, right_len) = (len(left), len(right));
+
+        let key = ptr::replace(key_at(parent, idx), ptr::read(key_at(left, left_len - 1)));
+        let val = ptr::replace(val_at(parent, idx), ptr::read(val_at(left, left_len - 1)));
+        slice_insert(key_at(right, 0), right_len, 0, key);
+        slice_insert(val_at(right, 0), right_len, 0, val);
+        if child_height > 0 {
+            slice_insert(edge_at(right, 0), right_len + 1, 0, edge(left, left_len));
+            correct_parent_links(right, 0..right_len + 2);
+        }
+        set_len(left, left_len - 1);
+        set_len(right, right_len + 1);
+    }
+
+    // Moves the first item of the child at edge `idx + 1` of `parent` up
+    // into `parent`, and the item there down to the end of the child before.
+    unsafe fn rotate_left(&self, parent: *mut Node<K, V>, idx: usize, child_height: usize) {
+        let left = edge(parent, idx);
+        let right = edge(parent, idx + 1);
+        let (left_len, right_len) = (len(left), len(right));
+
+        let key = ptr::replace(key_at(parent, idx), slice_remove(key_at(right, 0), right_len, 0));
+        let val = ptr::replace(val_at(parent, idx), slice_remove(val_at(right, 0), right_len, 0));
+        ptr::write(key_at(left, left_len), key);
+        ptr::write(val_at(left, left_len), val);
+        if child_height > 0 {
+            let child = slice_remove(edge_at(right, 0), right_len + 1, 0);
+            ptr::write(edge_at(left, left_len + 1), child);
+            correct_parent_links(left, left_len + 1..left_len + 2);
+            correct_parent_links(right, 0..right_len);
+        }
+        set_len(left, left_len + 1);
+        set_len(right, right_len - 1);
+    }
+
+    // Merges the children at edges `idx` and `idx + 1` of `parent`, along
+    // with the item between them, and frees the second child.
+    unsafe fn merge(&self, parent: *mut Node<K, V>, idx: usize, child_height: usize) {
+        let left = edge(parent, idx);
+        let right = edge(parent, idx + 1);
+        let (parent_len, left_len, right_len) = (len(parent), len(left), len(right));
+
+        let key = slice_remove(key_at(parent, 0), parent_len, idx);
+        let val = slice_remove(val_at(parent, 0), parent_len, idx);
+        slice_remove(edge_at(parent, 0), parent_len + 1, idx + 1);
+        correct_parent_links(parent, idx + 1..parent_len);
+        set_len(parent, parent_len - 1);
+
+        ptr::write(key_at(left, left_len), key);
+        ptr::write(val_at(left, left_len), val);
+        ptr::copy_nonoverlapping(key_at(right, 0), key_at(left, left_len + 1), right_len);
+        ptr::copy_nonoverlapping(val_at(right, 0), val_at(left, left_len + 1), right_len);
+        if child_height > 0 {
+            ptr::copy_nonoverlapping(edge_at(right, 0), edge_at(left, left_len + 1), right_len + 1);
+            correct_parent_links(left, left_len + 1..left_len + right_len + 2);
+        }
+        set_len(left, left_len + 1 + right_len);
+        self.free_node(right);
+    }
+
+    // Removes item `idx` of `node`, which is `height` levels above the
+    // leaves, and rebalances the tree.
+    unsafe fn remove_kv(&mut self, node: *mut Node<K, V>, idx: usize, height: usize) -> (K, V) {
+        // Items are only ever taken out of leaves. An internal item swaps
+        // with the one just before it, which is the last of a leaf.
+        let (leaf, leaf_idx) = if height == 0 {
+            (node, idx)
+        } else {
+            let edge = last_leaf_edge(edge(node, idx), height - 1);
+            (edge.node as *mut Node<K, V>, edge.idx - 1)
+        };
+        let leaf_len = len(leaf);
+        let key = slice_remove(key_at(leaf, 0), leaf_len, leaf_idx);
+        let val = slice_remove(val_at(leaf, 0), leaf_len, leaf_idx);
+        set_len(leaf, leaf_len - 1);
+
+        let item = if height == 0 {
+            (key, val)
+        } else {
+            (ptr::replace(key_at(node, idx), key), ptr::replace(val_at(node, idx), val))
+        };
+        self.len -= 1;
+        self.fix_underflow(leaf);
+        item
+    }
+
+    // Refills `node`, a leaf, and then its ancestors in turn, from their
+    // siblings, if they have fewer than `MIN_LEN` items.
+    unsafe fn fix_underflow(&mut self, mut node: *mut Node<K, V>) {
+        let mut height = 0;
+        loop {
+            let parent = (*node).parent as *mut Node<K, V>;
+            if parent.is_null() {
+                // The root may hold any number of items, until it is empty.
+                if len(node) == 0 {
+                    if height > 0 {
+                        let child = edge(node, 0);
+                        (*child).parent = ptr::null();
+                        self.root = NonNull::new(child);
+                        self.height -= 1;
+                    } else {
+                        self.root = None;
+                    }
+                    self.free_node(node);
+                }
+                return;
+            }
+            if len(node) >= MIN_LEN {
+                return;
+            }
+
+            let idx = (*node).parent_idx as usize;
+            if idx > 0 && len(edge(parent, idx - 1)) > MIN_LEN {
+                self.rotate_right(parent, idx - 1, height);
+                return;
+            }
+            if idx < len(parent) && len(edge(parent, idx + 1)) > MIN_LEN {
+                self.rotate_left(parent, idx, height);
+                return;
+            }
+            self.merge(parent, idx.saturating_sub(1), height);
+            node = parent;
+            height += 1;
+        }
+    }
+}
+
+impl <K, V, A> BTreeMap<K, V, A>
+    where K: Ord,
+          A: alloc_api::Allocator
+{
+    /// Insert `value` under `key`, returning any allocation errors.
+    ///
+    /// If the key was present, its value is replaced and the old value is
+    /// returned. The key itself is not replaced.
+    pub fn insert(&mut self, key: K, value: V) -> VecResult<Option<V>> {
+        match self.search(&key) {
+            Some((node, idx, _)) => unsafe {
+                Ok(Some(ptr::replace(val_at(node, idx), value)))
+            },
+            None => {
+                self.insert_new(key, value)?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Extend the BTreeMap with the items of `iter`, returning any allocation
+    /// errors. The items before the failing one are kept.
+    pub fn try_extend<I>(&mut self, iter: I) -> VecResult<()>
+        where I: IntoIterator<Item = (K, V)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the value stored under `key`, if there is one.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the stored key and value for `key`, if there are any.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        let (node, idx, _) = self.search(key)?;
+        unsafe {
+            Some((&*key_at(node, idx), &*val_at(node, idx)))
+        }
+    }
+
+    /// Returns the value stored under `key` mutably, if there is one.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        let (node, idx, _) = self.search(key)?;
+        unsafe {
+            Some(&mut *val_at(node, idx))
+        }
+    }
+
+    /// Returns `true` if the BTreeMap holds a value under `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.search(key).is_some()
+    }
+
+    /// Removes the value stored under `key`, and returns it.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes the item stored under `key`, and returns its key and value.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        let (node, idx, height) = self.search(key)?;
+        unsafe {
+            Some(self.remove_kv(node, idx, height))
+        }
+    }
+
+    /// Returns an iterator over the items with keys in `range`, in key order.
+    ///
+    /// # Panics
+    /// Panics if the range starts after it ends, or if it starts and ends at
+    /// the same excluded key.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized,
+              R: RangeBounds<Q>
+    {
+        Range {
+            range: self.leaf_range(&range),
+            _ref:  PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the keys, and mutable values, of the items
+    /// with keys in `range`, in key order.
+    ///
+    /// # Panics
+    /// Panics if the range starts after it ends, or if it starts and ends at
+    /// the same excluded key.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized,
+              R: RangeBounds<Q>
+    {
+        RangeMut {
+            range: self.leaf_range(&range),
+            _ref:  PhantomData,
+        }
+    }
+
+    /// Returns the entry for `key`, to read, insert or remove its value in
+    /// place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, A> {
+        match self.search(&key) {
+            Some((node, idx, height)) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                node,
+                idx,
+                height,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                map: self,
+                key,
+            }),
+        }
+    }
+
+    // Returns the node holding `key`, the key's index in it, and the node's
+    // height.
+    fn search<Q>(&self, key: &Q) -> Option<(*mut Node<K, V>, usize, usize)>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        let (mut node, mut height) = (self.root?.as_ptr(), self.height);
+        unsafe {
+            loop {
+                match search_node(node, key) {
+                    Ok(idx) => return Some((node, idx, height)),
+                    Err(_) if height == 0 => return None,
+                    Err(idx) => {
+                        node = edge(node, idx);
+                        height -= 1;
+                    },
+                }
+            }
+        }
+    }
+
+    // Inserts an item whose key isn't in the BTreeMap yet, and returns its
+    // node and index.
+    fn insert_new(&mut self, key: K, value: V) -> VecResult<(*mut Node<K, V>, usize)> {
+        unsafe {
+            let mut root = match self.root {
+                Some(root) => root.as_ptr(),
+                None       => {
+                    let root = self.new_node()?;
+                    self.root = NonNull::new(root);
+                    self.height = 0;
+                    root
+                },
+            };
+            if len(root) == CAPACITY {
+                let new_root = self.new_node()?;
+                *edge_at(new_root, 0) = root;
+                correct_parent_links(new_root, 0..1);
+                if let Err(err) = self.split_child(new_root, 0, self.height) {
+                    (*root).parent = ptr::null();
+                    self.free_node(new_root);
+                    return Err(err);
+                }
+                root = new_root;
+                self.root = NonNull::new(root);
+                self.height += 1;
+            }
+
+            // Split full nodes on the way down, so that there is always room
+            // for the item that a split moves up.
+            let (mut node, mut height) = (root, self.height);
+            loop {
+                let mut idx = match search_node(node, &key) {
+                    Ok(_)    => unreachable!("inserted a key that was present"),
+                    Err(idx) => idx,
+                };
+                if height == 0 {
+                    let node_len = len(node);
+                    slice_insert(key_at(node, 0), node_len, idx, key);
+                    slice_insert(val_at(node, 0), node_len, idx, value);
+                    set_len(node, node_len + 1);
+                    self.len += 1;
+                    return Ok((node, idx));
+                }
+                if len(edge(node, idx)) == CAPACITY {
+                    self.split_child(node, idx, height - 1)?;
+                    if key > *key_at(node, idx) {
+                        idx += 1;
+                    }
+                }
+                node = edge(node, idx);
+                height -= 1;
+            }
+        }
+    }
+
+    fn leaf_range<Q, R>(&self, range: &R) -> LeafRange<K, V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized,
+              R: RangeBounds<Q>
+    {
+        match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) if start == end => {
+                panic!("range start and end are equal and excluded")
+            },
+            (Bound::Included(start), Bound::Included(end))
+            | (Bound::Included(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end))
+            | (Bound::Excluded(start), Bound::Excluded(end)) if start > end => {
+                panic!("range start is greater than range end")
+            },
+            _ => {},
+        }
+        let root = match self.root {
+            Some(root) => root.as_ptr(),
+            None       => return LeafRange::none(),
+        };
+        unsafe {
+            LeafRange {
+                front: self.bound_edge(root, range.start_bound(), false),
+                back:  self.bound_edge(root, range.end_bound(), true),
+            }
+        }
+    }
+
+    // Finds the leaf edge at `bound`. For the start of a range, that is just
+    // before the first key in the range. For the end, it is just after the
+    // last key in the range.
+    unsafe fn bound_edge<Q>(&self, root: *mut Node<K, V>, bound: Bound<&Q>, is_end: bool)
+        -> LeafEdge<K, V>
+        where K: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        let (mut node, mut height) = (root, self.height);
+        loop {
+            let mut idx = 0;
+            while idx < len(node) {
+                let key = (*key_at(node, idx)).borrow();
+                let before = match (bound, is_end) {
+                    (Bound::Included(b), false) => key < b,
+                    (Bound::Excluded(b), false) => key <= b,
+                    (Bound::Included(b), true)  => key <= b,
+                    (Bound::Excluded(b), true)  => key < b,
+                    (Bound::Unbounded, end)     => end,
+                };
+                if !before {
+                    break;
+                }
+                idx += 1;
+            }
+            if height == 0 {
+                return LeafEdge { node, idx };
+            }
+            node = edge(node, idx);
+            height -= 1;
+        }
+    }
+}
+
+impl <K, A> BTreeMap<K, (), A>
+    where K: Ord,
+          A: alloc_api::Allocator
+{
+    // Swaps `key` in for an equal key in place, returning the old one, or
+    // inserts it if there is none. This backs `BTreeSet::replace`, which
+    // can't lose the old key when an insert fails.
+    pub(crate) fn replace(&mut self, key: K) -> VecResult<Option<K>> {
+        match self.search(&key) {
+            Some((node, idx, _)) => unsafe {
+                Ok(Some(ptr::replace(key_at(node, idx), key)))
+            },
+            None => self.insert(key, ()).map(|_| None),
+        }
+    }
+}
+
+// ----- BTreeMap Traits --------------------------------------------------------
+
+impl <K, V, A> Drop for BTreeMap<K, V, A>
+    where A: alloc_api::Allocator
+{
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl <K, V, A, Q> ops::Index<&Q> for BTreeMap<K, V, A>
+    where K: Ord + Borrow<Q>,
+          A: alloc_api::Allocator,
+          Q: Ord + ?Sized
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no value for the key")
+    }
+}
+
+impl <K, V, A> iter::IntoIterator for BTreeMap<K, V, A>
+    where A: alloc_api::Allocator
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let map = mem::ManuallyDrop::new(self);
+        IntoIter {
+            // The BTreeMap won't be dropped, so its alloc is moved out.
+            alloc:  unsafe { ptr::read(&map.alloc) },
+            root:   map.root,
+            height: map.height,
+            range:  map.full_range(),
+            length: map.len,
+        }
+    }
+}
+
+impl <'a, K, V, A> iter::IntoIterator for &'a BTreeMap<K, V, A>
+    where A: alloc_api::Allocator
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl <'a, K, V, A> iter::IntoIterator for &'a mut BTreeMap<K, V, A>
+    where A: alloc_api::Allocator
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl <K, V, A> fmt::Debug for BTreeMap<K, V, A>
+    where K: fmt::Debug,
+          V: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+impl <K, V, A, B> cmp::PartialEq<BTreeMap<K, V, B>> for BTreeMap<K, V, A>
+    where K: PartialEq,
+          V: PartialEq,
+          A: alloc_api::Allocator,
+          B: alloc_api::Allocator
+{
+    fn eq(&self, other: &BTreeMap<K, V, B>) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl <K, V, A> cmp::Eq for BTreeMap<K, V, A>
+    where K: Eq,
+          V: Eq,
+          A: alloc_api::Allocator
+{}
+
+// ----- Entry & Traits ---------------------------------------------------------
+
+/// An item's place in a BTreeMap, which may or may not hold a value.
+///
+/// See `BTreeMap::entry()`.
+pub enum Entry<'a, K: 'a, V: 'a, A: 'a>
+    where A: alloc_api::Allocator
+{
+    Occupied(OccupiedEntry<'a, K, V, A>),
+    Vacant(VacantEntry<'a, K, V, A>),
+}
+
+impl <'a, K, V, A> Entry<'a, K, V, A>
+    where K: Ord,
+          A: alloc_api::Allocator
+{
+    /// Returns the entry's key.
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry)   => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, then returns the value,
+    /// returning any allocation errors.
+    pub fn or_insert(self, default: V) -> VecResult<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, then returns
+    /// the value, returning any allocation errors.
+    pub fn or_insert_with<F>(self, default: F) -> VecResult<&'a mut V>
+        where F: FnOnce() -> V
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry)   => entry.insert(default()),
+        }
+    }
+
+    /// Inserts the result of `default`, which is given the key, if the entry
+    /// is vacant. Then returns the value, returning any allocation errors.
+    pub fn or_insert_with_key<F>(self, default: F) -> VecResult<&'a mut V>
+        where F: FnOnce(&K) -> V
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry)   => {
+                let value = default(entry.key());
+                entry.insert(value)
+            },
+        }
+    }
+
+    /// Inserts `V::default()` if the entry is vacant, then returns the value,
+    /// returning any allocation errors.
+    pub fn or_default(self) -> VecResult<&'a mut V>
+        where V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F>(self, f: F) -> Self
+        where F: FnOnce(&mut V)
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            entry => entry,
+        }
+    }
+}
+
+/// An entry that holds a value. See `Entry`.
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, A: 'a>
+    where A: alloc_api::Allocator
+{
+    map:    &'a mut BTreeMap<K, V, A>,
+    node:   *mut Node<K, V>, // The node holding the item.
+    idx:    usize,           // The item's index in `node`.
+    height: usize,           // The height of `node`.
+}
+
+impl <'a, K, V, A> OccupiedEntry<'a, K, V, A>
+    where K: Ord,
+          A: alloc_api::Allocator
+{
+    /// Returns the entry's key.
+    pub fn key(&self) -> &K {
+        unsafe {
+            &*key_at(self.node, self.idx)
+        }
+    }
+
+    /// Returns the entry's value.
+    pub fn get(&self) -> &V {
+        unsafe {
+            &*val_at(self.node, self.idx)
+        }
+    }
+
+    /// Returns the entry's value mutably.
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe {
+            &mut *val_at(self.node, self.idx)
+        }
+    }
+
+    /// Returns the entry's value mutably, for as long as the BTreeMap was
+    /// borrowed.
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe {
+            &mut *val_at(self.node, self.idx)
+        }
+    }
+
+    /// Replaces the entry's value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry's item, and returns its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry's item, and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        unsafe {
+            self.map.remove_kv(self.node, self.idx, self.height)
+        }
+    }
+}
+
+/// An entry without a value. See `Entry`.
+pub struct VacantEntry<'a, K: 'a, V: 'a, A: 'a>
+    where A: alloc_api::Allocator
+{
+    map: &'a mut BTreeMap<K, V, A>,
+    key: K,
+}
+
+impl <'a, K, V, A> VacantEntry<'a, K, V, A>
+    where K: Ord,
+          A: alloc_api::Allocator
+{
+    /// Returns the key that would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Gives back the key without inserting anything.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the entry's key and returns it, returning any
+    /// allocation errors.
+    pub fn insert(self, value: V) -> VecResult<&'a mut V> {
+        let (node, idx) = self.map.insert_new(self.key, value)?;
+        unsafe {
+            Ok(&mut *val_at(node, idx))
+        }
+    }
+}
+
+impl <'a, K, V, A> fmt::Debug for Entry<'a, K, V, A>
+    where K: fmt::Debug + Ord,
+          V: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Entry::Occupied(ref entry) => {
+                f.debug_tuple("Occupied").field(entry.key())
+                                         .field(entry.get())
+                                         .finish()
+            },
+            Entry::Vacant(ref entry) => {
+                f.debug_tuple("Vacant").field(entry.key()).finish()
+            },
+        }
+    }
+}
+
+// ----- Iterators & Traits -----------------------------------------------------
+
+// See `BTreeMap::iter()`
+pub struct Iter<'a, K: 'a, V: 'a> {
+    range:  LeafRange<K, V>,
+    length: usize, // Count of items left.
+    _ref:   PhantomData<&'a (K, V)>,
+}
+
+impl <'a, K, V> iter::Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        unsafe {
+            let (node, idx) = self.range.next_kv();
+            Some((&*key_at(node, idx), &*val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        unsafe {
+            let (node, idx) = self.range.next_back_kv();
+            Some((&*key_at(node, idx), &*val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for Iter<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for Iter<'a, K, V> {}
+
+impl <'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            range:  self.range,
+            length: self.length,
+            _ref:   PhantomData,
+        }
+    }
+}
+
+// See `BTreeMap::iter_mut()`
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    range:  LeafRange<K, V>,
+    length: usize, // Count of items left.
+    _ref:   PhantomData<(&'a K, &'a mut V)>,
+}
+
+impl <'a, K, V> iter::Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        unsafe {
+            let (node, idx) = self.range.next_kv();
+            Some((&*key_at(node, idx), &mut *val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        unsafe {
+            let (node, idx) = self.range.next_back_kv();
+            Some((&*key_at(node, idx), &mut *val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for IterMut<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for IterMut<'a, K, V> {}
+
+// See `BTreeMap::keys()`
+pub struct Keys<'a, K: 'a, V: 'a> {
+    iter: Iter<'a, K, V>,
+}
+
+impl <'a, K, V> iter::Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(key, _)| key)
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.iter.next_back().map(|(key, _)| key)
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for Keys<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for Keys<'a, K, V> {}
+
+impl <'a, K, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+// See `BTreeMap::values()`
+pub struct Values<'a, K: 'a, V: 'a> {
+    iter: Iter<'a, K, V>,
+}
+
+impl <'a, K, V> iter::Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.iter.next().map(|(_, value)| value)
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.iter.next_back().map(|(_, value)| value)
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for Values<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for Values<'a, K, V> {}
+
+// See `BTreeMap::values_mut()`
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    iter: IterMut<'a, K, V>,
+}
+
+impl <'a, K, V> iter::Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.iter.next().map(|(_, value)| value)
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for ValuesMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a mut V> {
+        self.iter.next_back().map(|(_, value)| value)
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for ValuesMut<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for ValuesMut<'a, K, V> {}
+
+// See `BTreeMap::range()`
+pub struct Range<'a, K: 'a, V: 'a> {
+    range: LeafRange<K, V>,
+    _ref:  PhantomData<&'a (K, V)>,
+}
+
+impl <'a, K, V> iter::Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.range.is_empty() {
+            return None;
+        }
+        unsafe {
+            let (node, idx) = self.range.next_kv();
+            Some((&*key_at(node, idx), &*val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.range.is_empty() {
+            return None;
+        }
+        unsafe {
+            let (node, idx) = self.range.next_back_kv();
+            Some((&*key_at(node, idx), &*val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::FusedIterator for Range<'a, K, V> {}
+
+impl <'a, K, V> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Self {
+        Range {
+            range: self.range,
+            _ref:  PhantomData,
+        }
+    }
+}
+
+// See `BTreeMap::range_mut()`
+pub struct RangeMut<'a, K: 'a, V: 'a> {
+    range: LeafRange<K, V>,
+    _ref:  PhantomData<(&'a K, &'a mut V)>,
+}
+
+impl <'a, K, V> iter::Iterator for RangeMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.range.is_empty() {
+            return None;
+        }
+        unsafe {
+            let (node, idx) = self.range.next_kv();
+            Some((&*key_at(node, idx), &mut *val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::DoubleEndedIterator for RangeMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        if self.range.is_empty() {
+            return None;
+        }
+        unsafe {
+            let (node, idx) = self.range.next_back_kv();
+            Some((&*key_at(node, idx), &mut *val_at(node, idx)))
+        }
+    }
+}
+
+impl <'a, K, V> iter::FusedIterator for RangeMut<'a, K, V> {}
+
+pub struct IntoIter<K, V, A>
+    where A: alloc_api::Allocator
+{
+    alloc:  A,
+    root:   Option<NonNull<Node<K, V>>>, // The tree, freed once dropped.
+    height: usize,
+    range:  LeafRange<K, V>,             // The items not yet moved out.
+    length: usize,
+}
+
+impl <K, V, A> Drop for IntoIter<K, V, A>
+    where A: alloc_api::Allocator
+{
+    fn drop(&mut self) {
+        // Drop all remaining items
+        for _ in &mut *self {}
+        if let Some(root) = self.root {
+            unsafe {
+                free_tree(&self.alloc, root.as_ptr(), self.height, false);
+            }
+        }
+    }
+}
+
+impl <K, V, A> iter::Iterator for IntoIter<K, V, A>
+    where A: alloc_api::Allocator
+{
+    type Item = (K, V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+
+    fn next(&mut self) -> Option<(K, V)> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        unsafe {
+            let (node, idx) = self.range.next_kv();
+            Some((ptr::read(key_at(node, idx)), ptr::read(val_at(node, idx))))
+        }
+    }
+}
+
+impl <K, V, A> iter::DoubleEndedIterator for IntoIter<K, V, A>
+    where A: alloc_api::Allocator
+{
+    fn next_back(&mut self) -> Option<(K, V)> {
+        if self.length == 0 {
+            return None;
+        }
+        self.length -= 1;
+        unsafe {
+            let (node, idx) = self.range.next_back_kv();
+            Some((ptr::read(key_at(node, idx)), ptr::read(val_at(node, idx))))
+        }
+    }
+}
+
+impl <K, V, A> iter::ExactSizeIterator for IntoIter<K, V, A>
+    where A: alloc_api::Allocator
+{}
+
+impl <K, V, A> iter::FusedIterator for IntoIter<K, V, A>
+    where A: alloc_api::Allocator
+{}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use string::String;
+    use test_support::{DropCounter, prop_config};
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        collections,
+        vec,
+    };
+
+    // Checks the shape of the tree: node sizes, parent links, key order, and
+    // that all leaves are at the same depth.
+    fn check_tree<K: Ord, V, A: alloc_api::Allocator>(map: &BTreeMap<K, V, A>) {
+        unsafe fn check_node<K: Ord, V>(node:   *mut Node<K, V>,
+                                        height: usize,
+                                        is_root: bool)
+            -> usize
+        {
+            let n = len(node);
+            assert!(n <= CAPACITY);
+            assert!(is_root || n >= MIN_LEN, "node has {} items", n);
+            for idx in 1..n {
+                assert!(*key_at(node, idx - 1) < *key_at(node, idx));
+            }
+            if height == 0 {
+                return n;
+            }
+            let mut count = n;
+            for idx in 0..=n {
+                let child = edge(node, idx);
+                assert_eq!((*child).parent, node as *const _);
+                assert_eq!((*child).parent_idx as usize, idx);
+                count += check_node(child, height - 1, false);
+            }
+            count
+        }
+
+        let root = match map.root {
+            Some(root) => root.as_ptr(),
+            None       => {
+                assert_eq!(map.len, 0);
+                return;
+            },
+        };
+        unsafe {
+            assert!((*root).parent.is_null());
+            assert!(map.len == 0 || len(root) > 0);
+            assert_eq!(check_node(root, map.height, true), map.len);
+        }
+        let keys: vec::Vec<_> = map.keys().collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    // Visits 0..n in a scrambled order.
+    fn scrambled(n: u32) -> impl Iterator<Item = u32> {
+        (0..n).map(move |i| (i * 7919) % n)
+    }
+
+    #[test]
+    fn check_insert_get_remove() {
+        let mut buf = vec![0u8; 1 << 18];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.first_key_value(), None);
+
+        for i in scrambled(1000) {
+            assert_eq!(m.insert(i, i * 2).expect("m.insert(..) failed."), None);
+        }
+        check_tree(&m);
+        assert!(m.height >= 2);
+        assert_eq!(m.len(), 1000);
+        assert_eq!(m.insert(5, 0).expect("m.insert(..) failed."), Some(10));
+        *m.get_mut(&6).expect("m.get_mut(..) failed.") += 1;
+        assert_eq!((m[&5], m[&6], m[&7]), (0, 13, 14));
+        assert_eq!(m.get_key_value(&999), Some((&999, &1998)));
+        assert!(!m.contains_key(&1000));
+
+        for i in scrambled(1000).filter(|i| i % 3 != 0) {
+            assert!(m.remove(&i).is_some());
+        }
+        check_tree(&m);
+        assert_eq!(m.remove(&1), None);
+        assert_eq!(m.len(), 334);
+        assert!(m.keys().cloned().eq((0..1000).step_by(3)));
+
+        for i in (0..1000).step_by(3) {
+            assert_eq!(m.remove_entry(&i).map(|(k, _)| k), Some(i));
+        }
+        check_tree(&m);
+        assert!(m.is_empty());
+        assert!(m.root.is_none());
+    }
+
+    #[test]
+    fn check_first_and_last() {
+        let mut buf = vec![0u8; 1 << 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        m.try_extend(scrambled(100).map(|i| (i, ()))).expect("m.try_extend(..) failed.");
+
+        assert_eq!(m.first_key_value(), Some((&0, &())));
+        assert_eq!(m.last_key_value(), Some((&99, &())));
+        for i in 0..50 {
+            assert_eq!(m.pop_first(), Some((i, ())));
+            assert_eq!(m.pop_last(), Some((99 - i, ())));
+            check_tree(&m);
+        }
+        assert_eq!(m.pop_first(), None);
+        assert_eq!(m.pop_last(), None);
+    }
+
+    #[test]
+    fn check_iters() {
+        let mut buf = vec![0u8; 1 << 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        m.try_extend(scrambled(200).map(|i| (i, i))).expect("m.try_extend(..) failed.");
+
+        let mut iter = m.iter();
+        assert_eq!(iter.len(), 200);
+        assert_eq!(iter.next(), Some((&0, &0)));
+        assert_eq!(iter.next_back(), Some((&199, &199)));
+        assert_eq!(iter.len(), 198);
+        assert!(iter.map(|(&k, _)| k).eq(1..199));
+        assert!(m.keys().rev().cloned().eq((0..200).rev()));
+
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        for v in m.values_mut().rev().take(2) {
+            *v = 0;
+        }
+        assert!(m.values().cloned().eq((0..198).map(|i| i * 2).chain(vec![0, 0])));
+
+        let mut into = m.into_iter();
+        assert_eq!(into.next_back(), Some((199, 0)));
+        assert_eq!(into.next(), Some((0, 0)));
+        assert_eq!(into.len(), 198);
+    }
+
+    fn keys<'a, I>(iter: I) -> vec::Vec<u32>
+        where I: Iterator<Item = (&'a u32, &'a u32)>
+    {
+        iter.map(|(&k, _)| k).collect()
+    }
+
+    #[test]
+    fn check_range() {
+        let mut buf = vec![0u8; 1 << 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        m.try_extend(scrambled(100).map(|i| (i * 2, i))).expect("m.try_extend(..) failed.");
+
+        assert_eq!(keys(m.range(10..16)), [10, 12, 14]);
+        assert_eq!(keys(m.range(9..=16)), [10, 12, 14, 16]);
+        assert_eq!(keys(m.range((Bound::Excluded(10), Bound::Included(14)))), [12, 14]);
+        assert_eq!(keys(m.range(195..)), [196, 198]);
+        assert_eq!(keys(m.range(..3)), [0, 2]);
+        assert_eq!(keys(m.range(11..12)), []);
+        assert_eq!(keys(m.range(500..)), []);
+        assert_eq!(m.range::<u32, _>(..).count(), 100);
+        assert_eq!(keys(m.range(40..50).rev()), [48, 46, 44, 42, 40]);
+
+        for (_, v) in m.range_mut(0..10) {
+            *v = 100;
+        }
+        assert!(m.values().take(6).eq(&[100, 100, 100, 100, 100, 5]));
+
+        let mut range = m.range(20..30);
+        assert_eq!(range.next().map(|(&k, _)| k), Some(20));
+        assert_eq!(range.next_back().map(|(&k, _)| k), Some(28));
+        assert_eq!(keys(range), [22, 24, 26]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_range_backwards() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+        let m = BTreeMap::<u32, u32, _>::new(&alloc);
+        m.range((Bound::Included(5), Bound::Excluded(3)));
+    }
+
+    #[test]
+    fn check_borrowed_keys() {
+        let mut buf = vec![0u8; 1 << 14];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        for word in &["pear", "apple", "fig", "kiwi"] {
+            let key = String::from_str_in(word, &alloc)
+                          .expect("String::from_str_in(..) failed.");
+            m.insert(key, word.len()).expect("m.insert(..) failed.");
+        }
+        assert_eq!(m["fig"], 3);
+        let bounds = (Bound::Included("b"), Bound::Excluded("k"));
+        assert_eq!(m.range::<str, _>(bounds).count(), 1);
+        assert_eq!(m.remove("apple"), Some(5));
+        assert_eq!(format!("{:?}", m), r#"{"fig": 3, "kiwi": 4, "pear": 4}"#);
+    }
+
+    #[test]
+    fn check_entry() {
+        let mut buf = vec![0u8; 1 << 14];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+
+        for word in "a b a c b a".split(' ') {
+            *m.entry(word).or_insert(0).expect("or_insert(..) failed.") += 1;
+        }
+        assert_eq!((m["a"], m["b"], m["c"]), (3, 2, 1));
+
+        m.entry("c").and_modify(|n| *n += 10).or_default().expect("or_default() failed.");
+        m.entry("d").and_modify(|n| *n += 10).or_default().expect("or_default() failed.");
+        assert_eq!((m["c"], m["d"]), (11, 0));
+
+        let len = *m.entry("eee").or_insert_with_key(|k| k.len())
+                                 .expect("or_insert_with_key(..) failed.");
+        assert_eq!(len, 3);
+
+        match m.entry("a") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &"a");
+                assert_eq!(e.insert(30), 3);
+                assert_eq!(e.remove_entry(), ("a", 30));
+            },
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        match m.entry("a") {
+            Entry::Vacant(e) => assert_eq!(e.into_key(), "a"),
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert_eq!(format!("{:?}", m.entry("b")), "Occupied(\"b\", 2)");
+        assert!(m.keys().cloned().eq(vec!["b", "c", "d", "eee"]));
+    }
+
+    #[test]
+    fn check_entry_remove_from_internal_node() {
+        let mut buf = vec![0u8; 1 << 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        m.try_extend((0..100u32).map(|i| (i, i))).expect("m.try_extend(..) failed.");
+
+        // The root's items are all in an internal node.
+        let root = m.root.expect("m.root was None.");
+        let key = unsafe { *key_at(root.as_ptr(), 0) };
+        match m.entry(key) {
+            Entry::Occupied(e) => assert_eq!(e.remove(), key),
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        check_tree(&m);
+        assert!(m.keys().cloned().eq((0..100).filter(|&i| i != key)));
+    }
+
+    #[test]
+    fn check_drops() {
+        let mut buf = vec![0u8; 1 << 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+
+        let mut m = BTreeMap::new(&alloc);
+        for i in scrambled(100) {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        m.insert(0, DropCounter(&count)).expect("m.insert(..) failed.");
+        assert_eq!(count.get(), 1);
+        mem::drop(m.remove(&1));
+        assert_eq!(count.get(), 2);
+        mem::drop(m);
+        assert_eq!(count.get(), 101);
+
+        let mut m = BTreeMap::new(&alloc);
+        for i in scrambled(100) {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        let mut iter = m.into_iter();
+        mem::drop(iter.next());
+        mem::drop(iter.next_back());
+        assert_eq!(count.get(), 103);
+        mem::drop(iter);
+        assert_eq!(count.get(), 201);
+    }
+
+    #[test]
+    fn check_frees_nodes() {
+        let mut buf = vec![0u8; 1 << 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        m.insert(1u32, 1u32).expect("m.insert(..) failed.");
+        let size = mem::size_of::<Node<u32, u32>>();
+        assert!(alloc.bytes_in_use() >= size);
+
+        // A single node on top of the stack goes back to the allocator. Only
+        // the padding to align it may stay in use.
+        m.remove(&1);
+        let in_use = alloc.bytes_in_use();
+        assert!(in_use < size);
+        m.insert(1, 1).expect("m.insert(..) failed.");
+        m.clear();
+        assert_eq!(alloc.bytes_in_use(), in_use);
+    }
+
+    #[test]
+    fn check_split_failure_keeps_tree() {
+        // Splitting a full root needs a new root and a sibling. Leave room for
+        // the new root, but not the sibling.
+        let size = mem::size_of::<Node<u32, DropCounter>>();
+        let mut buf = vec![0u8; 2 * size + size / 2];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+        let mut m = BTreeMap::new(&alloc);
+
+        for i in 0..CAPACITY as u32 {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        let in_use = alloc.bytes_in_use();
+        match m.insert(100, DropCounter(&count)) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res.map(|_| ())),
+        }
+        // The rejected value is dropped, the new root goes back to the
+        // allocator, and the old root is unchanged.
+        assert_eq!(count.get(), 1);
+        assert_eq!(alloc.bytes_in_use(), in_use);
+        check_tree(&m);
+        assert!(m.keys().cloned().eq(0..CAPACITY as u32));
+
+        // Replacing a value, or filling a removed item's place, needs no split.
+        mem::drop(m.insert(3, DropCounter(&count)).expect("m.insert(..) failed."));
+        assert_eq!(count.get(), 2);
+        mem::drop(m.remove(&0));
+        m.insert(100, DropCounter(&count)).expect("m.insert(..) failed.");
+        check_tree(&m);
+        assert!(m.keys().cloned().eq((1..CAPACITY as u32).chain([100])));
+    }
+
+    #[test]
+    fn check_covariant() {
+        // These only compile if the map and its iterators are covariant in
+        // `K` and `V`, like `std`'s.
+        fn shorten<'a, A>(m: BTreeMap<&'static str, &'static str, A>)
+            -> BTreeMap<&'a str, &'a str, A>
+            where A: alloc_api::Allocator
+        {
+            m
+        }
+        fn shorten_iter<'i, 'a>(iter: Iter<'i, &'static str, &'static str>)
+            -> Iter<'i, &'a str, &'a str>
+        {
+            iter
+        }
+        fn shorten_into_iter<'a, A>(iter: IntoIter<&'static str, &'static str, A>)
+            -> IntoIter<&'a str, &'a str, A>
+            where A: alloc_api::Allocator
+        {
+            iter
+        }
+
+        let a = std::string::String::from("a");
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+        m.insert("b", "x").expect("m.insert(..) failed.");
+        assert!(shorten_iter(m.iter()).map(|(&k, _)| k).eq(["b"]));
+
+        let mut m = shorten(m);
+        m.insert(&a, &a).expect("m.insert(..) failed.");
+        assert!(m.keys().cloned().eq(["a", "b"]));
+
+        let mut n = BTreeMap::new(&alloc);
+        n.insert("c", "y").expect("n.insert(..) failed.");
+        let mut iter = shorten_into_iter(n.into_iter());
+        assert_eq!(iter.next(), Some(("c", "y")));
+    }
+
+    #[test]
+    fn check_zero_sized() {
+        let mut buf = vec![0u8; 1 << 12];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = BTreeMap::new(&alloc);
+
+        assert_eq!(m.insert((), ()).expect("m.insert(..) failed."), None);
+        assert_eq!(m.insert((), ()).expect("m.insert(..) failed."), Some(()));
+        assert_eq!(m.len(), 1);
+        assert_eq!(m.iter().count(), 1);
+        assert_eq!(m.remove(&()), Some(()));
+        assert!(m.is_empty());
+    }
+
+    // A step of the model test below.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(u16, u32),
+        Remove(u16),
+        PopFirst,
+        PopLast,
+        Range(u16, u16),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => (0..512u16, any::<u32>()).prop_map(|(k, v)| Op::Insert(k, v)),
+            3 => (0..512u16).prop_map(Op::Remove),
+            1 => Just(Op::PopFirst),
+            1 => Just(Op::PopLast),
+            1 => (0..512u16, 0..512u16).prop_map(|(a, b)| Op::Range(a, b)),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(2))]
+
+        #[test]
+        fn prop_matches_std_btree_map(ops in proptest::collection::vec(arb_op(), 0..512)) {
+            let mut buf = vec![0u8; 1 << 20];
+            let alloc = LinearAlloc::new(&mut buf);
+            let mut m = BTreeMap::new(&alloc);
+            let mut model = collections::BTreeMap::new();
+
+            for op in ops {
+                match op {
+                    Op::Insert(k, v) => {
+                        let old = m.insert(k, v).expect("m.insert(..) failed.");
+                        prop_assert_eq!(old, model.insert(k, v));
+                    },
+                    Op::Remove(k) => prop_assert_eq!(m.remove(&k), model.remove(&k)),
+                    Op::PopFirst  => prop_assert_eq!(m.pop_first(), model.pop_first()),
+                    Op::PopLast   => prop_assert_eq!(m.pop_last(), model.pop_last()),
+                    Op::Range(a, b) => {
+                        let (a, b) = (a.min(b), a.max(b));
+                        prop_assert!(m.range(a..b).eq(model.range(a..b)));
+                        prop_assert!(m.range(a..=b).rev().eq(model.range(a..=b).rev()));
+                    },
+                }
+                prop_assert_eq!(m.len(), model.len());
+            }
+            check_tree(&m);
+            prop_assert!(m.iter().eq(model.iter()));
+        }
+    }
+}
@@ -0,0 +1,508 @@
+//! An ordered set whose nodes live in a customizable memory allocator. It is
+//! a `BTreeMap` with `()` values.
+
+use core::{
+    borrow::Borrow,
+    cmp,
+    fmt,
+    iter,
+    ops::RangeBounds,
+    result,
+};
+
+use alloc_api;
+use btree_map::{self, BTreeMap};
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- BTreeSet Impl ----------------------------------------------------------
+
+/// An ordered set with a customizable memory allocator.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{btree_set::BTreeSet, linear_alloc::LinearAlloc};
+/// #
+/// let mut buf = [0u8; 512];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut s = BTreeSet::new(&alloc);
+/// for &i in &[5u32, 1, 3] {
+///     assert!(s.insert(i).unwrap());
+/// }
+/// assert!(!s.insert(3).unwrap());
+/// assert!(s.iter().eq(&[1, 3, 5]));
+/// assert!(s.range(2..).eq(&[3, 5]));
+/// assert_eq!(s.pop_first(), Some(1));
+/// ```
+pub struct BTreeSet<T, A>
+    where A: alloc_api::Allocator
+{
+    map: BTreeMap<T, (), A>,
+}
+
+impl <T, A> BTreeSet<T, A>
+    where A: alloc_api::Allocator
+{
+    /// Construct a new BTreeSet using the allocator handle `alloc`. Nodes
+    /// are only allocated once items are inserted.
+    pub fn new(alloc: A) -> Self {
+        BTreeSet {
+            map: BTreeMap::new(alloc),
+        }
+    }
+
+    /// Returns the allocator handle that the BTreeSet is using.
+    pub fn alloc(&self) -> &A {
+        self.map.alloc()
+    }
+
+    /// The number of items currently in the BTreeSet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the BTreeSet holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Drops all items, and frees all nodes.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Returns the smallest item.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first_key_value().map(|(item, _)| item)
+    }
+
+    /// Returns the largest item.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last_key_value().map(|(item, _)| item)
+    }
+
+    /// Removes the smallest item, and returns it.
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(item, ())| item)
+    }
+
+    /// Removes the largest item, and returns it.
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(item, ())| item)
+    }
+
+    /// Returns an iterator over the items, in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            iter: self.map.keys(),
+        }
+    }
+}
+
+impl <T, A> BTreeSet<T, A>
+    where T: Ord,
+          A: alloc_api::Allocator
+{
+    /// Insert `value`, returning any allocation errors.
+    ///
+    /// Returns `false` if an equal item was already present. That item is
+    /// kept, and `value` is dropped.
+    pub fn insert(&mut self, value: T) -> VecResult<bool> {
+        match self.map.entry(value) {
+            btree_map::Entry::Occupied(_)   => Ok(false),
+            btree_map::Entry::Vacant(entry) => entry.insert(()).map(|_| true),
+        }
+    }
+
+    /// Insert `value`, replacing an equal item if there is one, and returning
+    /// any allocation errors.
+    ///
+    /// Returns the replaced item. Replacing an item swaps it in place, so only
+    /// inserting a new one can fail.
+    pub fn replace(&mut self, value: T) -> VecResult<Option<T>> {
+        self.map.replace(value)
+    }
+
+    /// Extend the BTreeSet with the items of `iter`, returning any allocation
+    /// errors. The items before the failing one are kept.
+    pub fn try_extend<I>(&mut self, iter: I) -> VecResult<()>
+        where I: IntoIterator<Item = T>
+    {
+        self.map.try_extend(iter.into_iter().map(|item| (item, ())))
+    }
+
+    /// Returns `true` if the BTreeSet holds an item equal to `value`.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+        where T: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Returns the stored item equal to `value`, if there is one.
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+        where T: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.map.get_key_value(value).map(|(item, _)| item)
+    }
+
+    /// Removes the item equal to `value`. Returns `true` if there was one.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+        where T: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes the item equal to `value`, and returns it.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+        where T: Borrow<Q>,
+              Q: Ord + ?Sized
+    {
+        self.map.remove_entry(value).map(|(item, ())| item)
+    }
+
+    /// Returns an iterator over the items in `range`, in order.
+    ///
+    /// # Panics
+    /// Panics if the range starts after it ends, or if it starts and ends at
+    /// the same excluded item.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, T>
+        where T: Borrow<Q>,
+              Q: Ord + ?Sized,
+              R: RangeBounds<Q>
+    {
+        Range {
+            iter: self.map.range(range),
+        }
+    }
+
+    /// Returns `true` if every item of this BTreeSet is in `other`.
+    pub fn is_subset<B>(&self, other: &BTreeSet<T, B>) -> bool
+        where B: alloc_api::Allocator
+    {
+        self.len() <= other.len() && self.iter().all(|item| other.contains(item))
+    }
+
+    /// Returns `true` if every item of `other` is in this BTreeSet.
+    pub fn is_superset<B>(&self, other: &BTreeSet<T, B>) -> bool
+        where B: alloc_api::Allocator
+    {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if no item is in both this BTreeSet and `other`.
+    pub fn is_disjoint<B>(&self, other: &BTreeSet<T, B>) -> bool
+        where B: alloc_api::Allocator
+    {
+        if self.len() <= other.len() {
+            self.iter().all(|item| !other.contains(item))
+        } else {
+            other.iter().all(|item| !self.contains(item))
+        }
+    }
+}
+
+// ----- BTreeSet Traits --------------------------------------------------------
+
+impl <T, A> iter::IntoIterator for BTreeSet<T, A>
+    where A: alloc_api::Allocator
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: self.map.into_iter(),
+        }
+    }
+}
+
+impl <'a, T, A> iter::IntoIterator for &'a BTreeSet<T, A>
+    where A: alloc_api::Allocator
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl <T, A> fmt::Debug for BTreeSet<T, A>
+    where T: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self).finish()
+    }
+}
+
+impl <T, A, B> cmp::PartialEq<BTreeSet<T, B>> for BTreeSet<T, A>
+    where T: PartialEq,
+          A: alloc_api::Allocator,
+          B: alloc_api::Allocator
+{
+    fn eq(&self, other: &BTreeSet<T, B>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl <T, A> cmp::Eq for BTreeSet<T, A>
+    where T: Eq,
+          A: alloc_api::Allocator
+{}
+
+// ----- Iterators & Traits -----------------------------------------------------
+
+// See `BTreeSet::iter()`
+pub struct Iter<'a, T: 'a> {
+    iter: btree_map::Keys<'a, T, ()>,
+}
+
+impl <'a, T> iter::Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next()
+    }
+}
+
+impl <'a, T> iter::DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.iter.next_back()
+    }
+}
+
+impl <'a, T> iter::ExactSizeIterator for Iter<'a, T> {}
+
+impl <'a, T> iter::FusedIterator for Iter<'a, T> {}
+
+impl <'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+// See `BTreeSet::range()`
+pub struct Range<'a, T: 'a> {
+    iter: btree_map::Range<'a, T, ()>,
+}
+
+impl <'a, T> iter::Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next().map(|(item, _)| item)
+    }
+}
+
+impl <'a, T> iter::DoubleEndedIterator for Range<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.iter.next_back().map(|(item, _)| item)
+    }
+}
+
+impl <'a, T> iter::FusedIterator for Range<'a, T> {}
+
+impl <'a, T> Clone for Range<'a, T> {
+    fn clone(&self) -> Self {
+        Range {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+pub struct IntoIter<T, A>
+    where A: alloc_api::Allocator
+{
+    iter: btree_map::IntoIter<T, (), A>,
+}
+
+impl <T, A> iter::Iterator for IntoIter<T, A>
+    where A: alloc_api::Allocator
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<T> {
+        self.iter.next().map(|(item, ())| item)
+    }
+}
+
+impl <T, A> iter::DoubleEndedIterator for IntoIter<T, A>
+    where A: alloc_api::Allocator
+{
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back().map(|(item, ())| item)
+    }
+}
+
+impl <T, A> iter::ExactSizeIterator for IntoIter<T, A>
+    where A: alloc_api::Allocator
+{}
+
+impl <T, A> iter::FusedIterator for IntoIter<T, A>
+    where A: alloc_api::Allocator
+{}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use alloc_api::{AllocError, Allocator, Layout};
+    use core::{cell, ptr::NonNull};
+    use linear_alloc::LinearAlloc;
+    use string::String;
+
+    use std::vec;
+
+    #[test]
+    fn check_insert_contains_remove() {
+        let mut buf = vec![0u8; 1 << 14];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = BTreeSet::new(&alloc);
+
+        for i in (0..100u32).rev() {
+            assert!(s.insert(i % 50).expect("s.insert(..) failed.") == (i >= 50));
+        }
+        assert_eq!(s.len(), 50);
+        assert!(s.contains(&49));
+        assert!(!s.contains(&50));
+        assert_eq!(s.get(&3), Some(&3));
+        assert_eq!((s.first(), s.last()), (Some(&0), Some(&49)));
+
+        assert!(s.remove(&3));
+        assert!(!s.remove(&3));
+        assert_eq!(s.take(&4), Some(4));
+        assert_eq!(s.pop_last(), Some(49));
+        assert_eq!(s.len(), 47);
+
+        assert!(s.range(..8).eq(&[0, 1, 2, 5, 6, 7]));
+        assert!(s.iter().rev().take(2).eq(&[48, 47]));
+        let items: vec::Vec<_> = s.into_iter().collect();
+        assert_eq!(items.len(), 47);
+        assert!(items.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn check_replace_and_borrowed_items() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = BTreeSet::new(&alloc);
+
+        let word = String::from_str_in("word", &alloc)
+                       .expect("String::from_str_in(..) failed.");
+        assert!(s.insert(word).expect("s.insert(..) failed."));
+        assert!(s.contains("word"));
+
+        let again = String::from_str_in("word", &alloc)
+                        .expect("String::from_str_in(..) failed.");
+        let old = s.replace(again).expect("s.replace(..) failed.");
+        assert_eq!(old.expect("expected a replaced item."), "word");
+        assert_eq!(s.len(), 1);
+        assert_eq!(format!("{:?}", s), r#"{"word"}"#);
+    }
+
+    // An allocator that refuses every block once `fail` is set.
+    struct Failing<'a> {
+        alloc: &'a LinearAlloc<'a>,
+        fail:  cell::Cell<bool>,
+    }
+
+    unsafe impl <'a> Allocator for Failing<'a> {
+        fn allocate(&self, layout: Layout)
+            -> result::Result<NonNull<[u8]>, AllocError>
+        {
+            if self.fail.get() {
+                return Err(AllocError);
+            }
+            self.alloc.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.alloc.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn check_replace_needs_no_room() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+        let failing = Failing { alloc: &alloc, fail: cell::Cell::new(false) };
+        let mut s = BTreeSet::new(&failing);
+        s.insert(0u32).expect("s.insert(..) failed.");
+
+        failing.fail.set(true);
+        let old = s.replace(0).expect("s.replace(0) failed.");
+        assert_eq!(old, Some(0));
+        assert!(s.contains(&0));
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn check_set_relations() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut a = BTreeSet::new(&alloc);
+        let mut b = BTreeSet::new(&alloc);
+        let mut c = BTreeSet::new(&alloc);
+        a.try_extend(0..4u32).expect("a.try_extend(..) failed.");
+        b.try_extend(0..8u32).expect("b.try_extend(..) failed.");
+        c.try_extend(10..12u32).expect("c.try_extend(..) failed.");
+
+        assert!(a.is_subset(&b));
+        assert!(b.is_superset(&a));
+        assert!(!b.is_subset(&a));
+        assert!(a.is_disjoint(&c));
+        assert!(!a.is_disjoint(&b));
+        assert!(a != b);
+
+        while b.len() > 4 {
+            b.pop_last();
+        }
+        assert!(a == b);
+    }
+
+    #[test]
+    fn check_full_node_reports_errors() {
+        let node = {
+            let mut buf = vec![0u8; 4096];
+            let alloc = LinearAlloc::new(&mut buf);
+            let mut s = BTreeSet::new(&alloc);
+            s.insert(0u32).expect("s.insert(..) failed.");
+            alloc.bytes_in_use()
+        };
+        // Room for one node, so the set fills it, but can't split it.
+        let mut buf = vec![0u8; node + node / 2];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = BTreeSet::new(&alloc);
+
+        let res = s.try_extend(0..100u32);
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        let full = s.len() as u32;
+        assert!(s.iter().cloned().eq(0..full));
+        // Items already in the set need no room.
+        assert!(!s.insert(0).expect("s.insert(..) failed."));
+
+        s.pop_first();
+        s.insert(full).expect("s.insert(..) failed.");
+        assert!(s.iter().cloned().eq(1..full + 1));
+    }
+}
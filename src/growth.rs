@@ -0,0 +1,143 @@
+//! Policies that decide how far a `RawVec` grows when it runs out of room.
+//!
+//! Doubling gives amortized constant time pushes, but in a small `LinearAlloc`
+//! each doubling can strand a large dead block below the new one. These
+//! policies let a container trade reallocation count against wasted space.
+//!
+//! `RawVec` never asks a policy for less than it needs. If the allocator can't
+//! satisfy the policy's choice, `RawVec` retries with the minimum capacity.
+
+use alloc_api::AvailableBytes;
+
+/// Picks the capacity, in elements, that a buffer grows to.
+///
+/// `A` is the allocator handle type of the buffer, so that policies can ask
+/// their allocator for more information.
+pub trait GrowthPolicy<A: ?Sized> {
+    /// Returns the capacity for a buffer that currently has room for `cap`
+    /// elements of `elem_size` bytes each, and needs room for at least
+    /// `required`.
+    ///
+    /// The result should be at least `required`. Smaller values are ignored.
+    fn next_capacity(&self,
+                     cap:       usize,
+                     required:  usize,
+                     elem_size: usize,
+                     alloc:     &A)
+        -> usize;
+}
+
+/// Doubles the capacity each time the buffer grows, starting from one element.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Doubling;
+
+impl <A: ?Sized> GrowthPolicy<A> for Doubling {
+    fn next_capacity(&self, cap: usize, required: usize, _: usize, _: &A)
+        -> usize
+    {
+        required.max(cap.saturating_mul(2))
+    }
+}
+
+/// Grows the capacity by half each time the buffer grows.
+///
+/// This reallocates more often than `Doubling`, but strands less space when
+/// the old block cannot be reused.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OneAndAHalf;
+
+impl <A: ?Sized> GrowthPolicy<A> for OneAndAHalf {
+    fn next_capacity(&self, cap: usize, required: usize, _: usize, _: &A)
+        -> usize
+    {
+        required.max(cap.saturating_add(cap / 2))
+    }
+}
+
+/// Grows the capacity by a fixed number of elements each time the buffer
+/// grows.
+///
+/// Pushes are no longer amortized constant time, but the buffer never holds
+/// more than that many spare elements.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Increment(pub usize);
+
+impl <A: ?Sized> GrowthPolicy<A> for Increment {
+    fn next_capacity(&self, cap: usize, required: usize, _: usize, _: &A)
+        -> usize
+    {
+        required.max(cap.saturating_add(self.0))
+    }
+}
+
+/// Grows the buffer to fill all of the space that its allocator has left.
+///
+/// This suits a buffer that is the last thing allocated from a `LinearAlloc`:
+/// it can keep growing in place until the allocator is full. If the buffer
+/// has to move instead, the request is too big and `RawVec` falls back to the
+/// minimum capacity.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FillAvailable;
+
+impl <A: AvailableBytes + ?Sized> GrowthPolicy<A> for FillAvailable {
+    fn next_capacity(&self,
+                     cap:       usize,
+                     required:  usize,
+                     elem_size: usize,
+                     alloc:     &A)
+        -> usize
+    {
+        let available = alloc.available_bytes() / elem_size.max(1);
+        required.max(cap.saturating_add(available))
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+
+    // Reports a fixed number of available bytes.
+    struct Avail(usize);
+
+    impl AvailableBytes for Avail {
+        fn available_bytes(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn check_policies_meet_required() {
+        let alloc = Avail(0);
+        assert_eq!(Doubling.next_capacity(0, 1, 4, &alloc), 1);
+        assert_eq!(Doubling.next_capacity(4, 5, 4, &alloc), 8);
+        assert_eq!(Doubling.next_capacity(4, 20, 4, &alloc), 20);
+
+        assert_eq!(OneAndAHalf.next_capacity(1, 2, 4, &alloc), 2);
+        assert_eq!(OneAndAHalf.next_capacity(4, 5, 4, &alloc), 6);
+        assert_eq!(OneAndAHalf.next_capacity(4, 20, 4, &alloc), 20);
+
+        assert_eq!(Increment(3).next_capacity(0, 1, 4, &alloc), 3);
+        assert_eq!(Increment(3).next_capacity(3, 4, 4, &alloc), 6);
+        assert_eq!(Increment(3).next_capacity(3, 20, 4, &alloc), 20);
+    }
+
+    #[test]
+    fn check_policies_saturate() {
+        let alloc = Avail(usize::MAX);
+        let cap = usize::MAX - 1;
+        assert_eq!(Doubling.next_capacity(cap, cap + 1, 1, &alloc), usize::MAX);
+        assert_eq!(OneAndAHalf.next_capacity(cap, cap + 1, 1, &alloc), usize::MAX);
+        assert_eq!(Increment(3).next_capacity(cap, cap + 1, 1, &alloc), usize::MAX);
+        assert_eq!(FillAvailable.next_capacity(cap, cap + 1, 1, &alloc), usize::MAX);
+    }
+
+    #[test]
+    fn check_fill_available() {
+        assert_eq!(FillAvailable.next_capacity(2, 3, 4, &Avail(17)), 6);
+        assert_eq!(FillAvailable.next_capacity(2, 3, 4, &Avail(0)), 3);
+        assert_eq!(FillAvailable.next_capacity(0, 1, 0, &Avail(17)), 17);
+        assert_eq!(FillAvailable.next_capacity(2, 3, 4, &&Avail(8)), 4);
+    }
+}
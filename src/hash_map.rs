@@ -0,0 +1,1515 @@
+//! A hash map whose table lives in a customizable memory allocator.
+//!
+//! The table uses open addressing with linear probing. Removing an item
+//! shifts the rest of its probe run back, so there are no tombstones, and a
+//! table that sees a lot of churn never needs rebuilding. Each slot stores
+//! its key's hash, so growing the table never calls back into `Hash` or `Eq`.
+
+use core::{
+    borrow::Borrow,
+    cmp,
+    fmt,
+    hash::{BuildHasher, Hash},
+    iter,
+    marker::PhantomData,
+    mem,
+    ops,
+    ptr::{self, NonNull},
+    result,
+    slice,
+};
+
+use alloc_api;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+/// The hasher builder that `HashMap::new` and `HashSet::new` use, which seeds
+/// SipHash randomly, like `std`'s `RandomState`.
+///
+/// Without `std`, there is no source of randomness, so it can't be built, and
+/// maps and sets need a hasher from `with_hasher`. It is still their default
+/// `S` then, so turning `std` on changes no types.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "std", derive(Default))]
+pub struct DefaultHashBuilder {
+    #[cfg(feature = "std")]
+    state: ::std::collections::hash_map::RandomState,
+    #[cfg(not(feature = "std"))]
+    _none: ::core::convert::Infallible,
+}
+
+#[cfg(feature = "std")]
+impl BuildHasher for DefaultHashBuilder {
+    type Hasher = ::std::collections::hash_map::DefaultHasher;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        self.state.build_hasher()
+    }
+}
+
+// A bucket of the table.
+enum Slot<K, V> {
+    Empty,
+    Full(u64, K, V), // The key's hash, the key and the value.
+}
+
+// The number of items that a table of `buckets` slots may hold, keeping at
+// least one slot empty so that probing always stops.
+fn bucket_capacity(buckets: usize) -> usize {
+    if buckets < 8 {
+        buckets.saturating_sub(1)
+    } else {
+        buckets / 8 * 7
+    }
+}
+
+// The number of slots needed to hold `capacity` items.
+fn capacity_to_buckets(capacity: usize) -> VecResult<usize> {
+    if capacity == 0 {
+        Ok(0)
+    } else if capacity < 4 {
+        Ok(4)
+    } else if capacity < 8 {
+        Ok(8)
+    } else {
+        capacity.checked_mul(8)
+                .map(|n| n / 7)
+                .and_then(usize::checked_next_power_of_two)
+                .ok_or(Error::SizeOverflowErr)
+    }
+}
+
+// ----- HashMap Impl -----------------------------------------------------------
+
+/// A hash map with a customizable memory allocator.
+///
+/// `S` builds the hashers for the keys, as in `std`.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{hash_map::HashMap, linear_alloc::LinearAlloc};
+/// #
+/// # #[cfg(feature = "std")] {
+/// let mut buf = [0u8; 512];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut m = HashMap::new(&alloc);
+/// m.insert("a", 1u32).unwrap();
+/// *m.entry("b").or_insert(0).unwrap() += 2;
+/// *m.entry("a").or_insert(0).unwrap() += 2;
+///
+/// assert_eq!(m.get("a"), Some(&3));
+/// assert_eq!(m["b"], 2);
+/// assert_eq!(m.remove("a"), Some(3));
+/// assert_eq!(m.len(), 1);
+/// # }
+/// ```
+pub struct HashMap<K, V, A, S = DefaultHashBuilder>
+    where A: alloc_api::Allocator
+{
+    alloc:        A,
+    slots:        NonNull<Slot<K, V>>, // The table, from `alloc`.
+    buckets:      usize,               // Count of slots. Zero or a power of 2.
+    len:          usize,               // Count of full slots.
+    hash_builder: S,
+    _own:         PhantomData<Slot<K, V>>,
+}
+
+#[cfg(feature = "std")]
+impl <K, V, A: alloc_api::Allocator> HashMap<K, V, A> {
+    /// Construct a new HashMap using the allocator handle `alloc`.
+    pub fn new(alloc: A) -> Self {
+        HashMap::with_hasher(alloc, DefaultHashBuilder::default())
+    }
+
+    /// Construct a new HashMap with room for at least `capacity` items,
+    /// using the allocator handle `alloc`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        HashMap::with_capacity_and_hasher(alloc, capacity, DefaultHashBuilder::default())
+    }
+}
+
+impl <K, V, A, S> HashMap<K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    /// Construct a new HashMap using the allocator handle `alloc`, which
+    /// hashes keys with `hash_builder`.
+    pub fn with_hasher(alloc: A, hash_builder: S) -> Self {
+        HashMap {
+            alloc,
+            slots:   NonNull::dangling(),
+            buckets: 0,
+            len:     0,
+            hash_builder,
+            _own:    PhantomData,
+        }
+    }
+
+    /// Construct a new HashMap with room for at least `capacity` items,
+    /// using the allocator handle `alloc`, which hashes keys with
+    /// `hash_builder`.
+    pub fn with_capacity_and_hasher(alloc: A, capacity: usize, hash_builder: S)
+        -> VecResult<Self>
+    {
+        let mut map = HashMap::with_hasher(alloc, hash_builder);
+        map.resize(capacity_to_buckets(capacity)?)?;
+        Ok(map)
+    }
+
+    /// Returns the allocator handle that the HashMap is using.
+    pub fn alloc(&self) -> &A {
+        &self.alloc
+    }
+
+    /// Returns the hasher builder that the HashMap is using.
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// The number of items that the HashMap can hold before resizing.
+    pub fn capacity(&self) -> usize {
+        bucket_capacity(self.buckets)
+    }
+
+    /// The number of items currently in the HashMap.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the HashMap holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drops all items, keeping the allocation.
+    pub fn clear(&mut self) {
+        for slot in self.slots_mut() {
+            *slot = Slot::Empty;
+        }
+        self.len = 0;
+    }
+
+    /// Keeps only the items for which `f` returns `true`.
+    ///
+    /// `f` may change the values of the items that it is given.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&K, &mut V) -> bool
+    {
+        if self.buckets == 0 {
+            return;
+        }
+        // Removing shifts items back, but never across an empty slot. So
+        // starting just after one, every item is visited exactly once.
+        let mask = self.buckets - 1;
+        let start = Self::find_insert_slot(self.slots(), 0);
+        let mut idx = (start + 1) & mask;
+        let mut visited = 0;
+        while visited < self.buckets {
+            let keep = match self.slots_mut()[idx] {
+                Slot::Full(_, ref key, ref mut value) => f(key, value),
+                Slot::Empty                           => true,
+            };
+            if keep {
+                idx = (idx + 1) & mask;
+                visited += 1;
+            } else {
+                // Look at whichever item was shifted into this slot.
+                self.remove_at(idx);
+            }
+        }
+    }
+
+    /// Returns an iterator over the keys and values, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            slots: self.slots().iter(),
+            left:  self.len,
+        }
+    }
+
+    /// Returns an iterator over the keys, and mutable values, in arbitrary
+    /// order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        let left = self.len;
+        IterMut {
+            slots: self.slots_mut().iter_mut(),
+            left,
+        }
+    }
+
+    /// Returns an iterator over the keys, in arbitrary order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys {
+            iter: self.iter(),
+        }
+    }
+
+    /// Returns an iterator over the values, in arbitrary order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values {
+            iter: self.iter(),
+        }
+    }
+
+    /// Returns an iterator over the mutable values, in arbitrary order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            iter: self.iter_mut(),
+        }
+    }
+
+    /// Creates a draining iterator that removes the items from the HashMap,
+    /// and then yields them. The allocation is kept.
+    ///
+    /// Items that aren't yielded are dropped along with the `Drain`.
+    pub fn drain(&mut self) -> Drain<'_, K, V, A, S> {
+        // The Drain takes the table, so that the HashMap is left empty even
+        // if the Drain is leaked.
+        let slots = mem::replace(&mut self.slots, NonNull::dangling());
+        let buckets = mem::replace(&mut self.buckets, 0);
+        let left = mem::replace(&mut self.len, 0);
+        Drain {
+            map: self,
+            slots,
+            buckets,
+            idx: 0,
+            left,
+        }
+    }
+
+    // The table, as a slice.
+    fn slots(&self) -> &[Slot<K, V>] {
+        unsafe {
+            slice::from_raw_parts(self.slots.as_ptr(), self.buckets)
+        }
+    }
+
+    // The table, as a mutable slice.
+    fn slots_mut(&mut self) -> &mut [Slot<K, V>] {
+        unsafe {
+            slice::from_raw_parts_mut(self.slots.as_ptr(), self.buckets)
+        }
+    }
+
+    // Takes the item out of the full slot `idx`, then shifts later items of
+    // the probe run back, so that lookups never stop early at the new hole.
+    fn remove_at(&mut self, idx: usize) -> (K, V) {
+        let mask = self.buckets - 1;
+        let slots = self.slots_mut();
+        let removed = mem::replace(&mut slots[idx], Slot::Empty);
+
+        let mut hole = idx;
+        let mut next = idx;
+        loop {
+            next = (next + 1) & mask;
+            let home = match slots[next] {
+                Slot::Empty            => break,
+                Slot::Full(hash, _, _) => hash as usize & mask,
+            };
+            // The item can only move back if the hole is between its home
+            // slot and where it is, going round the table.
+            let stays = if hole <= next {
+                hole < home && home <= next
+            } else {
+                hole < home || home <= next
+            };
+            if !stays {
+                slots.swap(hole, next);
+                hole = next;
+            }
+        }
+
+        self.len -= 1;
+        match removed {
+            Slot::Full(_, key, value) => (key, value),
+            Slot::Empty => unreachable!("removed a slot that wasn't full"),
+        }
+    }
+
+    // Finds the first empty slot that an item with `hash` may be inserted
+    // into. There must be at least one.
+    fn find_insert_slot(slots: &[Slot<K, V>], hash: u64) -> usize {
+        let mask = slots.len() - 1;
+        let mut idx = hash as usize & mask;
+        loop {
+            match slots[idx] {
+                Slot::Full(..) => idx = (idx + 1) & mask,
+                Slot::Empty    => return idx,
+            }
+        }
+    }
+
+    // Moves the items into a new table of `new_buckets` slots.
+    fn resize(&mut self, new_buckets: usize) -> VecResult<()> {
+        debug_assert!(bucket_capacity(new_buckets) >= self.len);
+        let new_slots = if new_buckets == 0 {
+            NonNull::dangling()
+        } else {
+            let layout = alloc_api::Layout::array::<Slot<K, V>>(new_buckets)?;
+            let slots = self.alloc.allocate(layout)?.cast::<Slot<K, V>>();
+            for idx in 0..new_buckets {
+                unsafe {
+                    ptr::write(slots.as_ptr().add(idx), Slot::Empty);
+                }
+            }
+            slots
+        };
+
+        // This can't panic, since the hashes are stored.
+        let new_table = unsafe {
+            slice::from_raw_parts_mut(new_slots.as_ptr(), new_buckets)
+        };
+        for slot in self.slots_mut() {
+            if let Slot::Full(hash, key, value) = mem::replace(slot, Slot::Empty) {
+                let idx = Self::find_insert_slot(new_table, hash);
+                new_table[idx] = Slot::Full(hash, key, value);
+            }
+        }
+
+        self.free_table();
+        self.slots = new_slots;
+        self.buckets = new_buckets;
+        Ok(())
+    }
+
+    // Frees the table. The items must have been dropped or moved out.
+    fn free_table(&mut self) {
+        if self.buckets != 0 {
+            unsafe {
+                let layout = alloc_api::Layout::array::<Slot<K, V>>(self.buckets)
+                                 .expect("the table's layout was valid");
+                self.alloc.deallocate(self.slots.cast(), layout);
+            }
+        }
+    }
+}
+
+impl <K, V, A, S> HashMap<K, V, A, S>
+    where K: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{
+    /// Reserve room for at least `additional` more items.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails. See `try_reserve` to handle the error.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            panic!("HashMap::reserve({}) failed: {:?}", additional, err);
+        }
+    }
+
+    /// Reserve room for at least `additional` more items, returning any
+    /// allocation errors. May reserve more to avoid frequent reallocations.
+    pub fn try_reserve(&mut self, additional: usize) -> VecResult<()> {
+        let needed = self.len.checked_add(additional)
+                             .ok_or(Error::SizeOverflowErr)?;
+        let cap = self.capacity();
+        if needed <= cap {
+            return Ok(());
+        }
+        // At least double, as the table's size is a power of two anyway.
+        self.resize(capacity_to_buckets(needed.max(cap + 1))?)
+    }
+
+    /// Shrinks the table as far as it can while holding the current items.
+    pub fn shrink_to_fit(&mut self) -> VecResult<()> {
+        let buckets = capacity_to_buckets(self.len)?;
+        if buckets < self.buckets {
+            self.resize(buckets)?;
+        }
+        Ok(())
+    }
+
+    /// Insert `value` under `key`, returning any allocation errors.
+    ///
+    /// If the key was present, its value is replaced and the old value is
+    /// returned. The key itself is not replaced.
+    pub fn insert(&mut self, key: K, value: V) -> VecResult<Option<V>> {
+        let hash = self.make_hash(&key);
+        match self.find(hash, &key) {
+            Some(idx) => match self.slots_mut()[idx] {
+                Slot::Full(_, _, ref mut old) => Ok(Some(mem::replace(old, value))),
+                _ => unreachable!("found a slot that wasn't full"),
+            },
+            None => {
+                self.insert_new(hash, key, value)?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Extend the HashMap with the items of `iter`, returning any allocation
+    /// errors. The items before the failing one are kept.
+    pub fn try_extend<I>(&mut self, iter: I) -> VecResult<()>
+        where I: IntoIterator<Item = (K, V)>
+    {
+        let iter = iter.into_iter();
+        // Keys may repeat, so only reserve for the fewest new items.
+        let (lower, _) = iter.size_hint();
+        if self.is_empty() {
+            self.try_reserve(lower)?;
+        }
+        for (key, value) in iter {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the value stored under `key`, if there is one.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns the stored key and value for `key`, if there are any.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+        where K: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        let idx = self.find(self.make_hash(key), key)?;
+        match self.slots()[idx] {
+            Slot::Full(_, ref key, ref value) => Some((key, value)),
+            _ => unreachable!("found a slot that wasn't full"),
+        }
+    }
+
+    /// Returns the value stored under `key` mutably, if there is one.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        let idx = self.find(self.make_hash(key), key)?;
+        match self.slots_mut()[idx] {
+            Slot::Full(_, _, ref mut value) => Some(value),
+            _ => unreachable!("found a slot that wasn't full"),
+        }
+    }
+
+    /// Returns `true` if the HashMap holds a value under `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.find(self.make_hash(key), key).is_some()
+    }
+
+    /// Removes the value stored under `key`, and returns it.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    /// Removes the item stored under `key`, and returns its key and value.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        let idx = self.find(self.make_hash(key), key)?;
+        Some(self.remove_at(idx))
+    }
+
+    /// Returns the entry for `key`, to read, insert or remove its value in
+    /// place.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, A, S> {
+        let hash = self.make_hash(&key);
+        match self.find(hash, &key) {
+            Some(idx) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                idx,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                map: self,
+                hash,
+                key,
+            }),
+        }
+    }
+
+    fn make_hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hash_builder.hash_one(key)
+    }
+
+    // Returns the index of the slot holding `key`.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+        where K: Borrow<Q>,
+              Q: Eq + ?Sized
+    {
+        if self.buckets == 0 {
+            return None;
+        }
+        let slots = self.slots();
+        let mask = self.buckets - 1;
+        let mut idx = hash as usize & mask;
+        // There is always an empty slot, so this stops.
+        loop {
+            match slots[idx] {
+                Slot::Empty => return None,
+                Slot::Full(h, ref k, _) if h == hash && k.borrow() == key => {
+                    return Some(idx);
+                },
+                Slot::Full(..) => idx = (idx + 1) & mask,
+            }
+        }
+    }
+
+    // Inserts an item whose key isn't in the HashMap yet, and returns its
+    // slot's index.
+    fn insert_new(&mut self, hash: u64, key: K, value: V) -> VecResult<usize> {
+        self.try_reserve(1)?;
+        let idx = Self::find_insert_slot(self.slots(), hash);
+        self.slots_mut()[idx] = Slot::Full(hash, key, value);
+        self.len += 1;
+        Ok(idx)
+    }
+}
+
+// ----- HashMap Traits ---------------------------------------------------------
+
+impl <K, V, A, S> Drop for HashMap<K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.slots_mut());
+        }
+        self.free_table();
+    }
+}
+
+impl <K, V, A, S, Q> ops::Index<&Q> for HashMap<K, V, A, S>
+    where K: Eq + Hash + Borrow<Q>,
+          A: alloc_api::Allocator,
+          S: BuildHasher,
+          Q: Eq + Hash + ?Sized
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no value for the key")
+    }
+}
+
+impl <K, V, A, S> iter::IntoIterator for HashMap<K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, A, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            map: self,
+            idx: 0,
+        }
+    }
+}
+
+impl <'a, K, V, A, S> iter::IntoIterator for &'a HashMap<K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl <'a, K, V, A, S> iter::IntoIterator for &'a mut HashMap<K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl <K, V, A, S> fmt::Debug for HashMap<K, V, A, S>
+    where K: fmt::Debug,
+          V: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self).finish()
+    }
+}
+
+impl <K, V, A, S, B, T> cmp::PartialEq<HashMap<K, V, B, T>> for HashMap<K, V, A, S>
+    where K: Eq + Hash,
+          V: PartialEq,
+          A: alloc_api::Allocator,
+          S: BuildHasher,
+          B: alloc_api::Allocator,
+          T: BuildHasher
+{
+    fn eq(&self, other: &HashMap<K, V, B, T>) -> bool {
+        self.len == other.len
+            && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl <K, V, A, S> cmp::Eq for HashMap<K, V, A, S>
+    where K: Eq + Hash,
+          V: Eq,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{}
+
+// ----- Entry & Traits ---------------------------------------------------------
+
+/// An item's place in a HashMap, which may or may not hold a value.
+///
+/// See `HashMap::entry()`.
+pub enum Entry<'a, K: 'a, V: 'a, A: 'a, S: 'a>
+    where A: alloc_api::Allocator
+{
+    Occupied(OccupiedEntry<'a, K, V, A, S>),
+    Vacant(VacantEntry<'a, K, V, A, S>),
+}
+
+impl <'a, K, V, A, S> Entry<'a, K, V, A, S>
+    where K: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{
+    /// Returns the entry's key.
+    pub fn key(&self) -> &K {
+        match *self {
+            Entry::Occupied(ref entry) => entry.key(),
+            Entry::Vacant(ref entry)   => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant, then returns the value,
+    /// returning any allocation errors.
+    pub fn or_insert(self, default: V) -> VecResult<&'a mut V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant, then returns
+    /// the value, returning any allocation errors.
+    pub fn or_insert_with<F>(self, default: F) -> VecResult<&'a mut V>
+        where F: FnOnce() -> V
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry)   => entry.insert(default()),
+        }
+    }
+
+    /// Inserts the result of `default`, which is given the key, if the entry
+    /// is vacant. Then returns the value, returning any allocation errors.
+    pub fn or_insert_with_key<F>(self, default: F) -> VecResult<&'a mut V>
+        where F: FnOnce(&K) -> V
+    {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry)   => {
+                let value = default(entry.key());
+                entry.insert(value)
+            },
+        }
+    }
+
+    /// Inserts `V::default()` if the entry is vacant, then returns the value,
+    /// returning any allocation errors.
+    pub fn or_default(self) -> VecResult<&'a mut V>
+        where V: Default
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F>(self, f: F) -> Self
+        where F: FnOnce(&mut V)
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            entry => entry,
+        }
+    }
+}
+
+/// An entry that holds a value. See `Entry`.
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, A: 'a, S: 'a>
+    where A: alloc_api::Allocator
+{
+    map: &'a mut HashMap<K, V, A, S>,
+    idx: usize, // The full slot.
+}
+
+impl <'a, K, V, A, S> OccupiedEntry<'a, K, V, A, S>
+    where K: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{
+    /// Returns the entry's key.
+    pub fn key(&self) -> &K {
+        match self.map.slots()[self.idx] {
+            Slot::Full(_, ref key, _) => key,
+            _ => unreachable!("an occupied entry's slot wasn't full"),
+        }
+    }
+
+    /// Returns the entry's value.
+    pub fn get(&self) -> &V {
+        match self.map.slots()[self.idx] {
+            Slot::Full(_, _, ref value) => value,
+            _ => unreachable!("an occupied entry's slot wasn't full"),
+        }
+    }
+
+    /// Returns the entry's value mutably.
+    pub fn get_mut(&mut self) -> &mut V {
+        match self.map.slots_mut()[self.idx] {
+            Slot::Full(_, _, ref mut value) => value,
+            _ => unreachable!("an occupied entry's slot wasn't full"),
+        }
+    }
+
+    /// Returns the entry's value mutably, for as long as the HashMap was
+    /// borrowed.
+    pub fn into_mut(self) -> &'a mut V {
+        match self.map.slots_mut()[self.idx] {
+            Slot::Full(_, _, ref mut value) => value,
+            _ => unreachable!("an occupied entry's slot wasn't full"),
+        }
+    }
+
+    /// Replaces the entry's value, and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry's item, and returns its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry's item, and returns its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        self.map.remove_at(self.idx)
+    }
+}
+
+/// An entry without a value. See `Entry`.
+pub struct VacantEntry<'a, K: 'a, V: 'a, A: 'a, S: 'a>
+    where A: alloc_api::Allocator
+{
+    map:  &'a mut HashMap<K, V, A, S>,
+    hash: u64,
+    key:  K,
+}
+
+impl <'a, K, V, A, S> VacantEntry<'a, K, V, A, S>
+    where K: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{
+    /// Returns the key that would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Gives back the key without inserting anything.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the entry's key and returns it, returning any
+    /// allocation errors.
+    pub fn insert(self, value: V) -> VecResult<&'a mut V> {
+        let map = self.map;
+        let idx = map.insert_new(self.hash, self.key, value)?;
+        match map.slots_mut()[idx] {
+            Slot::Full(_, _, ref mut value) => Ok(value),
+            _ => unreachable!("an inserted slot wasn't full"),
+        }
+    }
+}
+
+impl <'a, K, V, A, S> fmt::Debug for Entry<'a, K, V, A, S>
+    where K: fmt::Debug + Eq + Hash,
+          V: fmt::Debug,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Entry::Occupied(ref entry) => {
+                f.debug_tuple("Occupied").field(entry.key())
+                                         .field(entry.get())
+                                         .finish()
+            },
+            Entry::Vacant(ref entry) => {
+                f.debug_tuple("Vacant").field(entry.key()).finish()
+            },
+        }
+    }
+}
+
+// ----- Iterators & Traits -----------------------------------------------------
+
+// See `HashMap::iter()`
+pub struct Iter<'a, K: 'a, V: 'a> {
+    slots: slice::Iter<'a, Slot<K, V>>,
+    left:  usize, // Count of full slots left.
+}
+
+impl <'a, K, V> iter::Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        for slot in &mut self.slots {
+            if let Slot::Full(_, ref key, ref value) = *slot {
+                self.left -= 1;
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for Iter<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for Iter<'a, K, V> {}
+
+impl <'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            slots: self.slots.clone(),
+            left:  self.left,
+        }
+    }
+}
+
+// See `HashMap::iter_mut()`
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    slots: slice::IterMut<'a, Slot<K, V>>,
+    left:  usize, // Count of full slots left.
+}
+
+impl <'a, K, V> iter::Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        for slot in &mut self.slots {
+            if let Slot::Full(_, ref key, ref mut value) = *slot {
+                self.left -= 1;
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for IterMut<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for IterMut<'a, K, V> {}
+
+// See `HashMap::keys()`
+pub struct Keys<'a, K: 'a, V: 'a> {
+    iter: Iter<'a, K, V>,
+}
+
+impl <'a, K, V> iter::Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(key, _)| key)
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for Keys<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for Keys<'a, K, V> {}
+
+impl <'a, K, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Self {
+        Keys {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+// See `HashMap::values()`
+pub struct Values<'a, K: 'a, V: 'a> {
+    iter: Iter<'a, K, V>,
+}
+
+impl <'a, K, V> iter::Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.iter.next().map(|(_, value)| value)
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for Values<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for Values<'a, K, V> {}
+
+// See `HashMap::values_mut()`
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    iter: IterMut<'a, K, V>,
+}
+
+impl <'a, K, V> iter::Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.iter.next().map(|(_, value)| value)
+    }
+}
+
+impl <'a, K, V> iter::ExactSizeIterator for ValuesMut<'a, K, V> {}
+
+impl <'a, K, V> iter::FusedIterator for ValuesMut<'a, K, V> {}
+
+pub struct IntoIter<K, V, A, S = DefaultHashBuilder>
+    where A: alloc_api::Allocator
+{
+    map: HashMap<K, V, A, S>, // Drops the items that are left.
+    idx: usize,               // The next slot to look at.
+}
+
+impl <K, V, A, S> iter::Iterator for IntoIter<K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = (K, V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.map.len, Some(self.map.len))
+    }
+
+    fn next(&mut self) -> Option<(K, V)> {
+        while self.idx < self.map.buckets {
+            let slot = mem::replace(&mut self.map.slots_mut()[self.idx], Slot::Empty);
+            self.idx += 1;
+            if let Slot::Full(_, key, value) = slot {
+                self.map.len -= 1;
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+impl <K, V, A, S> iter::ExactSizeIterator for IntoIter<K, V, A, S>
+    where A: alloc_api::Allocator
+{}
+
+impl <K, V, A, S> iter::FusedIterator for IntoIter<K, V, A, S>
+    where A: alloc_api::Allocator
+{}
+
+// See `HashMap::drain()`
+pub struct Drain<'a, K: 'a, V: 'a, A: 'a, S: 'a = DefaultHashBuilder>
+    where A: alloc_api::Allocator
+{
+    map:     &'a mut HashMap<K, V, A, S>,
+    slots:   NonNull<Slot<K, V>>, // The table, taken from `map`.
+    buckets: usize,               // Count of slots in the table.
+    idx:     usize,               // The next slot to look at.
+    left:    usize,               // Count of full slots left.
+}
+
+impl <'a, K, V, A, S> iter::Iterator for Drain<'a, K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = (K, V);
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.left, Some(self.left))
+    }
+
+    fn next(&mut self) -> Option<(K, V)> {
+        while self.idx < self.buckets {
+            let slot = unsafe {
+                let slot = &mut *self.slots.as_ptr().add(self.idx);
+                mem::replace(slot, Slot::Empty)
+            };
+            self.idx += 1;
+            if let Slot::Full(_, key, value) = slot {
+                self.left -= 1;
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+impl <'a, K, V, A, S> iter::ExactSizeIterator for Drain<'a, K, V, A, S>
+    where A: alloc_api::Allocator
+{}
+
+impl <'a, K, V, A, S> iter::FusedIterator for Drain<'a, K, V, A, S>
+    where A: alloc_api::Allocator
+{}
+
+impl <'a, K, V, A, S> Drop for Drain<'a, K, V, A, S>
+    where A: alloc_api::Allocator
+{
+    fn drop(&mut self) {
+        // Drop all remaining items
+        for _ in &mut *self {}
+        // Hand back the table, which is now empty.
+        self.map.slots = self.slots;
+        self.map.buckets = self.buckets;
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use string::String;
+    use test_support::{DropCounter, SipState, prop_config};
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        collections,
+        hash::{BuildHasherDefault, Hasher},
+        mem,
+        vec,
+    };
+
+    // Hashes everything to the same value, so that every key collides.
+    #[derive(Default)]
+    struct Collide;
+
+    impl Hasher for Collide {
+        fn finish(&self) -> u64 {
+            7
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    type CollideState = BuildHasherDefault<Collide>;
+
+    // Hashes a `u8` to itself, so that probe runs are easy to predict, and
+    // often wrap around the end of the table.
+    #[derive(Default)]
+    struct Identity(u64);
+
+    impl Hasher for Identity {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 << 8) | u64::from(b);
+            }
+        }
+    }
+
+    type IdentityState = BuildHasherDefault<Identity>;
+
+    fn sorted<'a, I>(iter: I) -> vec::Vec<(u32, u32)>
+        where I: Iterator<Item = (&'a u32, &'a u32)>
+    {
+        let mut items: vec::Vec<_> = iter.map(|(&k, &v)| (k, v)).collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn check_insert_get_remove() {
+        let mut buf = vec![0u8; 1 << 15];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_hasher(&alloc, SipState::default());
+        assert_eq!(m.capacity(), 0);
+        assert_eq!(m.get(&1), None);
+
+        for i in 0..100u32 {
+            assert_eq!(m.insert(i, i * 10).expect("m.insert(..) failed."), None);
+        }
+        assert_eq!(m.len(), 100);
+        assert!(m.capacity() >= 100);
+        assert_eq!(m.insert(5, 0).expect("m.insert(..) failed."), Some(50));
+
+        for i in 0..100u32 {
+            let expected = if i == 5 { 0 } else { i * 10 };
+            assert_eq!(m.get(&i), Some(&expected));
+        }
+        *m.get_mut(&7).expect("m.get_mut(..) failed.") += 1;
+        assert_eq!(m[&7], 71);
+        assert_eq!(m.get_key_value(&8), Some((&8, &80)));
+        assert!(!m.contains_key(&100));
+
+        for i in (0..100u32).filter(|i| i % 2 == 0) {
+            assert!(m.remove(&i).is_some());
+        }
+        assert_eq!(m.remove(&0), None);
+        assert_eq!(m.remove_entry(&9), Some((9, 90)));
+        assert_eq!(m.len(), 49);
+        assert!(m.iter().all(|(k, _)| k % 2 == 1 && *k != 9));
+    }
+
+    #[test]
+    fn check_borrowed_keys() {
+        let mut buf = [0u8; 512];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_hasher(&alloc, SipState::default());
+
+        for (i, word) in ["one", "two"].iter().enumerate() {
+            let key = String::from_str_in(word, &alloc)
+                          .expect("String::from_str_in(..) failed.");
+            m.insert(key, i + 1).expect("m.insert(..) failed.");
+        }
+        assert_eq!(m.get("one"), Some(&1));
+        assert_eq!(m["two"], 2);
+        assert_eq!(m.remove("one"), Some(1));
+        assert!(!m.contains_key("one"));
+    }
+
+    #[test]
+    fn check_collisions() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_hasher(&alloc, CollideState::default());
+
+        for i in 0..20u32 {
+            m.insert(i, i).expect("m.insert(..) failed.");
+        }
+        // Removing from the middle of the probe chain keeps the rest found.
+        for i in (0..20u32).step_by(3) {
+            assert_eq!(m.remove(&i), Some(i));
+        }
+        for i in 0..20u32 {
+            assert_eq!(m.get(&i).is_some(), i % 3 != 0);
+        }
+        m.insert(3, 33).expect("m.insert(..) failed.");
+        m.insert(4, 44).expect("m.insert(..) failed.");
+        assert_eq!(m[&3], 33);
+        assert_eq!(m[&4], 44);
+        assert_eq!(m.len(), 14);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_default_hasher() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::new(&alloc);
+        assert_eq!(m.capacity(), 0);
+        m.insert(1u32, 10u32).expect("m.insert(..) failed.");
+        assert_eq!(m[&1], 10);
+
+        let n = HashMap::<u32, u32, _>::with_capacity(&alloc, 8)
+                    .expect("HashMap::with_capacity(..) failed.");
+        assert!(n.capacity() >= 8);
+    }
+
+    #[test]
+    fn check_churn_keeps_capacity() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_capacity_and_hasher(&alloc, 8, SipState::default())
+                        .expect("HashMap::with_capacity_and_hasher(..) failed.");
+        let cap = m.capacity();
+
+        // Churning through keys never needs a bigger table.
+        for i in 0..1000u32 {
+            m.insert(i, i).expect("m.insert(..) failed.");
+            if i >= 4 {
+                assert_eq!(m.remove(&(i - 4)), Some(i - 4));
+            }
+        }
+        assert_eq!(m.capacity(), cap);
+        assert_eq!(sorted(m.iter()), [(996, 996), (997, 997), (998, 998), (999, 999)]);
+    }
+
+    #[test]
+    fn check_entry() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_hasher(&alloc, SipState::default());
+
+        for word in "a b a c b a".split(' ') {
+            *m.entry(word).or_insert(0).expect("or_insert(..) failed.") += 1;
+        }
+        assert_eq!((m["a"], m["b"], m["c"]), (3, 2, 1));
+
+        m.entry("c").and_modify(|n| *n += 10).or_default()
+         .expect("or_default() failed.");
+        m.entry("d").and_modify(|n| *n += 10).or_default()
+         .expect("or_default() failed.");
+        assert_eq!((m["c"], m["d"]), (11, 0));
+
+        let len = *m.entry("eee").or_insert_with_key(|k| k.len())
+                                 .expect("or_insert_with_key(..) failed.");
+        assert_eq!(len, 3);
+
+        match m.entry("a") {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &"a");
+                assert_eq!(e.insert(30), 3);
+                assert_eq!(e.remove_entry(), ("a", 30));
+            },
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        match m.entry("a") {
+            Entry::Vacant(e) => assert_eq!(e.into_key(), "a"),
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+        }
+        assert_eq!(format!("{:?}", m.entry("b")), "Occupied(\"b\", 2)");
+        assert_eq!(m.len(), 4);
+    }
+
+    #[test]
+    fn check_retain_and_iters() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_hasher(&alloc, SipState::default());
+        m.try_extend((0..10u32).map(|i| (i, i))).expect("m.try_extend(..) failed.");
+
+        m.retain(|k, v| {
+            *v *= 2;
+            k % 3 == 0
+        });
+        assert_eq!(sorted(m.iter()), [(0, 0), (3, 6), (6, 12), (9, 18)]);
+
+        for (_, v) in &mut m {
+            *v += 1;
+        }
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        assert_eq!(m.iter().len(), 4);
+        let mut keys: vec::Vec<_> = m.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, [0, 3, 6, 9]);
+        assert_eq!(m.values().sum::<u32>(), 44);
+
+        let mut items: vec::Vec<_> = m.into_iter().collect();
+        items.sort();
+        assert_eq!(items, [(0, 2), (3, 8), (6, 14), (9, 20)]);
+    }
+
+    #[test]
+    fn check_drain_and_drops() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+        let mut m = HashMap::with_hasher(&alloc, SipState::default());
+
+        for i in 0..10u32 {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        m.insert(0, DropCounter(&count)).expect("m.insert(..) failed.");
+        assert_eq!(count.get(), 1);
+
+        let mut drain = m.drain();
+        assert_eq!(drain.len(), 10);
+        mem::drop(drain.next());
+        mem::drop(drain);
+        assert_eq!(count.get(), 11);
+        assert!(m.is_empty());
+
+        // A leaked Drain leaks its items, but leaves the HashMap usable.
+        for i in 0..4u32 {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        let mut drain = m.drain();
+        mem::drop(drain.next());
+        mem::forget(drain);
+        assert!(m.is_empty());
+        assert_eq!(m.get(&1).map(|_| ()), None);
+        assert_eq!(count.get(), 12);
+
+        for i in 0..2u32 {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        let mut iter = m.into_iter();
+        mem::drop(iter.next());
+        mem::drop(iter);
+        assert_eq!(count.get(), 14);
+    }
+
+    #[test]
+    fn check_shrink_to_fit() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_capacity_and_hasher(&alloc, 100, SipState::default())
+                        .expect("HashMap::with_capacity_and_hasher(..) failed.");
+        m.insert(1u32, 1u32).expect("m.insert(..) failed.");
+
+        m.shrink_to_fit().expect("m.shrink_to_fit() failed.");
+        assert_eq!(m.capacity(), 3);
+        assert_eq!(m[&1], 1);
+
+        m.remove(&1);
+        m.shrink_to_fit().expect("m.shrink_to_fit() failed.");
+        assert_eq!(m.capacity(), 0);
+        assert_eq!(m.get(&1), None);
+    }
+
+    #[test]
+    fn check_resize_failure_keeps_table() {
+        // A resize needs the old and the new table at once, so a buffer with
+        // room for twice the table can hold it full, but not grow it.
+        let table = {
+            let mut buf = vec![0u8; 4096];
+            let alloc = LinearAlloc::new(&mut buf);
+            let _m = HashMap::<u32, DropCounter, _, _>::with_capacity_and_hasher(
+                         &alloc, 7, SipState::default())
+                         .expect("HashMap::with_capacity_and_hasher(..) failed.");
+            alloc.bytes_in_use()
+        };
+        let mut buf = vec![0u8; 2 * table];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+
+        let mut m = HashMap::with_capacity_and_hasher(&alloc, 7, SipState::default())
+                        .expect("HashMap::with_capacity_and_hasher(..) failed.");
+        assert_eq!(m.capacity(), 7);
+        for i in 0..7u32 {
+            m.insert(i, DropCounter(&count)).expect("m.insert(..) failed.");
+        }
+        match m.insert(7, DropCounter(&count)) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res.map(|_| ())),
+        }
+        // The rejected value is dropped, and the table is untouched.
+        assert_eq!(count.get(), 1);
+        assert_eq!(m.capacity(), 7);
+        assert!((0..7).all(|i| m.contains_key(&i)));
+
+        // Replacing a value, or reusing a removed item's slot, doesn't resize.
+        let old = m.insert(3, DropCounter(&count)).expect("m.insert(..) failed.");
+        assert!(old.is_some());
+        mem::drop(old);
+        assert_eq!(count.get(), 2);
+        mem::drop(m.remove(&0));
+        m.insert(7, DropCounter(&count)).expect("m.insert(..) failed.");
+        assert!((1..8).all(|i| m.contains_key(&i)));
+        assert_eq!(m.capacity(), 7);
+
+        let res = HashMap::<u32, u32, _, _>::with_capacity_and_hasher(
+                      &alloc, usize::MAX, SipState::default());
+        match res {
+            Err(Error::SizeOverflowErr) => {},
+            res => panic!("expected SizeOverflowErr, got {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn check_eq_and_debug() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut m = HashMap::with_hasher(&alloc, SipState::default());
+        let mut n = HashMap::with_hasher(&alloc, CollideState::default());
+
+        for i in 0..3u32 {
+            m.insert(i, i).expect("m.insert(..) failed.");
+            n.insert(2 - i, 2 - i).expect("n.insert(..) failed.");
+        }
+        assert!(m == n);
+        n.insert(0, 1).expect("n.insert(..) failed.");
+        assert!(m != n);
+
+        let mut one = HashMap::with_hasher(&alloc, SipState::default());
+        one.insert(1u32, "x").expect("one.insert(..) failed.");
+        assert_eq!(format!("{:?}", one), "{1: \"x\"}");
+    }
+
+    // A step of the model test below.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(u8, u32),
+        Remove(u8),
+        Retain(u8),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (any::<u8>(), any::<u32>()).prop_map(|(k, v)| Op::Insert(k, v)),
+            any::<u8>().prop_map(Op::Remove),
+            (1..8u8).prop_map(Op::Retain),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_matches_std_hash_map(ops in proptest::collection::vec(arb_op(), 0..128)) {
+            let mut buf = vec![0u8; 1 << 16];
+            let alloc = LinearAlloc::new(&mut buf);
+            check_against_model(HashMap::with_hasher(&alloc, SipState::default()), ops)?;
+        }
+
+        #[test]
+        fn prop_matches_std_hash_map_when_runs_wrap(
+            ops in proptest::collection::vec(arb_op(), 0..128))
+        {
+            let mut buf = vec![0u8; 1 << 16];
+            let alloc = LinearAlloc::new(&mut buf);
+            check_against_model(HashMap::with_hasher(&alloc, IdentityState::default()),
+                                ops)?;
+        }
+    }
+
+    fn check_against_model<A, S>(mut m: HashMap<u8, u32, A, S>, ops: vec::Vec<Op>)
+        -> result::Result<(), TestCaseError>
+        where A: alloc_api::Allocator,
+              S: BuildHasher
+    {
+        let mut model = collections::HashMap::new();
+        for op in ops {
+            match op {
+                Op::Insert(k, v) => {
+                    let old = m.insert(k, v).expect("m.insert(..) failed.");
+                    prop_assert_eq!(old, model.insert(k, v));
+                },
+                Op::Remove(k) => prop_assert_eq!(m.remove(&k), model.remove(&k)),
+                Op::Retain(n) => {
+                    m.retain(|k, _| k % n != 0);
+                    model.retain(|k, _| k % n != 0);
+                },
+            }
+            prop_assert_eq!(m.len(), model.len());
+            prop_assert!(m.len() <= m.capacity());
+            for (k, v) in &model {
+                prop_assert_eq!(m.get(k), Some(v));
+            }
+        }
+        Ok(())
+    }
+}
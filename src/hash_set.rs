@@ -0,0 +1,523 @@
+//! A hash set whose table lives in a customizable memory allocator. It is a
+//! `HashMap` with `()` values.
+
+use core::{
+    borrow::Borrow,
+    cmp,
+    fmt,
+    hash::{BuildHasher, Hash},
+    iter,
+    result,
+};
+
+use alloc_api;
+use hash_map::{self, DefaultHashBuilder, HashMap};
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- HashSet Impl -----------------------------------------------------------
+
+/// A hash set with a customizable memory allocator.
+///
+/// `S` builds the hashers for the items, as in `std`.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{hash_set::HashSet, linear_alloc::LinearAlloc};
+/// #
+/// # #[cfg(feature = "std")] {
+/// let mut buf = [0u8; 256];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut s = HashSet::new(&alloc);
+/// assert!(s.insert(3u32).unwrap());
+/// assert!(!s.insert(3).unwrap());
+/// assert!(s.contains(&3));
+/// assert!(s.remove(&3));
+/// assert!(s.is_empty());
+/// # }
+/// ```
+pub struct HashSet<T, A, S = DefaultHashBuilder>
+    where A: alloc_api::Allocator
+{
+    map: HashMap<T, (), A, S>,
+}
+
+#[cfg(feature = "std")]
+impl <T, A: alloc_api::Allocator> HashSet<T, A> {
+    /// Construct a new HashSet using the allocator handle `alloc`.
+    pub fn new(alloc: A) -> Self {
+        HashSet {
+            map: HashMap::new(alloc),
+        }
+    }
+
+    /// Construct a new HashSet with room for at least `capacity` items,
+    /// using the allocator handle `alloc`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        Ok(HashSet {
+            map: HashMap::with_capacity(alloc, capacity)?,
+        })
+    }
+}
+
+impl <T, A, S> HashSet<T, A, S>
+    where A: alloc_api::Allocator
+{
+    /// Construct a new HashSet using the allocator handle `alloc`, which
+    /// hashes items with `hash_builder`.
+    pub fn with_hasher(alloc: A, hash_builder: S) -> Self {
+        HashSet {
+            map: HashMap::with_hasher(alloc, hash_builder),
+        }
+    }
+
+    /// Construct a new HashSet with room for at least `capacity` items,
+    /// using the allocator handle `alloc`, which hashes items with
+    /// `hash_builder`.
+    pub fn with_capacity_and_hasher(alloc: A, capacity: usize, hash_builder: S)
+        -> VecResult<Self>
+    {
+        Ok(HashSet {
+            map: HashMap::with_capacity_and_hasher(alloc, capacity, hash_builder)?,
+        })
+    }
+
+    /// Returns the allocator handle that the HashSet is using.
+    pub fn alloc(&self) -> &A {
+        self.map.alloc()
+    }
+
+    /// Returns the hasher builder that the HashSet is using.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+
+    /// The number of items that the HashSet can hold before resizing.
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    /// The number of items currently in the HashSet.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the HashSet holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Drops all items, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    /// Keeps only the items for which `f` returns `true`.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&T) -> bool
+    {
+        self.map.retain(|item, _| f(item))
+    }
+
+    /// Returns an iterator over the items, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            iter: self.map.keys(),
+        }
+    }
+
+    /// Creates a draining iterator that removes the items from the HashSet,
+    /// and then yields them. The allocation is kept.
+    pub fn drain(&mut self) -> Drain<'_, T, A, S> {
+        Drain {
+            iter: self.map.drain(),
+        }
+    }
+}
+
+impl <T, A, S> HashSet<T, A, S>
+    where T: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{
+    /// Reserve room for at least `additional` more items.
+    ///
+    /// # Panics
+    /// Panics if the allocation fails. See `try_reserve` to handle the error.
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    /// Reserve room for at least `additional` more items, returning any
+    /// allocation errors.
+    pub fn try_reserve(&mut self, additional: usize) -> VecResult<()> {
+        self.map.try_reserve(additional)
+    }
+
+    /// Shrinks the table as far as it can while holding the current items.
+    pub fn shrink_to_fit(&mut self) -> VecResult<()> {
+        self.map.shrink_to_fit()
+    }
+
+    /// Insert `value`, returning any allocation errors.
+    ///
+    /// Returns `false` if an equal item was already present. That item is
+    /// kept, and `value` is dropped.
+    pub fn insert(&mut self, value: T) -> VecResult<bool> {
+        match self.map.entry(value) {
+            hash_map::Entry::Occupied(_)   => Ok(false),
+            hash_map::Entry::Vacant(entry) => entry.insert(()).map(|_| true),
+        }
+    }
+
+    /// Insert `value`, replacing an equal item if there is one, and returning
+    /// any allocation errors.
+    ///
+    /// Returns the replaced item.
+    pub fn replace(&mut self, value: T) -> VecResult<Option<T>> {
+        let old = self.map.remove_entry(&value).map(|(item, ())| item);
+        self.map.insert(value, ())?;
+        Ok(old)
+    }
+
+    /// Extend the HashSet with the items of `iter`, returning any allocation
+    /// errors. The items before the failing one are kept.
+    pub fn try_extend<I>(&mut self, iter: I) -> VecResult<()>
+        where I: IntoIterator<Item = T>
+    {
+        self.map.try_extend(iter.into_iter().map(|item| (item, ())))
+    }
+
+    /// Returns `true` if the HashSet holds an item equal to `value`.
+    pub fn contains<Q>(&self, value: &Q) -> bool
+        where T: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Returns the stored item equal to `value`, if there is one.
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+        where T: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.map.get_key_value(value).map(|(item, _)| item)
+    }
+
+    /// Removes the item equal to `value`. Returns `true` if there was one.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+        where T: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes the item equal to `value`, and returns it.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+        where T: Borrow<Q>,
+              Q: Eq + Hash + ?Sized
+    {
+        self.map.remove_entry(value).map(|(item, ())| item)
+    }
+
+    /// Returns `true` if every item of this HashSet is in `other`.
+    pub fn is_subset<B, R>(&self, other: &HashSet<T, B, R>) -> bool
+        where B: alloc_api::Allocator,
+              R: BuildHasher
+    {
+        self.len() <= other.len() && self.iter().all(|item| other.contains(item))
+    }
+
+    /// Returns `true` if every item of `other` is in this HashSet.
+    pub fn is_superset<B, R>(&self, other: &HashSet<T, B, R>) -> bool
+        where B: alloc_api::Allocator,
+              R: BuildHasher
+    {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if no item is in both this HashSet and `other`.
+    pub fn is_disjoint<B, R>(&self, other: &HashSet<T, B, R>) -> bool
+        where B: alloc_api::Allocator,
+              R: BuildHasher
+    {
+        if self.len() <= other.len() {
+            self.iter().all(|item| !other.contains(item))
+        } else {
+            other.iter().all(|item| !self.contains(item))
+        }
+    }
+}
+
+// ----- HashSet Traits ---------------------------------------------------------
+
+impl <T, A, S> iter::IntoIterator for HashSet<T, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, A, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: self.map.into_iter(),
+        }
+    }
+}
+
+impl <'a, T, A, S> iter::IntoIterator for &'a HashSet<T, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl <T, A, S> fmt::Debug for HashSet<T, A, S>
+    where T: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self).finish()
+    }
+}
+
+impl <T, A, S, B, R> cmp::PartialEq<HashSet<T, B, R>> for HashSet<T, A, S>
+    where T: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher,
+          B: alloc_api::Allocator,
+          R: BuildHasher
+{
+    fn eq(&self, other: &HashSet<T, B, R>) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
+
+impl <T, A, S> cmp::Eq for HashSet<T, A, S>
+    where T: Eq + Hash,
+          A: alloc_api::Allocator,
+          S: BuildHasher
+{}
+
+// ----- Iterators & Traits -----------------------------------------------------
+
+// See `HashSet::iter()`
+pub struct Iter<'a, T: 'a> {
+    iter: hash_map::Keys<'a, T, ()>,
+}
+
+impl <'a, T> iter::Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next()
+    }
+}
+
+impl <'a, T> iter::ExactSizeIterator for Iter<'a, T> {}
+
+impl <'a, T> iter::FusedIterator for Iter<'a, T> {}
+
+impl <'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Self {
+        Iter {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+pub struct IntoIter<T, A, S = DefaultHashBuilder>
+    where A: alloc_api::Allocator
+{
+    iter: hash_map::IntoIter<T, (), A, S>,
+}
+
+impl <T, A, S> iter::Iterator for IntoIter<T, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<T> {
+        self.iter.next().map(|(item, ())| item)
+    }
+}
+
+impl <T, A, S> iter::ExactSizeIterator for IntoIter<T, A, S>
+    where A: alloc_api::Allocator
+{}
+
+impl <T, A, S> iter::FusedIterator for IntoIter<T, A, S>
+    where A: alloc_api::Allocator
+{}
+
+// See `HashSet::drain()`
+pub struct Drain<'a, T: 'a, A: 'a, S: 'a = DefaultHashBuilder>
+    where A: alloc_api::Allocator
+{
+    iter: hash_map::Drain<'a, T, (), A, S>,
+}
+
+impl <'a, T, A, S> iter::Iterator for Drain<'a, T, A, S>
+    where A: alloc_api::Allocator
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn next(&mut self) -> Option<T> {
+        self.iter.next().map(|(item, ())| item)
+    }
+}
+
+impl <'a, T, A, S> iter::ExactSizeIterator for Drain<'a, T, A, S>
+    where A: alloc_api::Allocator
+{}
+
+impl <'a, T, A, S> iter::FusedIterator for Drain<'a, T, A, S>
+    where A: alloc_api::Allocator
+{}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use string::String;
+    use test_support::SipState;
+
+    use std::vec;
+
+    fn sorted<'a, I>(iter: I) -> vec::Vec<u32>
+        where I: Iterator<Item = &'a u32>
+    {
+        let mut items: vec::Vec<_> = iter.cloned().collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn check_insert_contains_remove() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = HashSet::with_hasher(&alloc, SipState::default());
+
+        for i in 0..50u32 {
+            assert!(s.insert(i % 25).expect("s.insert(..) failed.") == (i < 25));
+        }
+        assert_eq!(s.len(), 25);
+        assert!(s.contains(&24));
+        assert!(!s.contains(&25));
+        assert_eq!(s.get(&3), Some(&3));
+
+        assert!(s.remove(&3));
+        assert!(!s.remove(&3));
+        assert_eq!(s.take(&4), Some(4));
+        assert_eq!(s.len(), 23);
+
+        s.retain(|&i| i % 5 == 0);
+        assert_eq!(sorted(s.iter()), [0, 5, 10, 15, 20]);
+        let drained: vec::Vec<_> = s.drain().collect();
+        assert_eq!(sorted(drained.iter()), [0, 5, 10, 15, 20]);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn check_replace_and_borrowed_items() {
+        let mut buf = [0u8; 512];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = HashSet::with_hasher(&alloc, SipState::default());
+
+        let word = String::from_str_in("word", &alloc)
+                       .expect("String::from_str_in(..) failed.");
+        assert!(s.insert(word).expect("s.insert(..) failed."));
+        assert!(s.contains("word"));
+
+        let again = String::from_str_in("word", &alloc)
+                        .expect("String::from_str_in(..) failed.");
+        let old = s.replace(again).expect("s.replace(..) failed.");
+        assert_eq!(old.expect("expected a replaced item."), "word");
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn check_set_relations() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut a = HashSet::with_hasher(&alloc, SipState::default());
+        let mut b = HashSet::with_hasher(&alloc, SipState::default());
+        let mut c = HashSet::with_hasher(&alloc, SipState::default());
+        a.try_extend(0..4u32).expect("a.try_extend(..) failed.");
+        b.try_extend(0..8u32).expect("b.try_extend(..) failed.");
+        c.try_extend(10..12u32).expect("c.try_extend(..) failed.");
+
+        assert!(a.is_subset(&b));
+        assert!(b.is_superset(&a));
+        assert!(!b.is_subset(&a));
+        assert!(a.is_disjoint(&c));
+        assert!(!a.is_disjoint(&b));
+        assert!(a != b);
+
+        b.retain(|&i| i < 4);
+        assert!(a == b);
+        assert_eq!(sorted(b.into_iter().collect::<vec::Vec<_>>().iter()),
+                   [0, 1, 2, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_default_hasher() {
+        let mut buf = vec![0u8; 4096];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = HashSet::new(&alloc);
+        assert!(s.insert(1u32).expect("s.insert(..) failed."));
+        assert!(s.contains(&1));
+
+        let t = HashSet::<u32, _>::with_capacity(&alloc, 8)
+                    .expect("HashSet::with_capacity(..) failed.");
+        assert!(t.capacity() >= 8);
+    }
+
+    #[test]
+    fn check_resize_failure_keeps_set() {
+        // A resize needs the old and the new table at once, so a buffer with
+        // room for twice the table can hold it full, but not grow it.
+        let table = {
+            let mut buf = vec![0u8; 4096];
+            let alloc = LinearAlloc::new(&mut buf);
+            let _s = HashSet::<u32, _, _>::with_capacity_and_hasher(
+                         &alloc, 7, SipState::default())
+                         .expect("HashSet::with_capacity_and_hasher(..) failed.");
+            alloc.bytes_in_use()
+        };
+        let mut buf = vec![0u8; 2 * table];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = HashSet::with_capacity_and_hasher(&alloc, 7, SipState::default())
+                        .expect("HashSet::with_capacity_and_hasher(..) failed.");
+
+        s.try_extend(0..7u32).expect("s.try_extend(..) failed.");
+        assert_eq!(s.capacity(), 7);
+        // Items already in the set need no room.
+        assert!(!s.insert(3).expect("s.insert(..) failed."));
+        match s.insert(7) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert_eq!(sorted(s.iter()), [0, 1, 2, 3, 4, 5, 6]);
+    }
+}
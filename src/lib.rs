@@ -1,30 +1,73 @@
-#![feature(allocator_api)]
+//! Collections and pointers that allocate through a customizable allocator.
+//!
+//! They all work the same way as `vec2::Vec`, whose docs describe how they
+//! report allocation errors and compare across allocators.
+
+#![cfg_attr(not(feature = "allocator-shim"), feature(allocator_api))]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 #![deny(warnings)]
 
+// `core` is only injected for `no_std` crates.
+#[cfg(feature = "std")]
+extern crate core;
+
+// Tests always run with `std` available.
+#[cfg(all(test, not(feature = "std")))]
+#[macro_use]
+extern crate std;
+
 #[cfg(test)]
 #[macro_use]
 extern crate pretty_assertions;
 
+#[cfg(test)]
+extern crate proptest;
+
 #[derive(Clone, Debug)]
 pub enum Error {
-    LayoutErr(std::alloc::LayoutErr),
-    AllocErr(std::alloc::AllocErr),
+    LayoutErr(alloc_api::LayoutError),
+    AllocErr(alloc_api::AllocError),
     SizeOverflowErr,
+    // A `StorageVec` over fixed capacity storage is out of room.
+    CapacityErr,
 }
 
-impl std::convert::From<std::alloc::LayoutErr> for Error {
-    fn from(layout_error: std::alloc::LayoutErr) -> Error {
+impl core::convert::From<alloc_api::LayoutError> for Error {
+    fn from(layout_error: alloc_api::LayoutError) -> Error {
         Error::LayoutErr(layout_error)
     }
 }
 
-impl std::convert::From<std::alloc::AllocErr> for Error {
-    fn from(alloc_error: std::alloc::AllocErr) -> Error {
+impl core::convert::From<alloc_api::AllocError> for Error {
+    fn from(alloc_error: alloc_api::AllocError) -> Error {
         Error::AllocErr(alloc_error)
     }
 }
 
+// Declared first, so the other modules can use the macros.
+#[macro_use]
+mod macros;
+
+pub mod alloc_api;
+pub mod array_vec;
+pub mod binary_heap;
+pub mod boxed;
+pub mod btree_map;
+pub mod btree_set;
+pub mod growth;
+pub mod hash_map;
+pub mod hash_set;
 pub mod linear_alloc;
 pub mod raw_vec;
+pub mod rc;
+pub mod slice_vec;
+pub mod small_vec;
+pub mod storage_vec;
+pub mod string;
+pub mod sync;
 pub mod vec2;
+pub mod vec_deque;
+
+#[cfg(test)]
+mod test_support;
@@ -1,10 +1,14 @@
 
-use std::{
-    alloc,
+use core::{
+    cell::Cell,
+    marker::PhantomData,
+    ptr::{self, NonNull},
     result,
-    ptr::NonNull,
+    slice,
 };
 
+use alloc_api;
+
 /// A linear allocator which uses a supplied-slice as backing memory.
 ///
 /// The user supplied slice can exist on the stack or heap, but it must outlive
@@ -15,12 +19,17 @@ use std::{
 ///
 /// (Note: If all allocations are `dealloc`ed in the exact,
 /// opposite order in which they were `alloc`ed, then all allocations can be
-/// reused for further memory requests.)
+/// reused for further memory requests. Padding inserted to align a block is
+/// not reclaimed, and keeps the block before it from being freed.)
+///
+/// The latest allocation can also be grown or shrunk in place.
+///
+/// `Allocator` is implemented for `LinearAlloc` through `&self`, so any number
+/// of containers can share one allocator by each holding a `&LinearAlloc`.
 ///
 /// ```rust
-/// # #![feature(allocator_api)]
-/// # use std::alloc::*;
-/// # use std::ptr;
+/// # #![cfg_attr(not(feature = "allocator-shim"), feature(allocator_api))]
+/// # use alloc_utils::alloc_api::*;
 /// # use alloc_utils::linear_alloc::LinearAlloc;
 /// #
 /// // Force the allocator to start on an 8-byte aligned boundary.
@@ -29,37 +38,37 @@ use std::{
 ///
 /// let mut allocator = LinearAlloc::new(&mut buf.buf);
 ///
-/// // The Allocator API is predominately unsafe.
+/// // Freeing memory through the Allocator API is unsafe.
 /// unsafe {
 ///     // Allocate extremely small blocks.
-///     let _ = allocator.alloc_one::<u8>().unwrap();
+///     let _ = allocator.allocate(Layout::new::<u8>()).unwrap();
 ///     assert_eq!(allocator.bytes_in_use(), 1);
 ///
 ///     // Allocations are still aligned, and can "waste" space.
 ///     // u16 is 2-byte aligned, so we "waste" a byte.
-///     let _ = allocator.alloc_one::<u16>().unwrap();
+///     let _ = allocator.allocate(Layout::new::<u16>()).unwrap();
 ///     assert_eq!(allocator.bytes_in_use(), 4);
 ///
 ///     // Save spots in the stack.
 ///     let marker_at_4 = allocator.get_marker();
 ///
 ///     // Allocating arrays.
-///     let _ = allocator.alloc_array::<u32>(2).unwrap();
+///     let _ = allocator.allocate(Layout::array::<u32>(2).unwrap()).unwrap();
 ///     assert_eq!(allocator.bytes_in_use(), 12);
 ///
-///     let ptr = allocator.alloc_one::<u64>().unwrap();
+///     let ptr = allocator.allocate(Layout::new::<u64>()).unwrap();
 ///     assert_eq!(allocator.bytes_in_use(), 24);
 ///
 ///     // Deallocating blocks from the top actually frees them.
-///     allocator.dealloc_one::<u64>(ptr);
+///     allocator.deallocate(ptr.cast(), Layout::new::<u64>());
 ///     assert_eq!(allocator.bytes_in_use(), 16);
 ///
 ///     // High water mark to see how bad it got.
 ///     assert_eq!(allocator.high_water_mark(), 24);
 ///
 ///     // Ooms fail gracefully.
-///     let res = allocator.alloc_array::<u64>(6);
-///     assert_eq!(res, Err(AllocErr));
+///     let res = allocator.allocate(Layout::array::<u64>(6).unwrap());
+///     assert_eq!(res, Err(AllocError));
 ///
 ///     // Restore saved locations.
 ///     allocator.reset_to(marker_at_4);
@@ -72,14 +81,26 @@ use std::{
 /// ```
 #[derive(Debug)]
 pub struct LinearAlloc<'a> {
-    // The buffer backing allocations
-    buf:  &'a [u8],
+    // The buffer backing allocations.
+    // This is kept as a raw pointer, derived from the `&mut` that we were
+    // constructed with, so that blocks handed out through `&self` may be
+    // written to.
+    buf:  NonNull<u8>,
+    // The length of the buffer backing allocations.
+    len:  usize,
     // The current top of the stack as an index into buf.
-    top:  usize,
+    // `Allocator` methods take `&self`, so this needs interior mutability.
+    top:  Cell<usize>,
     // The high water mark of the allocator, as an index into buf.
-    high: usize,
+    high: Cell<usize>,
+    // We have exclusive access to the buffer for as long as we live.
+    _buf: PhantomData<&'a mut [u8]>,
 }
 
+// The raw pointer makes us `!Send`, but we own the buffer through a `&'a mut`,
+// which is `Send`. `Cell` still keeps us `!Sync`.
+unsafe impl <'a> Send for LinearAlloc<'a> {}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Marker(usize);
 
@@ -94,33 +115,39 @@ type LinearAllocResult<T> = result::Result<T, LinearAllocError>;
 impl <'a> LinearAlloc<'a> {
 
     /// Create a new linear allocator with a backing buffer.
-    pub fn new(buf: &mut [u8]) -> LinearAlloc {
+    pub fn new(buf: &'a mut [u8]) -> LinearAlloc<'a> {
         LinearAlloc {
-            buf,
-            top:  0,
-            high: 0,
+            len:  buf.len(),
+            buf:  NonNull::from(buf).cast(),
+            top:  Cell::new(0),
+            high: Cell::new(0),
+            _buf: PhantomData,
         }
     }
 
     /// Resets the stack completely.
     ///
+    /// # Safety
+    ///
     /// This is unsafe because it marks all memory from this allocator as "free",
     /// even if there are still objects using this memory.
     /// It is the responsibility of the caller to ensure that this doesn't happen.
     pub unsafe fn reset(&mut self) {
-        self.top = 0;
+        self.top.set(0);
     }
 
     /// Resets the stack to a specified location.
     ///
+    /// # Safety
+    ///
     /// This is unsafe because it marks all memory from this allocator as "free",
     /// even if there are still objects using this memory.
     /// It is the responsibility of the caller to ensure that this doesn't happen.
     pub unsafe fn reset_to(&mut self, marker: Marker) -> LinearAllocResult<()> {
-        if marker.0 < self.buf.len() &&
-           marker.0 < self.top           // Don't reset "up".
+        if marker.0 < self.len &&
+           marker.0 < self.top.get()     // Don't reset "up".
         {
-            self.top = marker.0;
+            self.top.set(marker.0);
             Ok(())
         } else {
             Err(LinearAllocError::InvalidMarker)
@@ -129,19 +156,19 @@ impl <'a> LinearAlloc<'a> {
 
     /// Gets a marker that the stack can be reset to later.
     pub fn get_marker(&self) -> Marker {
-        Marker(self.top)
+        Marker(self.top.get())
     }
 
     /// Gets the number of bytes currently allocated.
     pub fn bytes_in_use(&self) -> usize {
-        self.top
+        self.top.get()
     }
 
     /// Gets the length of the backing buffer.
     ///
     /// This is the largest number that `bytes_in_use` can ever return.
     pub fn capacity(&self) -> usize {
-        self.buf.len()
+        self.len
     }
 
     /// Gets the "high water mark" of bytes that have been in use by this
@@ -149,7 +176,7 @@ impl <'a> LinearAlloc<'a> {
     ///
     /// This is not reset with calls to `reset()` or `reset_to()`.
     pub fn high_water_mark(&self) -> usize {
-        self.high
+        self.high.get()
     }
 
     /// Gets immutable access to the underlaying buffer.
@@ -157,12 +184,15 @@ impl <'a> LinearAlloc<'a> {
     /// This can be used to peek at the buffer even with the allocator in use,
     /// since construction of the allocator involves a mutable borrow that lives
     /// as long as the allocator does.
-    pub fn buf(&self) -> &[u32] {
-        unsafe {
-            use std::slice;
-            slice::from_raw_parts(self.buf.as_ptr() as *const u32,
-                                  self.buf.len() / 4)
-        }
+    ///
+    /// # Safety
+    ///
+    /// Blocks handed out by this allocator may be written through their owners
+    /// at any time. The caller must ensure that no allocated memory is written,
+    /// and that no `&mut` into allocated memory is live, for as long as the
+    /// returned slice is in use.
+    pub unsafe fn buf(&self) -> &[u8] {
+        slice::from_raw_parts(self.buf.as_ptr(), self.len)
     }
 
     // Gets the index into self.buf at which the given pointer begins.
@@ -172,153 +202,207 @@ impl <'a> LinearAlloc<'a> {
 
 }
 
-unsafe impl <'a> alloc::Alloc for LinearAlloc<'a> {
-
-    fn usable_size(&self, layout: &alloc::Layout) -> (usize, usize) {
-        // Our allocations are tight, and do not include any excess.
-        // This also sets the guarantees for `layout.size()` in other calls.
-        // The caller is responsible for giving us a correct size.
-        // This lets us walk back from the top of the stack and free allocations
-        // if they are on top when `dealloc` is called, without saving metadata.
-        (layout.size(), layout.size())
+impl <'a> alloc_api::AvailableBytes for LinearAlloc<'a> {
+    // Everything above the top of the stack is free. A block on top of the
+    // stack can grow in place to use all of it.
+    fn available_bytes(&self) -> usize {
+        self.len - self.top.get()
     }
+}
 
+unsafe impl <'a> alloc_api::Allocator for LinearAlloc<'a> {
+
+    // Our allocations are tight, and do not include any excess.
+    // The returned slice is always exactly `layout.size()` bytes long, which
+    // sets the guarantees for `layout.size()` in other calls.
+    // This lets us walk back from the top of the stack and free allocations
+    // if they are on top when `deallocate` is called, without saving metadata.
+    //
     // This function is carefully written! Be careful when making changes.
     // It has no direct panic calls in release builds.
-    unsafe fn alloc(&mut self, layout: alloc::Layout)
-        -> result::Result<NonNull<u8>, alloc::AllocErr>
+    fn allocate(&self, layout: alloc_api::Layout)
+        -> result::Result<NonNull<[u8]>, alloc_api::AllocError>
     {
         // Layout invariants
         debug_assert!(layout.align() != 0);
         debug_assert!(layout.align().is_power_of_two());
 
-        if self.top >= self.buf.len() {
-            return Err(alloc::AllocErr);
+        let top = self.top.get();
+        if top >= self.len {
+            return Err(alloc_api::AllocError);
         }
 
-        // block_base is the usize pointer where our new block starts.
+        // block_base is the usize address where our new block would start.
         // It needs to be computed from buf and top, but also adjusted
         // (as a pointer) for correct alignment.
-        let mut block_base: usize;
-        block_base    = self.buf.as_ptr() as usize + self.top;
-        // Alignment is tricky and this may not be correct!
-        let align_fix = block_base & (layout.align() - 1);
-        block_base   += align_fix;
-
-        // block_idx is the index into our backing buf where this block starts.
-        let block_idx      = self.top + align_fix;
+        let block_base = self.buf.as_ptr() as usize + top;
+        // align_fix is the padding needed to round block_base *up* to the
+        // next multiple of the alignment. It is zero if block_base is already
+        // aligned, and otherwise `align - (block_base % align)`.
+        // Since `align` is a power of two, this is the low bits of the
+        // negated address.
+        let align_fix  = block_base.wrapping_neg() & (layout.align() - 1);
+
+        // block_idx is the overflow-proof index into our backing buf where
+        // this block starts.
+        let chked_block_idx = top.checked_add(align_fix);
         // chked_new_top is the overflow-proof end of the newly created block.
-        let chked_new_top  = block_idx.checked_add(layout.size());
-        match (block_idx, chked_new_top) {
-            (index, Some(new_top))
+        let chked_new_top   = chked_block_idx.and_then(|block_idx| {
+            block_idx.checked_add(layout.size())
+        });
+        match (chked_block_idx, chked_new_top) {
+            (Some(index), Some(new_top))
                 // Verify that the block still *starts* in bounds.
                 // Alignment may have adjusted the block out of our range,
                 // in which case we cannot handle this request.
-                if (index < self.buf.len() &&
+                if index < self.len &&
                 // Verify that the block still *ends* in bounds.
                 // It is OK for new_top to be exactly the same as the buffer length.
                 // This is expected when filling the allocator perfectly.
-                    new_top <= self.buf.len()) =>
+                    new_top <= self.len =>
             {
-                self.top  = new_top;
-                self.high = self.high.max(self.top);
-                Ok(NonNull::new_unchecked(block_base as *mut u8))
+                self.top.set(new_top);
+                self.high.set(self.high.get().max(new_top));
+                // Blocks must be derived from self.buf, so that they may be
+                // written to.
+                let block = unsafe {
+                    NonNull::new_unchecked(self.buf.as_ptr().add(index))
+                };
+                Ok(NonNull::slice_from_raw_parts(block, layout.size()))
             },
             _ => {
                 // We do not have enough space to satisfy this allocation.
-                Err(alloc::AllocErr)
+                Err(alloc_api::AllocError)
             },
         }
     }
 
-    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: alloc::Layout) {
-        // Because we return tight bounds for calls to `usable_size()`,
-        // we can assume that this `layout` struct is exactly the size of our
-        // block.
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: alloc_api::Layout) {
+        // Because we return tight bounds from `allocate()`, we can assume that
+        // this `layout` struct is exactly the size of our block.
 
         // If our block is at the top of the stack, we can free it.
         let block_idx = self.get_block_idx(ptr);
-        if block_idx + layout.size() == self.top {
-            self.top = block_idx;
+        if block_idx + layout.size() == self.top.get() {
+            self.top.set(block_idx);
         }
         // Anything else... and we can't.
     }
 
-    unsafe fn grow_in_place(&mut self,
-                            ptr:      NonNull<u8>,
-                            layout:   alloc::Layout,
-                            new_size: usize)
-        -> result::Result<(), alloc::CannotReallocInPlace>
+    unsafe fn grow(&self,
+                   ptr:        NonNull<u8>,
+                   old_layout: alloc_api::Layout,
+                   new_layout: alloc_api::Layout)
+        -> result::Result<NonNull<[u8]>, alloc_api::AllocError>
     {
         let block_idx  = self.get_block_idx(ptr);
-        let block_size;
+        let top        = self.top.get();
 
         // We assert on these to catch errors quickly, but we do not guard
         // against them because they are *caller* errors.
 
-        // The spec for `Alloc::grow_in_place` guarantees:
+        // The spec for `Allocator::grow` guarantees:
         //    1) ptr must be currently allocated via this allocator,
-        assert!(block_idx < self.buf.len(),
+        assert!(block_idx < self.len,
                 "Pointer is not from this allocator.");
-        assert!(block_idx < self.top,
+        assert!(block_idx < top,
                 "Pointer has already been freed, or is invalid.");
         // This is guaranteed not to underflow now.
-        block_size = self.top - block_idx;
-        //    2) layout must fit the ptr;
-        //       note the new_size argument need not fit it
-        assert!(ptr.as_ptr() as usize % layout.align() == 0,
+        let block_size = top - block_idx;
+        //    2) old_layout must fit the ptr,
+        assert!((ptr.as_ptr() as usize).is_multiple_of(old_layout.align()),
                    "Pointer does not fit layout.");
-        assert!(self.usable_size(&layout).0 <= block_size,
+        assert!(old_layout.size() <= block_size,
                 "The blocks size is too small?");
-        //    3) new_size must not be greater than layout.size()
-        //       (and must be greater than zero),
-        assert!(new_size >= layout.size(),
+        //    3) new_layout.size() must not be smaller than old_layout.size().
+        assert!(new_layout.size() >= old_layout.size(),
                 "Attempting to \"grow\" an allocation smaller.");
-        assert!(new_size != 0,
-                "Attempting to \"grow\" an allocation to zero size.");
-
-        let space_left = self.capacity() - self.bytes_in_use();
-        // We need at this much additional room in order to grow in place.
-        let block_growth = new_size - layout.size();
-        if space_left < block_growth {
-            return Err(alloc::CannotReallocInPlace);
-        }
 
-        // This wasn't the last block allocated, so we can't grow it in place.
+        // We can only grow in place when
+        //      1) self.buf has enough room,
+        //      2) the block in question is at the top of the stack, and
+        //      3) the block already satisfies the new alignment.
         // Note: This test does not account for padding due to the alignment of
         //       a previous allocation that has since been freed.
-        if block_idx + layout.size() != self.top {
-            return Err(alloc::CannotReallocInPlace);
+        let space_left   = self.capacity() - self.bytes_in_use();
+        let block_growth = new_layout.size() - old_layout.size();
+        if space_left >= block_growth &&
+           block_idx + old_layout.size() == top &&
+           (ptr.as_ptr() as usize).is_multiple_of(new_layout.align())
+        {
+            // So we can go ahead and bump self.top and call it success.
+            self.top.set(top + block_growth);
+            self.high.set(self.high.get().max(self.top.get()));
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
         }
 
-        // Now we know that
-        //      1) self.buf has enough room, and
-        //      2) The block in question is at the top of the stack
-        // So we can go ahead and bump self.top and call it success.
-        self.top += block_growth;
-        Ok(())
+        // Otherwise, get a new block, move our data, and free the old one.
+        let new_block = self.allocate(new_layout)?;
+        ptr::copy_nonoverlapping(ptr.as_ptr(),
+                                 new_block.cast::<u8>().as_ptr(),
+                                 old_layout.size());
+        self.deallocate(ptr, old_layout);
+        Ok(new_block)
     }
 
-    // ----- These may be useful to implement later. ----------------------------
-
-    unsafe fn shrink_in_place(&mut self,
-                              _ptr:      NonNull<u8>,
-                              _layout:   alloc::Layout,
-                              _new_size: usize)
-        -> result::Result<(), alloc::CannotReallocInPlace>
+    unsafe fn shrink(&self,
+                     ptr:        NonNull<u8>,
+                     old_layout: alloc_api::Layout,
+                     new_layout: alloc_api::Layout)
+        -> result::Result<NonNull<[u8]>, alloc_api::AllocError>
     {
-        Err(alloc::CannotReallocInPlace)
+        let block_idx  = self.get_block_idx(ptr);
+        let top        = self.top.get();
+
+        // We assert on these to catch errors quickly, but we do not guard
+        // against them because they are *caller* errors.
+
+        // The spec for `Allocator::shrink` guarantees:
+        //    1) ptr must be currently allocated via this allocator,
+        assert!(block_idx < self.len,
+                "Pointer is not from this allocator.");
+        assert!(block_idx + old_layout.size() <= top,
+                "Pointer has already been freed, or is invalid.");
+        //    2) old_layout must fit the ptr,
+        assert!((ptr.as_ptr() as usize).is_multiple_of(old_layout.align()),
+                   "Pointer does not fit layout.");
+        //    3) new_layout.size() must not be larger than old_layout.size().
+        assert!(new_layout.size() <= old_layout.size(),
+                "Attempting to \"shrink\" an allocation larger.");
+
+        // A block which doesn't satisfy the new alignment has to move.
+        if !(ptr.as_ptr() as usize).is_multiple_of(new_layout.align()) {
+            let new_block = self.allocate(new_layout)?;
+            ptr::copy_nonoverlapping(ptr.as_ptr(),
+                                     new_block.cast::<u8>().as_ptr(),
+                                     new_layout.size());
+            self.deallocate(ptr, old_layout);
+            return Ok(new_block);
+        }
+
+        // The block on top of the stack can hand its tail back to us.
+        if block_idx + old_layout.size() == top {
+            self.top.set(block_idx + new_layout.size());
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_layout.size()));
+        }
+
+        // Anything else keeps its footprint, and we say so by returning the
+        // whole block. Callers that deallocate with this size let us free the
+        // block once it is back on top of the stack.
+        Ok(NonNull::slice_from_raw_parts(ptr, old_layout.size()))
     }
 
 }
 
 #[cfg(test)]
 mod t {
-
     use super::*;
+    use alloc_api::Allocator;
+    use test_support::prop_config;
+    use proptest::prelude::*;
     use std::{
-        alloc::Alloc,
         mem,
+        vec,
     };
 
     // Used to tag whether something should be Ok(..) or Err(..), but without
@@ -342,7 +426,9 @@ mod t {
 
     #[test]
     fn check_simple_alloc() {
-        let mut buf = [0u8; 2 * mem::size_of::<u32>()];
+        // Force the allocator to start on a 4-byte aligned boundary.
+        #[repr(align(4))] struct Buffer { buf: [u8; 2 * mem::size_of::<u32>()] }
+        let mut buf = Buffer { buf: [0u8; 2 * mem::size_of::<u32>()] };
 
         // The pointers we expect to be valid are saved here, and used at the
         // end of the function.
@@ -353,22 +439,21 @@ mod t {
         // alloc &muts buf, and we need to read buf to check the tests.
         // Some day, this can just use a mem::forget() call instead of scoping.
         {
-            let mut alloc = LinearAlloc::new(&mut buf);
+            let alloc = LinearAlloc::new(&mut buf.buf);
 
-            let layout = alloc::Layout::new::<u32>();
+            let layout = alloc_api::Layout::new::<u32>();
             // This *should* be knowable at compile time, but Rust isn't there yet.
             assert_eq!(2 * layout.size(), alloc.capacity());
 
             // We expect two allocations to work, and then two to fail.
             // Failure should *not* abort the test!
-            // unsafe due to calls to alloc::Alloc::alloc().
-            unsafe {
+            {
                 let allocs = [
-                    alloc.alloc(layout),
-                    alloc.alloc(layout),
+                    alloc.allocate(layout),
+                    alloc.allocate(layout),
 
-                    alloc.alloc(layout),
-                    alloc.alloc(layout),
+                    alloc.allocate(layout),
+                    alloc.allocate(layout),
                 ];
 
                 let expected_tags = [
@@ -388,8 +473,8 @@ mod t {
                 assert_eq!(actual_tags, expected_tags);
 
                 ptrs = [
-                    allocs[0].clone().unwrap(),
-                    allocs[1].clone().unwrap(),
+                    allocs[0].unwrap().cast(),
+                    allocs[1].unwrap().cast(),
                 ];
             }
 
@@ -398,43 +483,255 @@ mod t {
         }
 
         // Unsafe due to dereferencing pointers.
+        // We stick to raw pointers, since we read `buf` in between writes.
         unsafe {
-            let a: &mut u32 = (ptrs[0].as_ptr() as *mut u32).as_mut().unwrap();
-            *a = 23;
-            assert_eq!(*a as u8, buf[0]);
-            *a = 45;
-            assert_eq!(*a as u8, buf[0]);
-
-            let b: &mut u32 = (ptrs[1].as_ptr() as *mut u32).as_mut().unwrap();
-            *b = 23;
-            assert_eq!(*b as u8, buf[4]);
-            *b = 45;
-            assert_eq!(*b as u8, buf[4]);
+            let a = ptrs[0].as_ptr() as *mut u32;
+            a.write(23);
+            assert_eq!(a.read() as u8, buf.buf[0]);
+            a.write(45);
+            assert_eq!(a.read() as u8, buf.buf[0]);
+
+            let b = ptrs[1].as_ptr() as *mut u32;
+            b.write(23);
+            assert_eq!(b.read() as u8, buf.buf[4]);
+            b.write(45);
+            assert_eq!(b.read() as u8, buf.buf[4]);
         }
     }
 
     #[test]
     fn check_in_place_realloc() {
         let mut buf = [0u8; 3*8];
-        let mut alloc = LinearAlloc::new(&mut buf);
+        let alloc = LinearAlloc::new(&mut buf);
 
-        // Unsafe because of calls to alloc
+        // Unsafe because of calls to grow and deallocate
         unsafe {
-            let layout     = alloc::Layout::new::<[u8; 8]>();
-            let p_first  = alloc.alloc(layout).expect("Couldn't alloc [0, 8]");
-            let p_second = alloc.alloc(layout).expect("Couldn't alloc [8, 16]");
-
-            let new_layout = alloc::Layout::new::<[u8; 16]>();
-            alloc.grow_in_place(p_second, layout, 16)
+            let layout     = alloc_api::Layout::new::<[u8; 8]>();
+            let p_first  = alloc.allocate(layout)
+                                .expect("Couldn't alloc [0, 8]").cast();
+            let p_second = alloc.allocate(layout)
+                                .expect("Couldn't alloc [8, 16]").cast();
+
+            let new_layout = alloc_api::Layout::new::<[u8; 16]>();
+            let p_grown = alloc.grow(p_second, layout, new_layout)
                 .expect("Couldn't grow in place from [8, 16] to [8, 24]");
-            alloc.dealloc(p_second, new_layout);
+            assert_eq!(p_grown.cast(), p_second);
+            alloc.deallocate(p_second, new_layout);
 
-            alloc.grow_in_place(p_first, layout, 16)
+            let p_grown = alloc.grow(p_first, layout, new_layout)
                 .expect("Couldn't grow in place from [0, 8] to [0, 16]");
-            alloc.dealloc(p_first, new_layout);
+            assert_eq!(p_grown.cast(), p_first);
+            alloc.deallocate(p_first, new_layout);
+        }
+
+        assert_eq!(alloc.bytes_in_use(), 0);
+    }
+
+    #[test]
+    fn check_send() {
+        // Only compiles if an arena can be moved to another thread.
+        fn assert_send<T: Send>() {}
+        assert_send::<LinearAlloc>();
+    }
+
+    #[test]
+    fn check_shrink_top_block() {
+        let mut buf = [0u8; 3*8];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // Unsafe because of calls to shrink and deallocate
+        unsafe {
+            let layout     = alloc_api::Layout::new::<[u8; 16]>();
+            let new_layout = alloc_api::Layout::new::<[u8; 4]>();
+            let ptr = alloc.allocate(layout)
+                           .expect("Couldn't alloc [0, 16]").cast();
+
+            let block = alloc.shrink(ptr, layout, new_layout)
+                .expect("Couldn't shrink from [0, 16] to [0, 4]");
+            assert_eq!(block.cast(), ptr);
+            assert_eq!(block.len(), 4);
+            assert_eq!(alloc.bytes_in_use(), 4);
+            assert_eq!(alloc.high_water_mark(), 16);
+
+            alloc.deallocate(ptr, new_layout);
+        }
+
+        assert_eq!(alloc.bytes_in_use(), 0);
+    }
+
+    #[test]
+    fn check_shrink_buried_block() {
+        let mut buf = [0u8; 3*8];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // Unsafe because of calls to shrink and deallocate
+        unsafe {
+            let layout     = alloc_api::Layout::new::<[u8; 8]>();
+            let new_layout = alloc_api::Layout::new::<[u8; 2]>();
+            let p_first  = alloc.allocate(layout)
+                                .expect("Couldn't alloc [0, 8]").cast();
+            let p_second = alloc.allocate(layout)
+                                .expect("Couldn't alloc [8, 16]").cast();
+
+            // The first block isn't on top, so it keeps its footprint.
+            let block = alloc.shrink(p_first, layout, new_layout)
+                .expect("Couldn't shrink [0, 8] as a no-op");
+            assert_eq!(block.cast(), p_first);
+            assert_eq!(block.len(), 8);
+            assert_eq!(alloc.bytes_in_use(), 16);
+
+            // Once it is back on top, it can be freed with the size we got.
+            alloc.deallocate(p_second, layout);
+            let kept = alloc_api::Layout::from_size_align(block.len(), 1)
+                                         .unwrap();
+            alloc.deallocate(p_first, kept);
         }
 
         assert_eq!(alloc.bytes_in_use(), 0);
     }
 
+    // ----- Property tests -----------------------------------------------------
+
+    // Room for every block in a sequence, plus slack to misalign the buffer.
+    const PROP_BUF_SIZE:  usize = 64 * 1024;
+    const PROP_BUF_SLACK: usize = 64;
+
+    // Sizes up to a few hundred bytes, and alignments up to a 4 KiB page.
+    fn arb_layout() -> impl Strategy<Value = alloc_api::Layout> {
+        (0usize..512, 0u32..13).prop_map(|(size, align_shift)| {
+            alloc_api::Layout::from_size_align(size, 1 << align_shift).unwrap()
+        })
+    }
+
+    // Allocate a block, or free or shrink the most recent one that is live.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Alloc(alloc_api::Layout),
+        FreeLatest,
+        // Shrink to this percentage of the current size.
+        ShrinkLatest(usize),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            3 => arb_layout().prop_map(Op::Alloc),
+            1 => Just(Op::FreeLatest),
+            1 => (0usize..=100).prop_map(Op::ShrinkLatest),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_blocks_are_aligned_and_in_bounds(
+            offset  in 0..PROP_BUF_SLACK,
+            layouts in proptest::collection::vec(arb_layout(), 0..64))
+        {
+            let mut backing = vec![0u8; PROP_BUF_SIZE + PROP_BUF_SLACK];
+            let buf   = &mut backing[offset..];
+            let begin = buf.as_ptr() as usize;
+            let end   = begin + buf.len();
+            let alloc = LinearAlloc::new(buf);
+
+            let mut prev_end = begin;
+            for layout in layouts {
+                let block = match alloc.allocate(layout) {
+                    Ok(block) => block,
+                    // Running out of room is fine, handing out bad blocks isn't.
+                    Err(_)    => continue,
+                };
+                let start = block.cast::<u8>().as_ptr() as usize;
+
+                prop_assert_eq!(block.len(), layout.size());
+                prop_assert_eq!(start % layout.align(), 0);
+                // Blocks never overlap, and never leave the buffer.
+                prop_assert!(start >= prev_end);
+                prop_assert!(start + layout.size() <= end);
+                prop_assert_eq!(alloc.bytes_in_use(), start + layout.size() - begin);
+
+                // Touch every byte, so that Miri can check the block is usable.
+                unsafe {
+                    ptr::write_bytes(block.cast::<u8>().as_ptr(), 0xAB, layout.size());
+                }
+                prev_end = start + layout.size();
+            }
+
+            prop_assert!(alloc.high_water_mark() <= alloc.capacity());
+        }
+
+        #[test]
+        fn prop_free_and_shrink_keep_accounting(
+            offset in 0..PROP_BUF_SLACK,
+            ops    in proptest::collection::vec(arb_op(), 0..64))
+        {
+            let mut backing = vec![0u8; PROP_BUF_SIZE + PROP_BUF_SLACK];
+            let buf   = &mut backing[offset..];
+            let begin = buf.as_ptr() as usize;
+            let alloc = LinearAlloc::new(buf);
+
+            // Live blocks, in the order they were allocated.
+            let mut live = vec::Vec::new();
+            for op in ops {
+                match op {
+                    Op::Alloc(layout) => {
+                        if let Ok(block) = alloc.allocate(layout) {
+                            let start = block.cast::<u8>().as_ptr() as usize;
+                            prop_assert_eq!(start % layout.align(), 0);
+                            live.push((block.cast::<u8>(), layout));
+                        }
+                    },
+                    Op::FreeLatest => {
+                        if let Some((ptr, layout)) = live.pop() {
+                            let start = ptr.as_ptr() as usize - begin;
+                            let top   = alloc.bytes_in_use();
+                            unsafe {
+                                alloc.deallocate(ptr, layout);
+                            }
+                            // Only a block on top of the stack is reclaimed,
+                            // and padding in front of it stays in use.
+                            // Padding can keep earlier blocks off the top.
+                            if start + layout.size() == top {
+                                prop_assert_eq!(alloc.bytes_in_use(), start);
+                            } else {
+                                prop_assert_eq!(alloc.bytes_in_use(), top);
+                            }
+                        }
+                    },
+                    Op::ShrinkLatest(percent) => {
+                        if let Some((ptr, layout)) = live.pop() {
+                            let start = ptr.as_ptr() as usize - begin;
+                            let top   = alloc.bytes_in_use();
+                            let new_layout = alloc_api::Layout::from_size_align(
+                                layout.size() * percent / 100,
+                                layout.align()).unwrap();
+                            let block = unsafe {
+                                alloc.shrink(ptr, layout, new_layout)
+                                     .expect("shrink failed.")
+                            };
+                            prop_assert_eq!(block.cast::<u8>(), ptr);
+
+                            // The top block gives its tail back, while buried
+                            // blocks report that they kept their footprint.
+                            if start + layout.size() == top {
+                                prop_assert_eq!(block.len(), new_layout.size());
+                                prop_assert_eq!(alloc.bytes_in_use(),
+                                                start + new_layout.size());
+                            } else {
+                                prop_assert_eq!(block.len(), layout.size());
+                                prop_assert_eq!(alloc.bytes_in_use(), top);
+                            }
+
+                            // Deallocation goes by the size we were handed.
+                            let kept = alloc_api::Layout::from_size_align(
+                                block.len(),
+                                layout.align()).unwrap();
+                            live.push((ptr, kept));
+                        }
+                    },
+                }
+            }
+        }
+    }
+
 }
@@ -0,0 +1,165 @@
+//! Macros for building collections in a given allocator, in the style of
+//! `std`'s `vec!` and `format!`.
+
+/// Creates a `vec2::Vec` using the allocator handle given before the `;`.
+///
+/// Like `vec!`, this takes either a list of elements, or an element and a
+/// count to clone it into. It evaluates to a `Result`, which holds the crate
+/// `Error` if allocation fails.
+///
+/// # Examples
+/// ```rust
+/// # #[macro_use] extern crate alloc_utils;
+/// # use alloc_utils::linear_alloc::LinearAlloc;
+/// # fn main() {
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let v = vec_in!(&alloc; 1u32, 2, 3).unwrap();
+/// assert_eq!(v, [1, 2, 3]);
+///
+/// let w = vec_in!(&alloc; 0u8; 4).unwrap();
+/// assert_eq!(w, [0, 0, 0, 0]);
+///
+/// assert!(vec_in!(&alloc; 0u64; 100).is_err());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! vec_in {
+    ($alloc:expr; $elem:expr; $n:expr) => {{
+        let mut v = $crate::vec2::Vec::new($alloc);
+        v.resize($n, $elem).map(|()| v)
+    }};
+    ($alloc:expr; $($x:expr),* $(,)?) => {
+        $crate::vec2::Vec::try_from_iter_in([$($x),*], $alloc)
+    };
+}
+
+/// Creates a `vec2::Vec` using the system allocator.
+///
+/// This is `vec_in!` with `alloc_api::System` as the allocator.
+///
+/// # Examples
+/// ```rust
+/// # #[macro_use] extern crate alloc_utils;
+/// # fn main() {
+/// let v = system_vec![1, 2, 3].unwrap();
+/// assert_eq!(v, [1, 2, 3]);
+///
+/// let w = system_vec!['x'; 2].unwrap();
+/// assert_eq!(w, ['x', 'x']);
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! system_vec {
+    ($elem:expr; $n:expr) => {
+        $crate::vec_in!($crate::alloc_api::System; $elem; $n)
+    };
+    ($($x:expr),* $(,)?) => {
+        $crate::vec_in!($crate::alloc_api::System; $($x),*)
+    };
+}
+
+/// Formats text into a `string::String`, using the allocator handle given as
+/// the first argument.
+///
+/// The rest of the arguments are as for `format!`. It evaluates to a `Result`,
+/// which holds the crate `Error` if allocation fails.
+///
+/// # Examples
+/// ```rust
+/// # #[macro_use] extern crate alloc_utils;
+/// # use alloc_utils::linear_alloc::LinearAlloc;
+/// # fn main() {
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let s = format_in!(&alloc, "{}-{:03}", "id", 7).unwrap();
+/// assert_eq!(s, "id-007");
+///
+/// assert!(format_in!(&alloc, "{:100}", 0).is_err());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! format_in {
+    ($alloc:expr, $($arg:tt)*) => {{
+        let mut s = $crate::string::String::new($alloc);
+        s.try_write_fmt(format_args!($($arg)*)).map(|()| s)
+    }};
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use linear_alloc::LinearAlloc;
+    use vec2::Vec;
+    use Error;
+
+    #[test]
+    fn check_vec_in_lists() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let v = vec_in!(&alloc; 1u32, 2, 3,).expect("vec_in!(..) failed.");
+        assert_eq!(v, [1, 2, 3]);
+        assert_eq!(v.capacity(), 3);
+
+        let w: Vec<u8, _> = vec_in!(&alloc;).expect("vec_in!(..) failed.");
+        assert!(w.is_empty());
+        assert_eq!(w.capacity(), 0);
+    }
+
+    #[test]
+    fn check_vec_in_repeat() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let v = vec_in!(&alloc; [1u8, 2]; 3).expect("vec_in!(..) failed.");
+        assert_eq!(v, [[1, 2], [1, 2], [1, 2]]);
+
+        let w = vec_in!(&alloc; 7u32; 0).expect("vec_in!(..) failed.");
+        assert!(w.is_empty());
+    }
+
+    #[test]
+    fn check_vec_in_grows_on_insert() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // The list form reserves exactly enough, so the first insert grows.
+        let mut v = vec_in!(&alloc; 2u64, 4, 6).expect("vec_in!(..) failed.");
+        assert_eq!(v.capacity(), 3);
+        v.insert(1, 3).expect("v.insert(..) failed.");
+        assert!(v.capacity() > 3);
+        assert_eq!(v, [2, 3, 4, 6]);
+        assert_eq!(v.remove(0), 2);
+        assert_eq!(v, [3, 4, 6]);
+    }
+
+    #[test]
+    fn check_vec_in_reports_errors() {
+        let mut buf = [0u8; 16];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        match vec_in!(&alloc; 0u32; 100) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        let res = vec_in!(&alloc; 1u64, 2, 3, 4);
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_system_vec() {
+        let v = system_vec![1, 2, 3].expect("system_vec![..] failed.");
+        assert_eq!(v, [1, 2, 3]);
+        let w = system_vec![0u8; 5].expect("system_vec![..] failed.");
+        assert_eq!(w, [0; 5]);
+    }
+}
@@ -1,52 +1,56 @@
-use std::{
-    alloc,
+use core::{
+    marker::PhantomData,
     mem,
-    ptr::NonNull,
+    ptr::{self, NonNull},
     result,
 };
 
+use alloc_api;
+use growth::{
+    Doubling,
+    GrowthPolicy,
+};
+use storage_vec::Storage;
 use Error;
 type VecResult<T> = result::Result<T, Error>;
 
-pub struct RawVec<'v, T: 'v> {
-    // We store a pointer to the allocator instead of a reference to get around
-    // mutability restrictions.
+pub struct RawVec<T, A, G = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    // The allocator handle is stored by value, so growth through a concrete
+    // allocator (like `&LinearAlloc`) monomorphizes and can be inlined.
     // We cannot have the Vec exercise unilateral control over the allocator,
     // as we expect
     //  (1) other collections to use the same allocator, and
     //  (2) callers to interact with the allocator while the Vec does too.
-    // We do still have lifetime guarantees, however.
-    alloc: NonNull<dyn alloc::Alloc + 'v>,
-    ptr:   NonNull<T>, // Pointer to Ts
-    cap:   usize,      // How many Ts we have space for.
+    // So this is usually a handle to an allocator that lives elsewhere.
+    // `DynAlloc` is available for callers who want one concrete type.
+    alloc:  A,
+    ptr:    NonNull<T>, // Pointer to Ts
+    cap:    usize,      // How many Ts we have space for. Unused for ZSTs.
+    growth: G,          // Picks the capacity to grow to.
+    _own:   PhantomData<T>,
 }
 
-impl <'v, T> RawVec<'v, T> {
-    /// Create a new buffer. Does not allocate.
-    pub fn new(alloc: &mut (dyn alloc::Alloc + 'v)) -> Self {
-        assert!(mem::size_of::<T>() != 0, "Zero Sized Types are not supported");
-        RawVec {
-            alloc: NonNull::new(alloc).unwrap(),
-            ptr:   NonNull::dangling(),
-            cap:   0,
-        }
+impl <T, A: alloc_api::Allocator> RawVec<T, A> {
+    /// Create a new buffer which doubles as it grows. Does not allocate.
+    ///
+    /// Zero sized types never allocate, and have a capacity of `usize::MAX`.
+    pub fn new(alloc: A) -> Self {
+        RawVec::with_growth(alloc, Doubling)
     }
 
-    /// Create a new buffer backed by the system allocator. Does not allocate.
-    pub fn with_system_alloc() -> Self {
-        let alloc = &mut alloc::System;
-        assert!(mem::size_of::<T>() != 0, "Zero Sized Types are not supported");
-        RawVec {
-            alloc: NonNull::new(alloc).unwrap(),
-            ptr:   NonNull::dangling(),
-            cap:   0,
-        }
+    /// Create and allocate a new buffer which doubles as it grows.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        RawVec::with_capacity_and_growth(alloc, capacity, Doubling)
     }
+}
 
-
-    /// Get the type erased Allocator that the Vec is using.
-    pub fn alloc(&mut self) -> &mut dyn alloc::Alloc {
-        unsafe { self.alloc.as_mut() }
+impl <T, A: alloc_api::Allocator, G: GrowthPolicy<A>> RawVec<T, A, G> {
+    /// Get the allocator handle that the buffer is using.
+    pub fn alloc(&self) -> &A {
+        &self.alloc
     }
 
     /// Get the pointer to the buffer.
@@ -56,98 +60,284 @@ impl <'v, T> RawVec<'v, T> {
 
     /// Get the number of Ts that the buffer has space for.
     pub fn capacity(&self) -> usize {
-        self.cap
+        if Self::is_zst() {
+            usize::MAX
+        } else {
+            self.cap
+        }
     }
 
-    /// Create and allocate a new buffer.
-    pub fn with_capacity(alloc: &mut (dyn alloc::Alloc + 'v),
-                         capacity: usize)
-        -> VecResult<Self>
-    {
-        assert!(mem::size_of::<T>() != 0, "Zero Sized Types are not supported");
-        let mut raw_vec = RawVec {
-            alloc: NonNull::new(alloc).unwrap(),
-            ptr:   NonNull::dangling(),
-            cap:   0,
-        };
-        raw_vec.reserve(capacity)?;
-        Ok(raw_vec)
+    // Zero sized types need no memory, so we never allocate for them.
+    fn is_zst() -> bool {
+        mem::size_of::<T>() == 0
+    }
+
+    /// Get the growth policy that the buffer is using.
+    pub fn growth(&self) -> &G {
+        &self.growth
     }
 
     /// Get the Layout for the current allocation. This is suitable to pass to
-    /// `alloc::Alloc` methods.
-    pub fn alloc_layout(&self) -> alloc::Layout {
-        // I'm not entirely sure how this could fail.
-        alloc::Layout::array::<T>(self.cap).unwrap()
+    /// `alloc_api::Allocator` methods.
+    pub fn alloc_layout(&self) -> alloc_api::Layout {
+        // `cap` only ever comes from a block the allocator handed us, so this
+        // is a size that already fit in a valid Layout.
+        alloc_api::Layout::array::<T>(self.cap).unwrap()
     }
 
-    /// Each call to `grow` *doubles* the size of the allocation, which is
-    /// initially space for a single T.
+    /// Create a new buffer which grows according to `growth`. Does not
+    /// allocate.
+    pub fn with_growth(alloc: A, growth: G) -> Self {
+        RawVec {
+            alloc,
+            ptr:    NonNull::dangling(),
+            cap:    0,
+            growth,
+            _own:   PhantomData,
+        }
+    }
+
+    /// Create a buffer from a block that `alloc` allocated, which grows
+    /// according to `growth`.
+    ///
+    /// # Safety
+    ///
+    /// Unless `T` is zero sized or `capacity` is zero, `ptr` must point to a
+    /// block allocated by `alloc`, which `Layout::array::<T>(capacity)` fits.
+    /// Otherwise, it must be non-null and aligned.
+    pub unsafe fn from_raw_parts_in(ptr:      *mut T,
+                                    capacity: usize,
+                                    alloc:    A,
+                                    growth:   G)
+        -> Self
+    {
+        RawVec {
+            alloc,
+            ptr:    NonNull::new_unchecked(ptr),
+            // Zero sized types never own a block.
+            cap:    if Self::is_zst() { 0 } else { capacity },
+            growth,
+            _own:   PhantomData,
+        }
+    }
+
+    /// Give up ownership of the block, returning its pointer, the number of
+    /// Ts it has space for, and the allocator handle. The growth policy is
+    /// dropped.
+    pub fn into_raw_parts(self) -> (*mut T, usize, A) {
+        let ptr = self.ptr();
+        let cap = self.capacity();
+        unsafe {
+            // We need to use ptr::read to move the fields out, since RawVec
+            // implements Drop (and so we can't destructure it)
+            let alloc  = ptr::read(&self.alloc);
+            let growth = ptr::read(&self.growth);
+            mem::forget(self);
+            mem::drop(growth);
+            (ptr, cap, alloc)
+        }
+    }
+
+    /// Create and allocate a new buffer which grows according to `growth`.
+    pub fn with_capacity_and_growth(alloc: A, capacity: usize, growth: G)
+        -> VecResult<Self>
+    {
+        let mut raw_vec = RawVec::with_growth(alloc, growth);
+        raw_vec.reserve_exact(0, capacity)?;
+        Ok(raw_vec)
+    }
+
+    /// Grow the allocation by at least one T, as picked by the growth policy.
     pub fn grow(&mut self) -> VecResult<()> {
-        if self.cap == 0 {
-            self.reserve(1)
+        if Self::is_zst() {
+            // We only need to grow once all usize::MAX spaces are full.
+            Err(Error::SizeOverflowErr)
         } else {
             let cap = self.capacity();
-            // Reserve an *additional* `cap` spaces
-            self.reserve(cap)
+            self.reserve(cap, 1)
         }
     }
 
-    /// Increase the reserved space to hold at least `additional` more `T`s.
-    pub fn reserve(&mut self, additional: usize) -> VecResult<()> {
-        let new_cap: usize;
-        let new_ptr: NonNull<T>;
-        let layout:  alloc::Layout;
+    /// Ensure there is space for at least `additional` more `T`s past the
+    /// first `len`.
+    ///
+    /// If the buffer has to grow, the growth policy picks the new capacity.
+    /// Should the allocator refuse that, we fall back to exactly what is
+    /// required.
+    pub fn reserve(&mut self, len: usize, additional: usize) -> VecResult<()> {
+        let required = len.checked_add(additional)
+                          .ok_or(Error::SizeOverflowErr)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+
+        let new_cap = self.growth
+                          .next_capacity(self.cap,
+                                         required,
+                                         mem::size_of::<T>(),
+                                         &self.alloc)
+                          .max(required);
+        match self.grow_to(new_cap) {
+            Err(_) if new_cap > required => self.grow_to(required),
+            res => res,
+        }
+    }
 
-        // This is unsafe because of our calls to `*alloc` methods.
-        // We'd use the `*alloc_array()` methods, but those aren't working
-        // on a Trait object. (Why?)
+    /// Ensure there is space for at least `additional` more `T`s past the
+    /// first `len`, without over-allocating.
+    ///
+    /// The allocator may still hand back a larger block, which is kept.
+    pub fn reserve_exact(&mut self, len: usize, additional: usize)
+        -> VecResult<()>
+    {
+        let required = len.checked_add(additional)
+                          .ok_or(Error::SizeOverflowErr)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+
+        self.grow_to(required)
+    }
+
+    /// Shrink the allocation to hold at least `min_cap` Ts, freeing it if
+    /// `min_cap` is zero.
+    ///
+    /// The allocator may keep the block larger than asked, in which case the
+    /// capacity reflects that.
+    pub fn shrink_to(&mut self, min_cap: usize) -> VecResult<()> {
+        if Self::is_zst() || min_cap >= self.cap {
+            return Ok(());
+        }
+
+        let layout = self.alloc_layout();
+        let ptr = self.ptr.cast();
+
+        if min_cap == 0 {
+            unsafe {
+                self.alloc().deallocate(ptr, layout);
+            }
+            self.cap = 0;
+            self.ptr = NonNull::dangling();
+            return Ok(());
+        }
+
+        let new_layout = alloc_api::Layout::array::<T>(min_cap)?;
+        let block = unsafe {
+            self.alloc().shrink(ptr, layout, new_layout)?
+        };
+
+        self.cap = block.len() / mem::size_of::<T>();
+        self.ptr = block.cast();
+
+        Ok(())
+    }
+
+    // Shrink the allocation to hold exactly `cap` Ts, which must be at most
+    // the capacity. If the allocator keeps the block larger, the first `cap`
+    // Ts move to a new block of the right size.
+    pub(crate) fn shrink_to_exact(&mut self, cap: usize) -> VecResult<()> {
+        self.shrink_to(cap)?;
+        if Self::is_zst() || self.cap == cap {
+            return Ok(());
+        }
+
+        let layout = self.alloc_layout();
+        let new_layout = alloc_api::Layout::array::<T>(cap)?;
+
+        // This is unsafe because of our calls to `Allocator` methods.
         unsafe {
-            // The first allocation is special - it goes through `Alloc::alloc`.
+            let block = self.alloc().allocate(new_layout)?;
+            ptr::copy_nonoverlapping(self.ptr.as_ptr(),
+                                     block.cast::<T>().as_ptr(), cap);
+            self.alloc().deallocate(self.ptr.cast(), layout);
+            self.ptr = block.cast();
+        }
+
+        // Freeing with the size we asked for is valid even if the block is
+        // larger, and it is the size a caller expects to free with.
+        self.cap = cap;
+
+        Ok(())
+    }
+
+    // Resize the allocation to hold at least `new_cap` Ts. Never called for
+    // zero sized types, which always have enough capacity.
+    fn grow_to(&mut self, new_cap: usize) -> VecResult<()> {
+        debug_assert!(!Self::is_zst() && new_cap > self.cap);
+
+        let new_layout = alloc_api::Layout::array::<T>(new_cap)?;
+
+        // This is unsafe because of our calls to `Allocator` methods.
+        let block = unsafe {
+            // The first allocation is special - it goes through
+            // `Allocator::allocate`.
             if self.cap == 0 {
-                new_cap = 1;
-                layout  = alloc::Layout::array::<T>(additional).unwrap();
-                new_ptr = self.alloc().alloc(layout)?.cast();
-            // Otherwise, it can go through `Alloc::realloc`
+                self.alloc().allocate(new_layout)?
+            // Otherwise, it can go through `Allocator::grow`, which will
+            // try to resize our block in place before getting a new block
+            // (and freeing the old one.)
             } else {
                 // This layout must refer to the *existing* allocation.
-                layout  = self.alloc_layout();
-                new_cap = self.cap
-                              .checked_add(additional)
-                              .ok_or(Error::SizeOverflowErr)?;
-                let new_layout = alloc::Layout::array::<T>(new_cap).unwrap();
-                let new_size = new_layout.size();
-
+                let layout = self.alloc_layout();
                 let ptr = self.ptr.cast();
-                if let Ok(()) = self.alloc().grow_in_place(ptr, layout, new_size)
-                {
-                    // We resized our block in place and don't need to update.
-                    // This is just here to make sure `new_ptr` is initialized.
-                    new_ptr = self.ptr;
-                } else {
-                    // We cannot grow in place, so get a new block
-                    // (and free the old one.)
-                    new_ptr = self.alloc()
-                                  .realloc(ptr, layout, new_size)?
-                                  .cast();
-                }
+                self.alloc().grow(ptr, layout, new_layout)?
             }
-        }
+        };
 
-        self.cap = new_cap;
-        self.ptr = new_ptr;
+        // The allocator may give us more room than we asked for, and any
+        // size between the two is valid to hand back when freeing. So use
+        // all of it.
+        self.cap = block.len() / mem::size_of::<T>();
+        self.ptr = block.cast();
 
         Ok(())
     }
 }
 
-impl <'v, T> Drop for RawVec<'v, T> {
+#[cfg(feature = "std")]
+impl <T> RawVec<T, alloc_api::System> {
+    /// Create a new buffer backed by the system allocator. Does not allocate.
+    pub fn with_system_alloc() -> Self {
+        RawVec::new(alloc_api::System)
+    }
+}
+
+// This is what makes `vec2::Vec` a `StorageVec`. The buffer grows by its
+// growth policy.
+unsafe impl <T, A, G> Storage<T> for RawVec<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn capacity(&self) -> usize {
+        RawVec::capacity(self)
+    }
+
+    fn as_ptr(&self) -> *const T {
+        self.ptr()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr()
+    }
+
+    fn try_reserve(&mut self, len: usize, additional: usize) -> VecResult<()> {
+        self.reserve(len, additional)
+    }
+
+    fn try_reserve_exact(&mut self, len: usize, additional: usize)
+        -> VecResult<()>
+    {
+        self.reserve_exact(len, additional)
+    }
+}
+
+impl <T, A: alloc_api::Allocator, G: GrowthPolicy<A>> Drop for RawVec<T, A, G> {
     fn drop(&mut self) {
         if self.cap != 0 {
             unsafe {
                 let layout = self.alloc_layout();
                 let ptr = self.ptr.cast();
-                self.alloc().dealloc(ptr, layout);
+                self.alloc().deallocate(ptr, layout);
             }
         }
     }
@@ -0,0 +1,608 @@
+//! Single-threaded reference-counted pointers, whose shared value lives in a
+//! customizable memory allocator. See `sync` for the thread-safe version.
+
+use core::{
+    borrow,
+    cell::Cell,
+    cmp,
+    convert,
+    fmt,
+    hash,
+    marker::PhantomData,
+    mem,
+    ops,
+    ptr::{self, NonNull},
+    result,
+};
+
+use alloc_api;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// The block shared by every Rc and Weak to a value.
+struct RcBox<T> {
+    strong: Cell<usize>, // Count of Rcs.
+    weak:   Cell<usize>, // Count of Weaks, plus one shared by all the Rcs.
+    value:  T,
+}
+
+// The counts of a block, borrowed without the value. A Weak must only use
+// these, since the value may already be dropped.
+struct WeakInner<'a> {
+    strong: &'a Cell<usize>,
+    weak:   &'a Cell<usize>,
+}
+
+// Allocates a block for an RcBox, without initializing it.
+fn allocate_box<T, A>(alloc: &A) -> VecResult<NonNull<RcBox<T>>>
+    where A: alloc_api::Allocator
+{
+    Ok(alloc.allocate(alloc_api::Layout::new::<RcBox<T>>())?.cast())
+}
+
+// Drops one weak reference to the block at `ptr`, freeing it if that was the
+// last.
+unsafe fn release_weak<T, A>(ptr: NonNull<RcBox<T>>, alloc: &A)
+    where A: alloc_api::Allocator
+{
+    let weak = &(*ptr.as_ptr()).weak;
+    weak.set(weak.get() - 1);
+    if weak.get() == 0 {
+        alloc.deallocate(ptr.cast(), alloc_api::Layout::new::<RcBox<T>>());
+    }
+}
+
+// ----- Rc Impl ----------------------------------------------------------------
+
+/// A single-threaded reference-counted pointer to a value that lives in a
+/// customizable memory allocator.
+///
+/// It differs from `std::rc::Rc` by storing its own allocator handle, like
+/// `boxed::Box` does. Every clone holds a clone of the handle, and the last
+/// one to go frees the value's block through it. Methods are associated
+/// functions, such as `Rc::strong_count(&rc)`, so that they don't shadow
+/// methods of `T`.
+///
+/// The counts and the value share a single block, which is kept until the
+/// last `Weak` is gone too.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{linear_alloc::LinearAlloc, rc::Rc};
+/// #
+/// let mut buf = [0u8; 128];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let a = Rc::try_new_in([1u32, 2, 3], &alloc).unwrap();
+/// let b = a.clone();
+/// assert_eq!(Rc::strong_count(&a), 2);
+/// assert_eq!(b[1], 2);
+///
+/// let weak = Rc::downgrade(&a);
+/// drop((a, b));
+/// assert!(weak.upgrade().is_none());
+/// ```
+pub struct Rc<T, A: alloc_api::Allocator> {
+    ptr:   NonNull<RcBox<T>>, // The shared block, from `alloc`.
+    alloc: A,
+    _own:  PhantomData<RcBox<T>>,
+}
+
+impl <T, A: alloc_api::Allocator> Rc<T, A> {
+    /// Moves `value` into a block from the allocator handle `alloc`,
+    /// returning any allocation errors.
+    pub fn try_new_in(value: T, alloc: A) -> VecResult<Self> {
+        let ptr = allocate_box(&alloc)?;
+        unsafe {
+            ptr.as_ptr().write(RcBox {
+                strong: Cell::new(1),
+                weak:   Cell::new(1),
+                value,
+            });
+        }
+        Ok(Rc {
+            ptr,
+            alloc,
+            _own:  PhantomData,
+        })
+    }
+
+    /// Returns the allocator handle that the Rc is using.
+    pub fn alloc(this: &Self) -> &A {
+        &this.alloc
+    }
+
+    /// Returns a pointer to the value, which stays valid while any Rc to it
+    /// is alive.
+    pub fn as_ptr(this: &Self) -> *const T {
+        unsafe {
+            ptr::addr_of!((*this.ptr.as_ptr()).value)
+        }
+    }
+
+    /// The number of Rcs to the value.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    /// The number of Weaks to the value.
+    pub fn weak_count(this: &Self) -> usize {
+        this.inner().weak.get() - 1
+    }
+
+    /// Returns `true` if both Rcs point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Creates a Weak to the value, which doesn't keep it alive.
+    pub fn downgrade(this: &Self) -> Weak<T, A>
+        where A: Clone
+    {
+        let weak = &this.inner().weak;
+        weak.set(weak.get().checked_add(1).expect("Rc weak count overflowed"));
+        Weak {
+            ptr:   this.ptr,
+            alloc: this.alloc.clone(),
+        }
+    }
+
+    /// Returns a mutable reference to the value, if this is the only Rc or
+    /// Weak to it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Rc::is_unique(this) {
+            unsafe {
+                Some(&mut (*this.ptr.as_ptr()).value)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the value, cloning it into a new block
+    /// first if other Rcs share it, and returning any allocation errors.
+    ///
+    /// If only Weaks share it, the value is moved into a new block instead,
+    /// and they can no longer upgrade. On allocation errors, the Rc is left
+    /// untouched.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, rc::Rc};
+    /// #
+    /// let mut buf = [0u8; 128];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut a = Rc::try_new_in(5u32, &alloc).unwrap();
+    /// let b = a.clone();
+    /// *Rc::make_mut(&mut a).unwrap() += 1;
+    /// assert_eq!((*a, *b), (6, 5));
+    /// ```
+    pub fn make_mut(this: &mut Self) -> VecResult<&mut T>
+        where T: Clone,
+              A: Clone
+    {
+        if this.inner().strong.get() != 1 {
+            *this = Rc::try_new_in((**this).clone(), this.alloc.clone())?;
+        } else if this.inner().weak.get() != 1 {
+            let fresh = allocate_box(&this.alloc)?;
+            unsafe {
+                let old = this.ptr;
+                fresh.as_ptr().write(RcBox {
+                    strong: Cell::new(1),
+                    weak:   Cell::new(1),
+                    value:  ptr::read(&(*old.as_ptr()).value),
+                });
+                // The value moved out, so the Weaks must not see it again.
+                (*old.as_ptr()).strong.set(0);
+                release_weak(old, &this.alloc);
+                this.ptr = fresh;
+            }
+        }
+        unsafe {
+            Ok(&mut (*this.ptr.as_ptr()).value)
+        }
+    }
+
+    /// Moves the value out, if this is the only Rc to it. Otherwise, the Rc
+    /// is handed back.
+    ///
+    /// Any Weaks can no longer upgrade.
+    pub fn try_unwrap(this: Self) -> result::Result<T, Self> {
+        if this.inner().strong.get() != 1 {
+            return Err(this);
+        }
+        unsafe {
+            let ptr = this.ptr;
+            // We need to use ptr::read to move the alloc out, since Rc
+            // implements Drop (and so we can't destructure it)
+            let alloc = ptr::read(&this.alloc);
+            mem::forget(this);
+
+            (*ptr.as_ptr()).strong.set(0);
+            let value = ptr::read(&(*ptr.as_ptr()).value);
+            release_weak(ptr, &alloc);
+            Ok(value)
+        }
+    }
+
+    fn inner(&self) -> &RcBox<T> {
+        unsafe {
+            self.ptr.as_ref()
+        }
+    }
+
+    fn is_unique(this: &Self) -> bool {
+        this.inner().strong.get() == 1 && this.inner().weak.get() == 1
+    }
+}
+
+// ----- Rc Traits --------------------------------------------------------------
+
+impl <T, A: alloc_api::Allocator> Drop for Rc<T, A> {
+    fn drop(&mut self) {
+        let strong = &self.inner().strong;
+        strong.set(strong.get() - 1);
+        if strong.get() != 0 {
+            return;
+        }
+        unsafe {
+            ptr::drop_in_place(ptr::addr_of_mut!((*self.ptr.as_ptr()).value));
+            // Drop the weak reference shared by the Rcs.
+            release_weak(self.ptr, &self.alloc);
+        }
+    }
+}
+
+impl <T, A> Clone for Rc<T, A>
+    where A: alloc_api::Allocator + Clone
+{
+    fn clone(&self) -> Self {
+        let strong = &self.inner().strong;
+        strong.set(strong.get().checked_add(1).expect("Rc strong count overflowed"));
+        Rc {
+            ptr:   self.ptr,
+            alloc: self.alloc.clone(),
+            _own:  PhantomData,
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> ops::Deref for Rc<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl <T, A> fmt::Debug for Rc<T, A>
+    where T: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl <T, A> fmt::Display for Rc<T, A>
+    where T: fmt::Display,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl <T, A, U, B> cmp::PartialEq<Rc<U, B>> for Rc<T, A>
+    where T: cmp::PartialEq<U>,
+          A: alloc_api::Allocator,
+          B: alloc_api::Allocator
+{
+    fn eq(&self, other: &Rc<U, B>) -> bool {
+        **self == **other
+    }
+}
+
+impl <T, A> cmp::Eq for Rc<T, A>
+    where T: cmp::Eq,
+          A: alloc_api::Allocator
+{}
+
+impl <T, A, U, B> cmp::PartialOrd<Rc<U, B>> for Rc<T, A>
+    where T: cmp::PartialOrd<U>,
+          A: alloc_api::Allocator,
+          B: alloc_api::Allocator
+{
+    fn partial_cmp(&self, other: &Rc<U, B>) -> Option<cmp::Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl <T, A> cmp::Ord for Rc<T, A>
+    where T: cmp::Ord,
+          A: alloc_api::Allocator
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl <T, A> hash::Hash for Rc<T, A>
+    where T: hash::Hash,
+          A: alloc_api::Allocator
+{
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl <T, A: alloc_api::Allocator> convert::AsRef<T> for Rc<T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl <T, A: alloc_api::Allocator> borrow::Borrow<T> for Rc<T, A> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+// ----- Weak & Traits ----------------------------------------------------------
+
+/// A reference to an `Rc`'s value that doesn't keep it alive. See
+/// `Rc::downgrade()`.
+///
+/// It keeps the value's block allocated, but not the value itself.
+pub struct Weak<T, A: alloc_api::Allocator> {
+    ptr:   NonNull<RcBox<T>>, // The shared block, from `alloc`.
+    alloc: A,
+}
+
+impl <T, A: alloc_api::Allocator> Weak<T, A> {
+    /// Returns an Rc to the value, or `None` if it has been dropped.
+    pub fn upgrade(&self) -> Option<Rc<T, A>>
+        where A: Clone
+    {
+        let strong = self.inner().strong;
+        if strong.get() == 0 {
+            return None;
+        }
+        strong.set(strong.get().checked_add(1).expect("Rc strong count overflowed"));
+        Some(Rc {
+            ptr:   self.ptr,
+            alloc: self.alloc.clone(),
+            _own:  PhantomData,
+        })
+    }
+
+    /// The number of Rcs to the value.
+    pub fn strong_count(&self) -> usize {
+        self.inner().strong.get()
+    }
+
+    /// The number of Weaks to the value, or 0 if it has been dropped.
+    pub fn weak_count(&self) -> usize {
+        let inner = self.inner();
+        if inner.strong.get() == 0 {
+            0
+        } else {
+            inner.weak.get() - 1
+        }
+    }
+
+    /// Returns `true` if both Weaks point to the same block.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+
+    // Only the counts are valid once the value has been dropped, so never
+    // borrow the whole block.
+    fn inner(&self) -> WeakInner<'_> {
+        let ptr = self.ptr.as_ptr();
+        unsafe {
+            WeakInner {
+                strong: &*ptr::addr_of!((*ptr).strong),
+                weak:   &*ptr::addr_of!((*ptr).weak),
+            }
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> Drop for Weak<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            release_weak(self.ptr, &self.alloc);
+        }
+    }
+}
+
+impl <T, A> Clone for Weak<T, A>
+    where A: alloc_api::Allocator + Clone
+{
+    fn clone(&self) -> Self {
+        let weak = self.inner().weak;
+        weak.set(weak.get().checked_add(1).expect("Rc weak count overflowed"));
+        Weak {
+            ptr:   self.ptr,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> fmt::Debug for Weak<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use test_support::DropCounter;
+
+    use std::{
+        cell::Cell,
+        mem,
+        string::String,
+    };
+
+    #[test]
+    fn check_counts() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let a = Rc::try_new_in(String::from("hi"), &alloc).expect("Rc::try_new_in(..) failed.");
+        let b = a.clone();
+        let w = Rc::downgrade(&b);
+        assert_eq!((Rc::strong_count(&a), Rc::weak_count(&a)), (2, 1));
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(Rc::as_ptr(&a), &*b as *const String);
+        assert_eq!(*a, "hi");
+        assert_eq!(format!("{:?} {}", a, b), "\"hi\" hi");
+
+        mem::drop(a);
+        assert_eq!((w.strong_count(), w.weak_count()), (1, 1));
+        let c = w.upgrade().expect("w.upgrade() failed.");
+        assert_eq!(c, b);
+        mem::drop((b, c));
+        assert!(w.upgrade().is_none());
+        assert_eq!((w.strong_count(), w.weak_count()), (0, 0));
+    }
+
+    #[test]
+    fn check_frees_block() {
+        // Aligned, so that no padding is left behind.
+        #[repr(align(16))] struct Buffer { buf: [u8; 128] }
+        let mut buf = Buffer { buf: [0u8; 128] };
+        let alloc = LinearAlloc::new(&mut buf.buf);
+        let in_use = alloc.bytes_in_use();
+
+        let a = Rc::try_new_in(7u64, &alloc).expect("Rc::try_new_in(..) failed.");
+        let w = Rc::downgrade(&a);
+        mem::drop(a);
+        // The Weak still holds the block.
+        assert!(alloc.bytes_in_use() > in_use);
+        let w2 = w.clone();
+        mem::drop(w);
+        mem::drop(w2);
+        assert_eq!(alloc.bytes_in_use(), in_use);
+    }
+
+    #[test]
+    fn check_new_reports_errors() {
+        // The counts share the value's block, so room for the value alone,
+        // or even the value and one count, isn't enough.
+        let size = mem::size_of::<RcBox<DropCounter>>();
+        assert!(size > mem::size_of::<DropCounter>() + mem::size_of::<usize>());
+        // Aligned, so that no room is lost to padding.
+        #[repr(align(16))] struct Buffer { buf: [u8; 128] }
+        let mut buf = Buffer { buf: [0u8; 128] };
+        let alloc = LinearAlloc::new(&mut buf.buf[..size - 1]);
+        let count = Cell::new(0);
+
+        let res = Rc::try_new_in(DropCounter(&count), &alloc).map(|_| ());
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        // The value is dropped, and nothing is left allocated.
+        assert_eq!(count.get(), 1);
+        assert_eq!(alloc.bytes_in_use(), 0);
+
+        let alloc = LinearAlloc::new(&mut buf.buf[..size]);
+        let a = Rc::try_new_in(DropCounter(&count), &alloc).expect("Rc::try_new_in(..) failed.");
+        mem::drop(a);
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn check_get_mut_and_make_mut() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let mut a = Rc::try_new_in(vec![1u32], &alloc).expect("Rc::try_new_in(..) failed.");
+        Rc::get_mut(&mut a).expect("Rc::get_mut(..) failed.").push(2);
+
+        // Shared with another Rc, so it gets cloned.
+        let b = a.clone();
+        assert!(Rc::get_mut(&mut a).is_none());
+        Rc::make_mut(&mut a).expect("Rc::make_mut(..) failed.").push(3);
+        assert_eq!((&a[..], &b[..]), (&[1, 2, 3][..], &[1, 2][..]));
+        assert!(!Rc::ptr_eq(&a, &b));
+
+        // Shared with a Weak, so it gets moved.
+        let w = Rc::downgrade(&a);
+        Rc::make_mut(&mut a).expect("Rc::make_mut(..) failed.").push(4);
+        assert!(w.upgrade().is_none());
+        assert_eq!((Rc::strong_count(&a), Rc::weak_count(&a)), (1, 0));
+
+        // Unshared, so it stays put.
+        let ptr = Rc::as_ptr(&a);
+        Rc::make_mut(&mut a).expect("Rc::make_mut(..) failed.").push(5);
+        assert_eq!(Rc::as_ptr(&a), ptr);
+        assert_eq!(*a, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn check_make_mut_reports_errors() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let mut a = Rc::try_new_in([7u8; 24], &alloc).expect("Rc::try_new_in(..) failed.");
+        let b = a.clone();
+        let res = Rc::make_mut(&mut a).map(|_| ());
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert!(Rc::ptr_eq(&a, &b));
+        assert_eq!(Rc::strong_count(&a), 2);
+    }
+
+    #[test]
+    fn check_try_unwrap_and_drops() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+
+        let a = Rc::try_new_in(DropCounter(&count), &alloc).expect("Rc::try_new_in(..) failed.");
+        let b = a.clone();
+        let a = match Rc::try_unwrap(a) {
+            Err(a) => a,
+            Ok(_)  => panic!("Rc::try_unwrap(..) should fail while shared."),
+        };
+        mem::drop(b);
+        assert_eq!(count.get(), 0);
+
+        let w = Rc::downgrade(&a);
+        let value = Rc::try_unwrap(a).ok().expect("Rc::try_unwrap(..) failed.");
+        assert!(w.upgrade().is_none());
+        assert_eq!(count.get(), 0);
+        mem::drop(value);
+        assert_eq!(count.get(), 1);
+
+        // Values are dropped with the last Rc, even while Weaks remain.
+        let a = Rc::try_new_in(DropCounter(&count), &alloc).expect("Rc::try_new_in(..) failed.");
+        let w = Rc::downgrade(&a);
+        mem::drop(a);
+        assert_eq!(count.get(), 2);
+        mem::drop(w);
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn check_zero_sized() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let a = Rc::try_new_in((), &alloc).expect("Rc::try_new_in(..) failed.");
+        let b = a.clone();
+        assert_eq!(Rc::strong_count(&b), 2);
+        assert!(alloc.bytes_in_use() > 0);
+    }
+}
@@ -0,0 +1,218 @@
+//! A vec over a caller-provided, uninitialized slice, which never allocates.
+
+use core::{
+    mem::{ManuallyDrop, MaybeUninit},
+    ptr,
+    slice,
+};
+
+use storage_vec::{Storage, StorageVec};
+
+// ----- SliceVec Impl ----------------------------------------------------------
+
+/// A vec that holds its items in a borrowed slice, with no allocator at all.
+///
+/// Like `LinearAlloc::new` takes a `&mut [u8]`, the slice can be carved from a
+/// stack buffer or anywhere else, but it must outlive the SliceVec. Its length
+/// is the SliceVec's capacity.
+///
+/// It is a `StorageVec` over the slice, so it has the same push/insert/drain
+/// API as `vec2::Vec`. Rather than growing when full, operations that need
+/// more room return a `CapacityErr`. See `array_vec::ArrayVec` for the same
+/// with inline storage.
+///
+/// The SliceVec owns the items it holds, and drops them when it is dropped.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{slice_vec::SliceVec, Error};
+/// # use std::mem::MaybeUninit;
+/// #
+/// let mut buf = [MaybeUninit::<u32>::uninit(); 3];
+/// let mut v = SliceVec::new(&mut buf);
+/// v.extend_from_slice(&[1, 2]).unwrap();
+/// v.insert(0, 0).unwrap();
+///
+/// match v.push(3) {
+///     Err(Error::CapacityErr) => {},
+///     res => panic!("expected CapacityErr, got {:?}", res),
+/// }
+///
+/// v.remove(1);
+/// assert_eq!(v.into_slice(), &[0, 2]);
+/// ```
+pub type SliceVec<'a, T> = StorageVec<T, &'a mut [MaybeUninit<T>]>;
+
+// Borrowed slices never grow, so they keep the `CapacityErr` returning
+// `try_reserve`.
+unsafe impl <T> Storage<T> for &mut [MaybeUninit<T>] {
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    fn as_ptr(&self) -> *const T {
+        self[..].as_ptr() as *const T
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        self[..].as_mut_ptr() as *mut T
+    }
+}
+
+impl <'a, T> SliceVec<'a, T> {
+    /// Construct a new, empty SliceVec over `buf`. It can hold as many items
+    /// as `buf` is long.
+    pub fn new(buf: &'a mut [MaybeUninit<T>]) -> Self {
+        StorageVec::from_storage(buf)
+    }
+
+    /// Converts the SliceVec into the slice of its items, which stay in the
+    /// borrowed buffer.
+    ///
+    /// The items are no longer dropped by the SliceVec, so they are leaked
+    /// unless the caller drops them.
+    pub fn into_slice(self) -> &'a mut [T] {
+        let len = self.len;
+        let me = ManuallyDrop::new(self);
+        unsafe {
+            // The SliceVec won't be dropped, so the buffer is moved out, and
+            // its first `len` items are initialized.
+            let mut buf = ptr::read(&me.storage);
+            slice::from_raw_parts_mut(Storage::as_mut_ptr(&mut buf), len)
+        }
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use array_vec::ArrayVec;
+    use Error;
+    use test_support::{DropCounter, prop_config};
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        mem,
+        vec,
+    };
+
+    #[test]
+    fn check_push_insert_remove() {
+        let mut buf = [MaybeUninit::uninit(); 4];
+        let mut v = SliceVec::new(&mut buf);
+        assert_eq!(v.capacity(), 4);
+
+        v.extend_from_slice_copy(&[1u32, 3]).expect("v.extend_from_slice_copy(..) failed.");
+        v.insert(1, 2).expect("v.insert(..) failed.");
+        v.insert(0, 0).expect("v.insert(..) failed.");
+        assert_eq!(v, [0, 1, 2, 3]);
+        match v.push(4) {
+            Err(Error::CapacityErr) => {},
+            res => panic!("expected CapacityErr, got {:?}", res),
+        }
+
+        assert_eq!(v.remove(1), 1);
+        assert_eq!(v.swap_remove(0), 0);
+        v.retain_mut(|i| {
+            *i *= 10;
+            true
+        });
+        assert_eq!(v, [30, 20]);
+        assert_eq!(format!("{:?}", v), "[30, 20]");
+    }
+
+    #[test]
+    fn check_empty_slice() {
+        let mut buf: [MaybeUninit<u8>; 0] = [];
+        let mut v = SliceVec::new(&mut buf);
+        assert!(v.is_full());
+        match v.push(1) {
+            Err(Error::CapacityErr) => {},
+            res => panic!("expected CapacityErr, got {:?}", res),
+        }
+        assert_eq!(v.drain(..).count(), 0);
+    }
+
+    #[test]
+    fn check_drain_and_into_iter() {
+        let mut buf = [MaybeUninit::uninit(); 8];
+        let mut v = SliceVec::new(&mut buf);
+        v.try_extend(0..8u32).expect("v.try_extend(..) failed.");
+
+        let drained: vec::Vec<_> = v.drain(2..5).rev().collect();
+        assert_eq!(drained, [4, 3, 2]);
+        assert_eq!(v, [0, 1, 5, 6, 7]);
+
+        let mut iter = v.into_iter();
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.next_back(), Some(7));
+        assert!(iter.eq([1, 5, 6]));
+    }
+
+    #[test]
+    fn check_comparisons() {
+        let mut buf = [MaybeUninit::uninit(); 4];
+        let mut v = SliceVec::new(&mut buf);
+        v.extend_from_slice(&[1u32, 2, 3]).expect("v.extend_from_slice(..) failed.");
+        let mut w = ArrayVec::<u32, 4>::try_from_iter([1, 2, 3])
+                                       .expect("ArrayVec::try_from_iter(..) failed.");
+
+        // Fixed storages compare by their elems, like any other.
+        assert!(v == w);
+        w.push(0).expect("w.push(0) failed.");
+        assert!(v < w);
+    }
+
+    #[test]
+    fn check_drops() {
+        let count = Cell::new(0);
+        let mut buf = [MaybeUninit::uninit(), MaybeUninit::uninit(), MaybeUninit::uninit()];
+
+        let mut v = SliceVec::new(&mut buf);
+        for _ in 0..3 {
+            v.push(DropCounter(&count)).expect("v.push(..) failed.");
+        }
+        v.truncate(1);
+        assert_eq!(count.get(), 2);
+        mem::drop(v);
+        assert_eq!(count.get(), 3);
+
+        // The buffer can be reused, and `into_slice` hands the items over.
+        let mut v = SliceVec::new(&mut buf);
+        v.push(DropCounter(&count)).expect("v.push(..) failed.");
+        let items = v.into_slice();
+        assert_eq!(items.len(), 1);
+        assert_eq!(count.get(), 3);
+        unsafe {
+            std::ptr::drop_in_place(items);
+        }
+        assert_eq!(count.get(), 4);
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_fills_to_capacity(cap in 0..16usize,
+                                  items in proptest::collection::vec(any::<u16>(), 0..32))
+        {
+            let mut buf = vec![MaybeUninit::uninit(); cap];
+            let mut v = SliceVec::new(&mut buf);
+            let res = v.extend_from_slice(&items);
+
+            prop_assert_eq!(res.is_ok(), items.len() <= cap);
+            if items.len() <= cap {
+                prop_assert_eq!(v.as_slice(), items.as_slice());
+            } else {
+                prop_assert!(v.is_empty());
+                for &item in &items {
+                    let _ = v.push(item);
+                }
+                prop_assert_eq!(v.as_slice(), &items[..cap]);
+            }
+        }
+    }
+}
@@ -0,0 +1,692 @@
+//! A growable array that holds its first few items inline, and only moves
+//! them into a customizable memory allocator once it outgrows that.
+
+use core::{
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ptr,
+    result,
+};
+
+use alloc_api;
+use growth::{
+    Doubling,
+    GrowthPolicy,
+};
+use raw_vec::RawVec;
+use storage_vec::{Storage, StorageVec};
+use vec2::Vec;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- SmallVec Impl ----------------------------------------------------------
+
+/// A growable array that keeps up to `N` items inline, with a customizable
+/// memory allocator for when it holds more.
+///
+/// It is a `StorageVec` over a `SmallStorage`, so it has the same API as
+/// `vec2::Vec`. Until it first needs room for more than `N` items, it never
+/// touches the allocator. Then it moves its items into an allocation, and
+/// grows from there like a Vec. `shrink_to_fit` moves them back inline once
+/// they fit again.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{linear_alloc::LinearAlloc, small_vec::SmallVec};
+/// #
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut v = SmallVec::<u32, 4, _>::new(&alloc);
+/// v.extend_from_slice(&[1, 2, 3, 4]).unwrap();
+/// assert!(!v.spilled());
+/// assert_eq!(alloc.bytes_in_use(), 0);
+///
+/// v.push(5).unwrap();
+/// assert!(v.spilled());
+/// assert_eq!(v, [1, 2, 3, 4, 5]);
+/// ```
+pub type SmallVec<T, const N: usize, A, G = Doubling> =
+    StorageVec<T, SmallStorage<T, N, A, G>>;
+
+impl <T, const N: usize, A: alloc_api::Allocator> SmallVec<T, N, A> {
+    /// Construct a new SmallVec using the allocator handle `alloc` once it
+    /// spills.
+    pub fn new(alloc: A) -> Self {
+        SmallVec::with_growth(alloc, Doubling)
+    }
+
+    /// Construct a new SmallVec with room for at least `capacity` items,
+    /// using the allocator handle `alloc`. It only allocates if `capacity`
+    /// is more than `N`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        SmallVec::with_capacity_and_growth(alloc, capacity, Doubling)
+    }
+
+    /// Construct a new SmallVec holding the items of `iter`, using the
+    /// allocator handle `alloc`, and returning any allocation errors.
+    pub fn try_from_iter_in<I>(iter: I, alloc: A) -> VecResult<Self>
+        where I: IntoIterator<Item = T>
+    {
+        let mut v = SmallVec::new(alloc);
+        v.try_extend(iter)?;
+        Ok(v)
+    }
+}
+
+impl <T, const N: usize, A, G> SmallVec<T, N, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Construct a new SmallVec using the allocator handle `alloc` once it
+    /// spills, which grows according to the policy `growth`.
+    pub fn with_growth(alloc: A, growth: G) -> Self {
+        StorageVec::from_storage(SmallStorage {
+            buf:    RawVec::with_growth(alloc, growth),
+            inline: MaybeUninit::uninit(),
+        })
+    }
+
+    /// Construct a new SmallVec with room for at least `capacity` items,
+    /// which grows according to the policy `growth`. It only allocates if
+    /// `capacity` is more than `N`.
+    pub fn with_capacity_and_growth(alloc: A, capacity: usize, growth: G)
+        -> VecResult<Self>
+    {
+        let mut v = SmallVec::with_growth(alloc, growth);
+        v.try_reserve_exact(capacity)?;
+        Ok(v)
+    }
+
+    /// Returns the growth policy that the SmallVec is using.
+    pub fn growth(&self) -> &G {
+        self.storage.buf.growth()
+    }
+
+    /// Returns the allocator handle that the SmallVec is using.
+    pub fn alloc(&self) -> &A {
+        self.storage.buf.alloc()
+    }
+
+    /// The number of items that the SmallVec holds inline.
+    pub fn inline_capacity(&self) -> usize {
+        SmallStorage::<T, N, A, G>::inline_capacity()
+    }
+
+    /// Returns `true` if the items have moved into an allocation.
+    pub fn spilled(&self) -> bool {
+        self.storage.spilled()
+    }
+
+    /// Moves the elems from `at` onwards into a new SmallVec, which shares
+    /// this SmallVec's allocator and growth policy.
+    ///
+    /// On allocation errors, both SmallVecs are left untouched.
+    pub fn split_off(&mut self, at: usize) -> VecResult<Self>
+        where A: Clone,
+              G: Clone
+    {
+        assert!(at <= self.len, "`at` out of bounds");
+
+        let count = self.len - at;
+        let mut other = SmallVec::with_capacity_and_growth(self.alloc().clone(),
+                                                           count,
+                                                           self.growth().clone())?;
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr().add(at),
+                                     other.as_mut_ptr(),
+                                     count);
+        }
+        self.len = at;
+        other.len = count;
+        Ok(other)
+    }
+
+    /// Shrinks the allocation to hold at least `min_capacity` elems, and no
+    /// fewer than the SmallVec holds.
+    ///
+    /// If that many fit inline, the elems move back inline and the allocation
+    /// is freed. Otherwise, allocators may not be able to give memory back,
+    /// in which case the capacity is unchanged.
+    pub fn shrink_to(&mut self, min_capacity: usize) -> VecResult<()> {
+        if !self.spilled() {
+            return Ok(());
+        }
+        let min_capacity = min_capacity.max(self.len);
+        let storage = &mut self.storage;
+        if min_capacity > N {
+            return storage.buf.shrink_to(min_capacity);
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(storage.buf.ptr(),
+                                     storage.inline.as_mut_ptr() as *mut T,
+                                     self.len);
+        }
+        // Freeing can't fail.
+        storage.buf.shrink_to(0)
+    }
+
+    /// Shrinks the allocation to hold only as many elems as the SmallVec
+    /// holds, moving them back inline if they fit.
+    pub fn shrink_to_fit(&mut self) -> VecResult<()> {
+        self.shrink_to(0)
+    }
+
+    /// Converts the SmallVec into a `vec2::Vec` in the same allocator. Spilled
+    /// items stay where they are. Inline ones are moved into an allocation
+    /// that fits them.
+    ///
+    /// If that allocation fails, the error is returned and the SmallVec is
+    /// dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, small_vec::SmallVec};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut v = SmallVec::<u32, 4, _>::new(&alloc);
+    /// v.extend_from_slice(&[1, 2, 3]).unwrap();
+    ///
+    /// let v = v.into_vec().unwrap();
+    /// assert_eq!(v.as_slice(), &[1, 2, 3]);
+    /// ```
+    pub fn into_vec(mut self) -> VecResult<Vec<T, A, G>> {
+        let len = self.len;
+        if !self.spilled() && len > 0 && mem::size_of::<T>() != 0 {
+            self.storage.spill(len, len)?;
+        }
+        let me = ManuallyDrop::new(self);
+        unsafe {
+            // The SmallVec won't be dropped, so its buf is moved out.
+            let buf = ptr::read(&me.storage.buf);
+            Ok(Vec::from_raw_vec(buf, len))
+        }
+    }
+}
+
+impl <T, const N: usize, A, G> SmallVec<T, N, A, G>
+    where T: Clone,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Clone the SmallVec into a new one, which shares this SmallVec's
+    /// allocator and growth policy, returning any allocation errors.
+    pub fn try_clone(&self) -> VecResult<Self>
+        where A: Clone,
+              G: Clone
+    {
+        let mut clone = SmallVec::with_capacity_and_growth(self.alloc().clone(),
+                                                           self.len,
+                                                           self.growth().clone())?;
+        clone.extend_from_slice(self)?;
+        Ok(clone)
+    }
+}
+
+// ----- SmallStorage Impl ------------------------------------------------------
+
+/// The storage of a `SmallVec`: room for `N` items inline, and a buffer in
+/// the allocator `A` that they spill into.
+pub struct SmallStorage<T, const N: usize, A, G = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    buf:    RawVec<T, A, G>,     // The items once spilled. Unallocated before.
+    inline: MaybeUninit<[T; N]>, // The items until they are spilled.
+}
+
+impl <T, const N: usize, A, G> SmallStorage<T, N, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    // Zero sized types never need to spill.
+    fn inline_capacity() -> usize {
+        if mem::size_of::<T>() == 0 {
+            usize::MAX
+        } else {
+            N
+        }
+    }
+
+    fn spilled(&self) -> bool {
+        mem::size_of::<T>() != 0 && self.buf.capacity() != 0
+    }
+
+    // Moves the `len` inline items into a new allocation with room for
+    // `capacity`.
+    fn spill(&mut self, len: usize, capacity: usize) -> VecResult<()> {
+        debug_assert!(!self.spilled() && capacity >= len);
+        self.buf.reserve_exact(0, capacity)?;
+        unsafe {
+            ptr::copy_nonoverlapping(self.inline.as_ptr() as *const T,
+                                     self.buf.ptr(),
+                                     len);
+        }
+        Ok(())
+    }
+}
+
+unsafe impl <T, const N: usize, A, G> Storage<T> for SmallStorage<T, N, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn capacity(&self) -> usize {
+        if self.spilled() {
+            self.buf.capacity()
+        } else {
+            Self::inline_capacity()
+        }
+    }
+
+    fn as_ptr(&self) -> *const T {
+        if self.spilled() {
+            self.buf.ptr()
+        } else {
+            self.inline.as_ptr() as *const T
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut T {
+        if self.spilled() {
+            self.buf.ptr()
+        } else {
+            self.inline.as_mut_ptr() as *mut T
+        }
+    }
+
+    // When the items spill, the growth policy grows from the inline capacity.
+    fn try_reserve(&mut self, len: usize, additional: usize) -> VecResult<()> {
+        if self.spilled() {
+            return self.buf.reserve(len, additional);
+        }
+        let required = len.checked_add(additional)
+                          .ok_or(Error::SizeOverflowErr)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+
+        let new_cap = self.buf
+                          .growth()
+                          .next_capacity(N,
+                                         required,
+                                         mem::size_of::<T>(),
+                                         self.buf.alloc())
+                          .max(required);
+        match self.spill(len, new_cap) {
+            Err(_) if new_cap > required => self.spill(len, required),
+            res => res,
+        }
+    }
+
+    fn try_reserve_exact(&mut self, len: usize, additional: usize)
+        -> VecResult<()>
+    {
+        if self.spilled() {
+            return self.buf.reserve_exact(len, additional);
+        }
+        let required = len.checked_add(additional)
+                          .ok_or(Error::SizeOverflowErr)?;
+        if required <= self.capacity() {
+            return Ok(());
+        }
+        self.spill(len, required)
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use growth::Increment;
+    use linear_alloc::LinearAlloc;
+    use test_support::{DropCounter, Liar, prop_config};
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        vec,
+    };
+
+    #[test]
+    fn check_stays_inline() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 4, _>::new(&alloc);
+        assert_eq!(v.capacity(), 4);
+
+        v.extend_from_slice(&[1, 2, 3]).expect("v.extend_from_slice(..) failed.");
+        v.insert(0, 0).expect("v.insert(..) failed.");
+        assert_eq!(v, [0, 1, 2, 3]);
+        assert!(!v.spilled());
+        assert_eq!(alloc.bytes_in_use(), 0);
+
+        assert_eq!(v.remove(1), 1);
+        assert_eq!(v.swap_remove(0), 0);
+        assert_eq!(v.pop(), Some(2));
+        assert_eq!(v, [3]);
+        v[0] = 7;
+        assert_eq!(&v[..], &[7]);
+
+        // Moving the SmallVec moves its items along.
+        let moved = v;
+        assert_eq!(moved, [7]);
+        assert_eq!(alloc.bytes_in_use(), 0);
+    }
+
+    #[test]
+    fn check_spills_and_comes_back() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 4, _>::new(&alloc);
+        v.try_extend(0..5).expect("v.try_extend(..) failed.");
+        assert!(v.spilled());
+        // Doubling grows from the inline capacity.
+        assert_eq!(v.capacity(), 8);
+        assert!(alloc.bytes_in_use() >= 8 * 4);
+
+        v.truncate(3);
+        v.shrink_to_fit().expect("v.shrink_to_fit() failed.");
+        assert!(!v.spilled());
+        assert_eq!(v.capacity(), 4);
+        assert_eq!(v, [0, 1, 2]);
+
+        let mut w = SmallVec::<u32, 4, _>::with_capacity(&alloc, 10)
+                                          .expect("SmallVec::with_capacity(..) failed.");
+        assert!(w.spilled());
+        assert!(w.capacity() >= 10);
+        w.append(&mut v).expect("w.append(..) failed.");
+        assert!(v.is_empty());
+        assert_eq!(w, [0, 1, 2]);
+    }
+
+    #[test]
+    fn check_growth_policy() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u8, 2, _, _>::with_growth(&alloc, Increment(3));
+
+        v.extend_from_slice_copy(b"abc").expect("v.extend_from_slice_copy(..) failed.");
+        assert_eq!(v.capacity(), 5);
+        v.extend_from_slice_copy(b"def").expect("v.extend_from_slice_copy(..) failed.");
+        assert_eq!(v.capacity(), 8);
+        assert_eq!(v, *b"abcdef");
+    }
+
+    #[test]
+    fn check_drain_and_into_iter() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // Both inline and spilled.
+        for &count in &[4u32, 12] {
+            let mut v = SmallVec::<u32, 6, _>::new(&alloc);
+            v.try_extend(0..count).expect("v.try_extend(..) failed.");
+
+            let drained: vec::Vec<_> = v.drain(1..3).rev().collect();
+            assert_eq!(drained, [2, 1]);
+            assert_eq!(v.len(), count as usize - 2);
+            assert_eq!(v[..2], [0, 3]);
+
+            let mut iter = v.into_iter();
+            assert_eq!(iter.next(), Some(0));
+            assert_eq!(iter.next_back(), Some(count - 1));
+            assert_eq!(iter.len(), count as usize - 4);
+            assert!(iter.eq(3..count - 1));
+        }
+    }
+
+    #[test]
+    fn check_retain_dedup_resize() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 8, _>::new(&alloc);
+        v.extend_from_slice(&[1, 1, 2, 3, 3, 4]).expect("v.extend_from_slice(..) failed.");
+
+        v.dedup();
+        assert_eq!(v, [1, 2, 3, 4]);
+        v.retain(|&i| i % 2 == 0);
+        assert_eq!(v, [2, 4]);
+        v.resize(4, 9).expect("v.resize(..) failed.");
+        assert_eq!(v, [2, 4, 9, 9]);
+        v.extend_from_within(..2).expect("v.extend_from_within(..) failed.");
+        assert_eq!(v, [2, 4, 9, 9, 2, 4]);
+
+        let tail = v.split_off(4).expect("v.split_off(..) failed.");
+        assert_eq!((v.len(), tail.len()), (4, 2));
+        let clone = v.try_clone().expect("v.try_clone() failed.");
+        assert_eq!(clone, v);
+        assert!(tail < clone);
+        assert_eq!(format!("{:?}", tail), "[2, 4]");
+    }
+
+    #[test]
+    fn check_drops() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+
+        let mut v = SmallVec::<_, 2, _>::new(&alloc);
+        v.push(DropCounter(&count)).expect("v.push(..) failed.");
+        mem::drop(v);
+        assert_eq!(count.get(), 1);
+
+        let mut v = SmallVec::<_, 2, _>::new(&alloc);
+        for _ in 0..5 {
+            v.push(DropCounter(&count)).expect("v.push(..) failed.");
+        }
+        let mut iter = v.into_iter();
+        mem::drop(iter.next());
+        assert_eq!(count.get(), 2);
+        mem::drop(iter);
+        assert_eq!(count.get(), 6);
+    }
+
+    #[test]
+    fn check_into_vec() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let mut v = SmallVec::<u32, 4, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2]).expect("v.extend_from_slice(..) failed.");
+        let vec = v.into_vec().expect("v.into_vec() failed.");
+        assert_eq!(vec, [1, 2]);
+
+        let mut v = SmallVec::<u32, 1, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2]).expect("v.extend_from_slice(..) failed.");
+        let ptr = v.as_ptr();
+        let vec = v.into_vec().expect("v.into_vec() failed.");
+        assert_eq!(vec.ptr() as *const u32, ptr);
+        assert_eq!(vec, [1, 2]);
+    }
+
+    #[test]
+    fn check_splice() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 4, _>::try_from_iter_in([1, 2, 3], &alloc)
+                                          .expect("SmallVec::try_from_iter_in(..) failed.");
+
+        // Fits inline.
+        let removed: vec::Vec<_> = v.splice(1..2, [7, 8])
+                                    .expect("v.splice(..) failed.")
+                                    .collect();
+        assert_eq!(removed, [2]);
+        assert_eq!(v, [1, 7, 8, 3]);
+        assert!(!v.spilled());
+
+        // Room for the extra items is made by spilling, before any move.
+        mem::drop(v.splice(..1, [4, 5, 6]).expect("v.splice(..) failed."));
+        assert!(v.spilled());
+        assert_eq!(v, [4, 5, 6, 7, 8, 3]);
+    }
+
+    #[test]
+    fn check_splice_spills_midway() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 4, _>::try_from_iter_in([10, 20, 30], &alloc)
+                                          .expect("SmallVec::try_from_iter_in(..) failed.");
+
+        // The first items fill the gap inline, and the rest spill the items,
+        // tail and all.
+        mem::drop(v.splice(1..2, Liar(4)).expect("v.splice(..) failed."));
+        assert!(v.spilled());
+        assert_eq!(v, [10, 3, 2, 1, 0, 30]);
+    }
+
+    #[test]
+    fn check_extract_if() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // Both inline and spilled.
+        for &count in &[6u32, 12] {
+            let mut v = SmallVec::<u32, 8, _>::try_from_iter_in(0..count, &alloc)
+                                              .expect("SmallVec::try_from_iter_in(..) failed.");
+
+            let odds: vec::Vec<_> = v.extract_if(1.., |x| *x % 2 == 1).collect();
+            let evens: vec::Vec<_> = (0..count).step_by(2).collect();
+            assert_eq!(odds, (1..count).step_by(2).collect::<vec::Vec<_>>());
+            assert_eq!(v.as_slice(), evens.as_slice());
+
+            // Elems that weren't visited are kept.
+            let mut extract = v.extract_if(.., |_| true);
+            assert_eq!(extract.next(), Some(0));
+            mem::drop(extract);
+            assert_eq!(v.len(), count as usize / 2 - 1);
+        }
+    }
+
+    #[test]
+    fn check_comparisons() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let v = SmallVec::<u32, 2, _>::try_from_iter_in([1, 2, 3], &alloc)
+                                      .expect("SmallVec::try_from_iter_in(..) failed.");
+        let w = SmallVec::<u32, 4, _>::try_from_iter_in([1, 2, 3], &alloc)
+                                      .expect("SmallVec::try_from_iter_in(..) failed.");
+        assert!(v.spilled() && !w.spilled());
+
+        assert!(v == w);
+        let slice: &[u32] = &[1, 2, 3];
+        assert!(v == [1, 2, 3]);
+        assert!(v == slice);
+        assert!(slice == w);
+        assert!(v[..] == w);
+        let x = vec_in!(&alloc; 1u32, 2, 3).expect("vec_in!(..) failed.");
+        assert!(x == v);
+        assert!(w == x);
+        #[cfg(feature = "std")]
+        {
+            assert!(v == vec![1, 2, 3]);
+            assert!(vec![1, 2, 3] == w);
+        }
+        assert!(v <= w);
+    }
+
+    #[test]
+    fn check_zero_sized() {
+        let mut buf = [0u8; 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<(), 0, _>::new(&alloc);
+        for _ in 0..100 {
+            v.push(()).expect("v.push(..) failed.");
+        }
+        assert!(!v.spilled());
+        assert_eq!(v.len(), 100);
+        assert_eq!(v.drain(10..).count(), 90);
+        assert_eq!(v.into_iter().count(), 10);
+        assert_eq!(alloc.bytes_in_use(), 0);
+    }
+
+    #[test]
+    fn check_spill_falls_back_to_exact() {
+        // Room for three items on the heap, but not the four that doubling
+        // asks for, so the spill takes only what it needs. Aligned, so that
+        // no room is lost to padding.
+        #[repr(align(8))] struct Buffer { buf: [u8; 24] }
+        let mut buf = Buffer { buf: [0u8; 24] };
+        let alloc = LinearAlloc::new(&mut buf.buf);
+        let mut v = SmallVec::<u64, 2, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2]).expect("v.extend_from_slice(..) failed.");
+
+        v.push(3).expect("v.push(..) failed.");
+        assert!(v.spilled());
+        assert_eq!(v.capacity(), 3);
+
+        // Once spilled, there's nowhere left to grow.
+        let res = v.push(4);
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert!(v.spilled());
+        assert_eq!(v, [1, 2, 3]);
+    }
+
+    // A step of the model test below.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(u8),
+        Pop,
+        Insert(usize, u8),
+        Remove(usize),
+        Truncate(usize),
+        ShrinkToFit,
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => any::<u8>().prop_map(Op::Push),
+            2 => Just(Op::Pop),
+            2 => (any::<usize>(), any::<u8>()).prop_map(|(i, x)| Op::Insert(i, x)),
+            2 => any::<usize>().prop_map(Op::Remove),
+            1 => (0..16usize).prop_map(Op::Truncate),
+            1 => Just(Op::ShrinkToFit),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_matches_std_vec(ops in proptest::collection::vec(arb_op(), 0..128)) {
+            let mut buf = vec![0u8; 1 << 14];
+            let alloc = LinearAlloc::new(&mut buf);
+            let mut v = SmallVec::<u8, 4, _>::new(&alloc);
+            let mut model = vec::Vec::new();
+
+            for op in ops {
+                match op {
+                    Op::Push(x) => {
+                        v.push(x).expect("v.push(..) failed.");
+                        model.push(x);
+                    },
+                    Op::Pop => prop_assert_eq!(v.pop(), model.pop()),
+                    Op::Insert(i, x) => {
+                        let i = i % (model.len() + 1);
+                        v.insert(i, x).expect("v.insert(..) failed.");
+                        model.insert(i, x);
+                    },
+                    Op::Remove(i) if !model.is_empty() => {
+                        let i = i % model.len();
+                        prop_assert_eq!(v.remove(i), model.remove(i));
+                    },
+                    Op::Remove(_) => {},
+                    Op::Truncate(len) => {
+                        v.truncate(len);
+                        model.truncate(len);
+                    },
+                    Op::ShrinkToFit => {
+                        v.shrink_to_fit().expect("v.shrink_to_fit() failed.");
+                        prop_assert_eq!(v.spilled(), model.len() > 4);
+                    },
+                }
+                prop_assert_eq!(v.as_slice(), model.as_slice());
+            }
+        }
+    }
+}
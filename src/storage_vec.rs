@@ -0,0 +1,1475 @@
+//! A vec generic over where it keeps its items. `vec2::Vec` is a `StorageVec`
+//! over a `RawVec`, and `ArrayVec`, `SliceVec` and `SmallVec` are ones over
+//! other `Storage`. The Vec API lives here, once, for all of them.
+
+use core::{
+    borrow,
+    cmp,
+    fmt,
+    hash,
+    iter,
+    marker::PhantomData,
+    mem,
+    ops::{self, RangeBounds},
+    ptr,
+    result,
+    slice,
+};
+
+use vec2::to_range;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- Storage ----------------------------------------------------------------
+
+/// A block of room for `T`s, which a `StorageVec` fills from the front.
+///
+/// Fixed storage, like an array or a borrowed slice, can use the default
+/// `try_reserve` methods, which return a `CapacityErr` once it is full.
+/// Storage that can grow overrides them.
+///
+/// # Safety
+/// `as_ptr` and `as_mut_ptr` must point to room for `capacity()` Ts. The
+/// items there must stay put unless the storage is moved, or `try_reserve`
+/// or `try_reserve_exact` move them. Those must only return `Ok` once there
+/// is room for `len + additional` Ts, with the first `len` kept in order.
+pub unsafe trait Storage<T> {
+    /// The number of Ts that there is room for.
+    fn capacity(&self) -> usize;
+
+    /// Returns a pointer to the first T.
+    fn as_ptr(&self) -> *const T;
+
+    /// Returns a mutable pointer to the first T.
+    fn as_mut_ptr(&mut self) -> *mut T;
+
+    /// Makes room for at least `additional` more Ts past the first `len`,
+    /// which are initialized.
+    fn try_reserve(&mut self, len: usize, additional: usize) -> VecResult<()> {
+        if additional > self.capacity() - len {
+            Err(Error::CapacityErr)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Makes room for at least `additional` more Ts past the first `len`,
+    /// without over-allocating.
+    fn try_reserve_exact(&mut self, len: usize, additional: usize)
+        -> VecResult<()>
+    {
+        self.try_reserve(len, additional)
+    }
+}
+
+// ----- StorageVec Impl --------------------------------------------------------
+
+/// A vec that keeps its items in the storage `S`.
+///
+/// Every method that needs more room asks the storage for it, so whether a
+/// full vec grows or returns a `CapacityErr` is up to `S`. The items are
+/// dropped along with the vec.
+///
+/// This is usually named through one of its aliases: `vec2::Vec`,
+/// `array_vec::ArrayVec`, `slice_vec::SliceVec` or `small_vec::SmallVec`.
+pub struct StorageVec<T, S>
+    where S: Storage<T>
+{
+    pub(crate) storage: S,     // Holds the items, initialized up to `len`.
+    pub(crate) len:     usize, // Count of Ts stored.
+    _own:               PhantomData<T>,
+}
+
+impl <T, S> StorageVec<T, S>
+    where S: Storage<T>
+{
+    /// Construct a new, empty vec over `storage`.
+    pub const fn from_storage(storage: S) -> Self {
+        StorageVec {
+            storage,
+            len:  0,
+            _own: PhantomData,
+        }
+    }
+
+    /// The number of items that the vec can hold before it needs more room.
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// The number of items currently in the vec.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Sets the length of the vec, without dropping or initializing items.
+    ///
+    /// # Safety
+    /// `new_len` must be no more than `capacity()`, and the first `new_len`
+    /// items must be initialized.
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        debug_assert!(new_len <= self.capacity());
+        self.len = new_len;
+    }
+
+    /// Returns `true` if the vec holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the vec can't take another item without more room.
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    /// The number of items that can be added before the vec needs more room.
+    pub fn remaining_capacity(&self) -> usize {
+        self.capacity() - self.len
+    }
+
+    /// Returns a pointer to the items.
+    ///
+    /// Storage held inline moves with the vec, so the pointer is only valid
+    /// until the vec moves or makes more room.
+    pub fn as_ptr(&self) -> *const T {
+        self.storage.as_ptr()
+    }
+
+    /// Returns a mutable pointer to the items.
+    ///
+    /// Storage held inline moves with the vec, so the pointer is only valid
+    /// until the vec moves or makes more room.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.storage.as_mut_ptr()
+    }
+
+    /// Make room for at least `additional` more items.
+    ///
+    /// # Panics
+    /// Panics if the storage can't make room. See `try_reserve` to handle
+    /// the error.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            panic!("StorageVec::reserve({}) failed: {:?}", additional, err);
+        }
+    }
+
+    /// Make room for exactly `additional` more items, if the storage grows.
+    ///
+    /// # Panics
+    /// Panics if the storage can't make room. See `try_reserve_exact` to
+    /// handle the error.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve_exact(additional) {
+            panic!("StorageVec::reserve_exact({}) failed: {:?}",
+                   additional, err);
+        }
+    }
+
+    /// Make room for at least `additional` more items, returning the
+    /// storage's error if it can't.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    ///
+    /// v.try_reserve(10).unwrap();
+    /// assert!(v.capacity() >= 10);
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> VecResult<()> {
+        self.storage.try_reserve(self.len, additional)
+    }
+
+    /// Make room for exactly `additional` more items, if the storage grows,
+    /// returning the storage's error if it can't.
+    pub fn try_reserve_exact(&mut self, additional: usize) -> VecResult<()> {
+        self.storage.try_reserve_exact(self.len, additional)
+    }
+
+    /// Move `elem` onto the end of the vec. If there is no room for it, the
+    /// error is returned and `elem` is dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec, Error};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::new(&alloc);
+    ///
+    /// match v.push(2) {
+    ///     // Inserstion worked: Either no allocation happened, or it worked.
+    ///     Ok(()) => {},
+    ///     // If (re)allocation fails...
+    ///     Err(err) => match err {
+    ///         // Operations with `alloc_api::Allocator` and `alloc_api::Layout`
+    ///         // can generate a `alloc_api::LayoutError` error.
+    ///         Error::LayoutErr(layout_err) => {
+    ///             println!("layout error: {:?}", layout_err);
+    ///         },
+    ///         // Allocation errors propgate from `alloc_api::Allocator` as
+    ///         // `alloc_api::AllocError`.
+    ///         Error::AllocErr(alloc_err) => {
+    ///             println!("alloc error: {:?}", alloc_err);
+    ///         },
+    ///         // If layout or size calculations overflow, the resize fails.
+    ///         Error::SizeOverflowErr => {
+    ///             println!("Size overflowed trying to allocate");
+    ///         },
+    ///         // Only fixed capacity containers run out of room.
+    ///         Error::CapacityErr => unreachable!(),
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(v.as_slice(), &[2]);
+    /// ```
+    pub fn push(&mut self, elem: T) -> VecResult<()> {
+        if self.is_full() {
+            self.try_reserve(1)?;
+        }
+        unsafe {
+            ptr::write(self.as_mut_ptr().add(self.len), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Move the last elem out of the vec, or return `None` if it is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(unsafe {
+                self.len -= 1;
+                ptr::read(self.as_ptr().add(self.len))
+            })
+        }
+    }
+
+    /// Move `elem` into the vec at `index`, shifting the elems after it up
+    /// by one.
+    pub fn insert(&mut self, index: usize, elem: T) -> VecResult<()> {
+        assert!(index <= self.len);
+        if self.is_full() {
+            self.try_reserve(1)?;
+        }
+
+        unsafe {
+            let ptr = self.as_mut_ptr();
+            if index < self.len {
+                ptr::copy(ptr.add(index), ptr.add(index + 1), self.len - index);
+            }
+            ptr::write(ptr.add(index), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Move the elem at `index` out of the vec, shifting the elems after it
+    /// down by one.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len);
+        unsafe {
+            self.len -= 1;
+            let ptr = self.as_mut_ptr();
+            let corpse = ptr::read(ptr.add(index));
+            ptr::copy(ptr.add(index + 1), ptr.add(index), self.len - index);
+            corpse
+        }
+    }
+
+    /// Move the elem at `index` out of the vec, and move the last elem into
+    /// its spot. This doesn't preserve ordering, but is O(1).
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len);
+        unsafe {
+            self.len -= 1;
+            let ptr = self.as_mut_ptr();
+            let corpse = ptr::read(ptr.add(index));
+            ptr::copy(ptr.add(self.len), ptr.add(index), 1);
+            corpse
+        }
+    }
+
+    /// Drops the elems past the first `len`, if there are any.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        unsafe {
+            // Shorten first, so a panicking destructor can't cause the tail
+            // to be dropped twice. `drop_in_place` still drops the rest of
+            // the tail if one destructor panics.
+            let tail = slice::from_raw_parts_mut(self.as_mut_ptr().add(len),
+                                                 self.len - len);
+            self.len = len;
+            ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Drops every elem. The storage keeps any room it has.
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    /// Drops the elems for which `f` returns `false`, keeping the others in
+    /// order.
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&T) -> bool
+    {
+        self.retain_mut(|elem| f(elem))
+    }
+
+    /// Like `retain`, but `f` may also modify the elems it visits.
+    pub fn retain_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&mut T) -> bool
+    {
+        let original_len = self.len;
+        // The pointer and the length are disjoint fields, so the pointer is
+        // still good while `shift` holds the length.
+        let ptr = self.storage.as_mut_ptr();
+        let mut shift = BackshiftOnDrop {
+            ptr,
+            len:       &mut self.len,
+            processed: 0,
+            deleted:   0,
+            original_len,
+        };
+
+        while shift.processed < original_len {
+            unsafe {
+                let cur = ptr.add(shift.processed);
+                // If `f` panics, `cur` is still unprocessed and gets moved
+                // down with the rest.
+                if !f(&mut *cur) {
+                    // Count it as gone first, in case its destructor panics.
+                    shift.processed += 1;
+                    shift.deleted += 1;
+                    ptr::drop_in_place(cur);
+                } else {
+                    if shift.deleted > 0 {
+                        ptr::copy_nonoverlapping(cur,
+                                                 cur.sub(shift.deleted),
+                                                 1);
+                    }
+                    shift.processed += 1;
+                }
+            }
+        }
+    }
+
+    /// Drops each elem whose key matches the key of the elem before it.
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F)
+        where F: FnMut(&mut T) -> K,
+              K: PartialEq
+    {
+        self.dedup_by(|a, b| key(a) == key(b))
+    }
+
+    /// Drops each elem for which `same_bucket(elem, previous)` returns
+    /// `true`, where `previous` is the last elem kept.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+        where F: FnMut(&mut T, &mut T) -> bool
+    {
+        let original_len = self.len;
+        if original_len <= 1 {
+            return;
+        }
+
+        let ptr = self.storage.as_mut_ptr();
+        let mut shift = BackshiftOnDrop {
+            ptr,
+            len:       &mut self.len,
+            processed: 1,
+            deleted:   0,
+            original_len,
+        };
+
+        while shift.processed < original_len {
+            unsafe {
+                let cur  = ptr.add(shift.processed);
+                let prev = cur.sub(shift.deleted + 1);
+                if same_bucket(&mut *cur, &mut *prev) {
+                    shift.processed += 1;
+                    shift.deleted += 1;
+                    ptr::drop_in_place(cur);
+                } else {
+                    if shift.deleted > 0 {
+                        ptr::copy_nonoverlapping(cur,
+                                                 cur.sub(shift.deleted),
+                                                 1);
+                    }
+                    shift.processed += 1;
+                }
+            }
+        }
+    }
+
+    /// Truncates or extends the vec to `new_len` elems, calling `f` for each
+    /// new one. Room is made up front, so on errors the vec is untouched.
+    pub fn resize_with<F>(&mut self, new_len: usize, mut f: F) -> VecResult<()>
+        where F: FnMut() -> T
+    {
+        if new_len <= self.len {
+            self.truncate(new_len);
+            return Ok(());
+        }
+
+        self.try_reserve(new_len - self.len)?;
+        while self.len < new_len {
+            unsafe {
+                ptr::write(self.as_mut_ptr().add(self.len), f());
+            }
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    /// Moves every elem of `other` onto the end of this vec, leaving `other`
+    /// empty. `other` may use any storage.
+    ///
+    /// If there is no room, the error is returned and neither vec changes.
+    pub fn append<S2>(&mut self, other: &mut StorageVec<T, S2>) -> VecResult<()>
+        where S2: Storage<T>
+    {
+        self.try_reserve(other.len)?;
+        unsafe {
+            ptr::copy_nonoverlapping(other.as_ptr(),
+                                     self.as_mut_ptr().add(self.len),
+                                     other.len);
+        }
+        self.len += other.len;
+        other.len = 0;
+        Ok(())
+    }
+
+    /// Moves the items of `iter` onto the end of the vec.
+    ///
+    /// Room for the iterator's lower size hint is made first, so if that
+    /// fails, no items are moved. If making room fails later on, the items
+    /// moved so far are kept and the rest of `iter` is dropped.
+    pub fn try_extend<I>(&mut self, iter: I) -> VecResult<()>
+        where I: IntoIterator<Item = T>
+    {
+        let mut iter = iter.into_iter();
+        let (lower, _) = iter.size_hint();
+        self.try_reserve(lower)?;
+
+        while let Some(elem) = iter.next() {
+            if self.is_full() {
+                let (lower, _) = iter.size_hint();
+                self.try_reserve(lower.saturating_add(1))?;
+            }
+            unsafe {
+                ptr::write(self.as_mut_ptr().add(self.len), elem);
+            }
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns the elems as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        self
+    }
+
+    /// Returns the elems as a mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+
+    /// Removes the elems in `range`, returning an iterator that yields them.
+    ///
+    /// The elems after the range move down when the `Drain` is dropped. A
+    /// `Drain` that is leaked leaks them too, rather than dropping anything
+    /// twice.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, small_vec::SmallVec};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut v = SmallVec::<u32, 8, _>::try_from_iter_in(1..6, &alloc).unwrap();
+    ///
+    /// let drained: std::vec::Vec<_> = v.drain(1..3).collect();
+    /// assert_eq!(drained, [2, 3]);
+    /// assert_eq!(v, [1, 4, 5]);
+    /// ```
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, S>
+        where R: RangeBounds<usize>
+    {
+        let len = self.len;
+        let ops::Range { start, end } = to_range(range, len);
+        // The Drain hands the head back right away, and the tail on drop.
+        self.len = start;
+        Drain {
+            vec:        self,
+            start,
+            end,
+            tail_start: end,
+            tail_len:   len - end,
+        }
+    }
+
+    /// Replaces the elems in `range` with the items of `replace_with`, and
+    /// returns an iterator over the elems taken out.
+    ///
+    /// Room for the extra items is made here, so if that fails the error is
+    /// returned and the vec is untouched. The new items move in when the
+    /// `Splice` is dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, small_vec::SmallVec};
+    /// #
+    /// // With no room to spill into, it only has its inline storage.
+    /// let mut buf = [0u8; 0];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut v = SmallVec::<u32, 5, _>::try_from_iter_in([1, 2, 3, 4], &alloc)
+    ///                                   .unwrap();
+    ///
+    /// let removed: std::vec::Vec<_> = v.splice(1..3, [7, 8, 9]).unwrap()
+    ///                                  .collect();
+    /// assert_eq!(removed, [2, 3]);
+    /// assert_eq!(v, [1, 7, 8, 9, 4]);
+    ///
+    /// // There is only room for one more.
+    /// assert!(v.splice(..1, [0, 0]).is_err());
+    /// ```
+    pub fn splice<R, I>(&mut self, range: R, replace_with: I)
+        -> VecResult<Splice<'_, I::IntoIter, S>>
+        where R: RangeBounds<usize>,
+              I: IntoIterator<Item = T>,
+              I::IntoIter: ExactSizeIterator
+    {
+        let range = to_range(range, self.len);
+        let replace_with = replace_with.into_iter();
+        self.try_reserve(replace_with.len().saturating_sub(range.len()))?;
+        Ok(Splice {
+            drain: self.drain(range),
+            replace_with,
+        })
+    }
+
+    /// Returns an iterator that takes out and yields the elems in `range`
+    /// for which `filter` returns `true`.
+    ///
+    /// `filter` may modify the elems it visits. If the `ExtractIf` is
+    /// dropped early, the elems it didn't visit stay in the vec.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, small_vec::SmallVec};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut v = SmallVec::<u32, 8, _>::try_from_iter_in(1..7, &alloc).unwrap();
+    ///
+    /// let evens: std::vec::Vec<_> = v.extract_if(.., |x| *x % 2 == 0)
+    ///                                .collect();
+    /// assert_eq!(evens, [2, 4, 6]);
+    /// assert_eq!(v, [1, 3, 5]);
+    /// ```
+    pub fn extract_if<R, F>(&mut self, range: R, filter: F)
+        -> ExtractIf<'_, T, S, F>
+        where R: RangeBounds<usize>,
+              F: FnMut(&mut T) -> bool
+    {
+        let old_len = self.len;
+        let ops::Range { start, end } = to_range(range, old_len);
+        // Until the ExtractIf is dropped, the vec looks empty, so leaking it
+        // can't get an extracted elem dropped twice.
+        self.len = 0;
+        ExtractIf {
+            vec:     self,
+            idx:     start,
+            end,
+            del:     0,
+            old_len,
+            filter,
+        }
+    }
+}
+
+impl <T, S> StorageVec<T, S>
+    where T: Clone,
+          S: Storage<T>
+{
+    /// Append clones of the items in `slice`. Room is made up front, so on
+    /// errors the vec is untouched. For `Copy` types,
+    /// `extend_from_slice_copy` is faster.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    ///
+    /// v.extend_from_slice(&[1, 2, 3, 4]).unwrap();
+    /// assert_eq!(v.as_slice(), &[1, 2, 3, 4]);
+    /// ```
+    pub fn extend_from_slice(&mut self, slice: &[T]) -> VecResult<()> {
+        self.try_reserve(slice.len())?;
+        for elem in slice {
+            unsafe {
+                ptr::write(self.as_mut_ptr().add(self.len), elem.clone());
+            }
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    /// Truncates or extends the vec to `new_len` elems, filling new spots
+    /// with clones of `value`. Room is made up front, so on errors the vec
+    /// is untouched.
+    pub fn resize(&mut self, new_len: usize, value: T) -> VecResult<()> {
+        if new_len <= self.len {
+            self.truncate(new_len);
+            return Ok(());
+        }
+
+        self.try_reserve(new_len - self.len)?;
+        while self.len < new_len - 1 {
+            unsafe {
+                ptr::write(self.as_mut_ptr().add(self.len), value.clone());
+            }
+            self.len += 1;
+        }
+        // The last spot can take `value` itself.
+        unsafe {
+            ptr::write(self.as_mut_ptr().add(self.len), value);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Append clones of the elems in `src`, which indexes this vec. Room is
+    /// made up front, so on errors the vec is untouched.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    /// v.extend_from_slice(&[1, 2, 3]).unwrap();
+    ///
+    /// v.extend_from_within(1..).unwrap();
+    /// assert_eq!(v.as_slice(), &[1, 2, 3, 2, 3]);
+    /// ```
+    pub fn extend_from_within<R>(&mut self, src: R) -> VecResult<()>
+        where R: RangeBounds<usize>
+    {
+        let src = to_range(src, self.len);
+        self.try_reserve(src.len())?;
+        for index in src {
+            unsafe {
+                let ptr = self.as_mut_ptr();
+                ptr::write(ptr.add(self.len), (*ptr.add(index)).clone());
+            }
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+impl <T, S> StorageVec<T, S>
+    where T: PartialEq,
+          S: Storage<T>
+{
+    /// Drops each elem that equals the elem before it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    /// v.extend_from_slice(&[1, 1, 2, 3, 3, 3, 1]).unwrap();
+    ///
+    /// v.dedup();
+    /// assert_eq!(v.as_slice(), &[1, 2, 3, 1]);
+    /// ```
+    pub fn dedup(&mut self) {
+        self.dedup_by(|a, b| a == b)
+    }
+}
+
+impl <T, S> StorageVec<T, S>
+    where T: Copy,
+          S: Storage<T>
+{
+    /// Append the items in `slice` with a single copy. Room is made up
+    /// front, so on errors the vec is untouched.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u8, _>::new(&alloc);
+    ///
+    /// v.extend_from_slice_copy(b"hello").unwrap();
+    /// assert_eq!(v.as_slice(), b"hello");
+    /// ```
+    pub fn extend_from_slice_copy(&mut self, slice: &[T]) -> VecResult<()> {
+        self.try_reserve(slice.len())?;
+        unsafe {
+            ptr::copy_nonoverlapping(slice.as_ptr(),
+                                     self.as_mut_ptr().add(self.len),
+                                     slice.len());
+        }
+        self.len += slice.len();
+        Ok(())
+    }
+}
+
+// Used by `retain_mut` and `dedup_by` to close the gaps left by removed elems.
+//
+// Elems before `processed` have been visited. The kept ones have already been
+// moved down by `deleted` spots. If a callback panics partway through, the
+// unvisited elems still need moving down, so that the vec is left with no
+// holes.
+struct BackshiftOnDrop<'a, T> {
+    ptr:          *mut T,
+    len:          &'a mut usize,
+    processed:    usize,
+    deleted:      usize,
+    original_len: usize,
+}
+
+impl <'a, T> Drop for BackshiftOnDrop<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.deleted > 0 && self.processed < self.original_len {
+                ptr::copy(self.ptr.add(self.processed),
+                          self.ptr.add(self.processed - self.deleted),
+                          self.original_len - self.processed);
+            }
+        }
+        *self.len = self.original_len - self.deleted;
+    }
+}
+
+// ----- StorageVec Traits ------------------------------------------------------
+
+impl <T, S> Drop for StorageVec<T, S>
+    where S: Storage<T>
+{
+    fn drop(&mut self) {
+        // The storage frees itself, if it needs to.
+        self.clear();
+    }
+}
+
+impl <T, S> ops::Deref for StorageVec<T, S>
+    where S: Storage<T>
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe {
+            slice::from_raw_parts(self.as_ptr(), self.len)
+        }
+    }
+}
+
+impl <T, S> ops::DerefMut for StorageVec<T, S>
+    where S: Storage<T>
+{
+    fn deref_mut(&mut self) -> &mut [T] {
+        unsafe {
+            slice::from_raw_parts_mut(self.as_mut_ptr(), self.len)
+        }
+    }
+}
+
+impl <T, S> iter::IntoIterator for StorageVec<T, S>
+    where S: Storage<T>
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, S>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        // The IntoIter owns the items from here on.
+        let end = mem::replace(&mut self.len, 0);
+        IntoIter {
+            vec:   self,
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl <'a, T, S> iter::IntoIterator for &'a StorageVec<T, S>
+    where S: Storage<T>
+{
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl <'a, T, S> iter::IntoIterator for &'a mut StorageVec<T, S>
+    where S: Storage<T>
+{
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> slice::IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl <T, S> fmt::Debug for StorageVec<T, S>
+    where T: fmt::Debug,
+          S: Storage<T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+impl <T, U, S, S2> cmp::PartialEq<StorageVec<U, S2>> for StorageVec<T, S>
+    where T: cmp::PartialEq<U>,
+          S: Storage<T>,
+          S2: Storage<U>
+{
+    fn eq(&self, other: &StorageVec<U, S2>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl <T, U, S> cmp::PartialEq<[U]> for StorageVec<T, S>
+    where T: cmp::PartialEq<U>,
+          S: Storage<T>
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl <'b, T, U, S> cmp::PartialEq<&'b [U]> for StorageVec<T, S>
+    where T: cmp::PartialEq<U>,
+          S: Storage<T>
+{
+    fn eq(&self, other: &&'b [U]) -> bool {
+        self.as_slice() == *other
+    }
+}
+
+impl <T, U, S, const M: usize> cmp::PartialEq<[U; M]> for StorageVec<T, S>
+    where T: cmp::PartialEq<U>,
+          S: Storage<T>
+{
+    fn eq(&self, other: &[U; M]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl <T, U, S> cmp::PartialEq<StorageVec<U, S>> for [T]
+    where T: cmp::PartialEq<U>,
+          S: Storage<U>
+{
+    fn eq(&self, other: &StorageVec<U, S>) -> bool {
+        self == other.as_slice()
+    }
+}
+
+impl <T, U, S> cmp::PartialEq<StorageVec<U, S>> for &[T]
+    where T: cmp::PartialEq<U>,
+          S: Storage<U>
+{
+    fn eq(&self, other: &StorageVec<U, S>) -> bool {
+        *self == other.as_slice()
+    }
+}
+
+#[cfg(feature = "std")]
+impl <T, U, S> cmp::PartialEq<::std::vec::Vec<U>> for StorageVec<T, S>
+    where T: cmp::PartialEq<U>,
+          S: Storage<T>
+{
+    fn eq(&self, other: &::std::vec::Vec<U>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+#[cfg(feature = "std")]
+impl <T, U, S> cmp::PartialEq<StorageVec<U, S>> for ::std::vec::Vec<T>
+    where T: cmp::PartialEq<U>,
+          S: Storage<U>
+{
+    fn eq(&self, other: &StorageVec<U, S>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl <T, S> cmp::Eq for StorageVec<T, S>
+    where T: cmp::Eq,
+          S: Storage<T>
+{}
+
+impl <T, S, S2> cmp::PartialOrd<StorageVec<T, S2>> for StorageVec<T, S>
+    where T: cmp::PartialOrd,
+          S: Storage<T>,
+          S2: Storage<T>
+{
+    fn partial_cmp(&self, other: &StorageVec<T, S2>) -> Option<cmp::Ordering> {
+        self.as_slice().partial_cmp(other.as_slice())
+    }
+}
+
+impl <T, S> cmp::Ord for StorageVec<T, S>
+    where T: cmp::Ord,
+          S: Storage<T>
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+// This must hash the same as `[T]`, since we implement `Borrow<[T]>`.
+impl <T, S> hash::Hash for StorageVec<T, S>
+    where T: hash::Hash,
+          S: Storage<T>
+{
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+impl <T, S> AsRef<[T]> for StorageVec<T, S>
+    where S: Storage<T>
+{
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl <T, S> AsMut<[T]> for StorageVec<T, S>
+    where S: Storage<T>
+{
+    fn as_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl <T, S> borrow::Borrow<[T]> for StorageVec<T, S>
+    where S: Storage<T>
+{
+    fn borrow(&self) -> &[T] {
+        self
+    }
+}
+
+impl <T, S> borrow::BorrowMut<[T]> for StorageVec<T, S>
+    where S: Storage<T>
+{
+    fn borrow_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+impl <T, I, S> ops::Index<I> for StorageVec<T, S>
+    where I: slice::SliceIndex<[T]>,
+          S: Storage<T>
+{
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &I::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl <T, I, S> ops::IndexMut<I> for StorageVec<T, S>
+    where I: slice::SliceIndex<[T]>,
+          S: Storage<T>
+{
+    fn index_mut(&mut self, index: I) -> &mut I::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+// ----- IntoIter & Traits ------------------------------------------------------
+
+// See `StorageVec::into_iter()`
+//
+// The iterators below go through the vec by index, and look up its pointer on
+// each step, since inline storage moves whenever the vec does.
+pub struct IntoIter<T, S>
+    where S: Storage<T>
+{
+    vec:   StorageVec<T, S>, // Holds the items, but with a length of 0.
+    start: usize,            // Index of the next item.
+    end:   usize,            // Index past the next_back item.
+}
+
+impl <T, S> IntoIter<T, S>
+    where S: Storage<T>
+{
+    /// Returns the items that have not been yielded yet.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe {
+            slice::from_raw_parts(self.vec.as_ptr().add(self.start),
+                                  self.end - self.start)
+        }
+    }
+}
+
+impl <T, S> Drop for IntoIter<T, S>
+    where S: Storage<T>
+{
+    fn drop(&mut self) {
+        // Drop all remaining items
+        for _ in &mut *self {}
+    }
+}
+
+impl <T, S> iter::Iterator for IntoIter<T, S>
+    where S: Storage<T>
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.start += 1;
+        unsafe {
+            Some(ptr::read(self.vec.as_ptr().add(self.start - 1)))
+        }
+    }
+}
+
+impl <T, S> iter::DoubleEndedIterator for IntoIter<T, S>
+    where S: Storage<T>
+{
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        unsafe {
+            Some(ptr::read(self.vec.as_ptr().add(self.end)))
+        }
+    }
+}
+
+impl <T, S> iter::ExactSizeIterator for IntoIter<T, S>
+    where S: Storage<T>
+{}
+
+impl <T, S> iter::FusedIterator for IntoIter<T, S>
+    where S: Storage<T>
+{}
+
+// ----- Drain & Traits ---------------------------------------------------------
+
+// See `StorageVec::drain()`
+pub struct Drain<'a, T: 'a, S: 'a>
+    where S: Storage<T>
+{
+    vec:        &'a mut StorageVec<T, S>, // Its length only covers the head.
+    start:      usize,                    // Index of the next drained elem.
+    end:        usize,                    // Index past the next_back one.
+    tail_start: usize,                    // Index of the first elem after.
+    tail_len:   usize,                    // Count of elems after the range.
+}
+
+impl <'a, T, S> Drain<'a, T, S>
+    where S: Storage<T>
+{
+    /// Returns the drained elems that have not been yielded yet.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe {
+            slice::from_raw_parts(self.vec.as_ptr().add(self.start),
+                                  self.end - self.start)
+        }
+    }
+
+    // Moves the tail down to the end of the head. Only called once the
+    // drained range is empty.
+    fn close_gap(&mut self) {
+        let start = self.vec.len;
+        unsafe {
+            if self.tail_start != start {
+                let ptr = self.vec.as_mut_ptr();
+                ptr::copy(ptr.add(self.tail_start),
+                          ptr.add(start),
+                          self.tail_len);
+            }
+        }
+        self.tail_start = start;
+        self.vec.len = start + self.tail_len;
+    }
+
+    // Moves items from `items` into the gap before the tail. Returns `false`
+    // if `items` ran out first.
+    fn fill<I>(&mut self, items: &mut I) -> bool
+        where I: Iterator<Item = T>
+    {
+        while self.vec.len < self.tail_start {
+            match items.next() {
+                Some(item) => unsafe {
+                    ptr::write(self.vec.as_mut_ptr().add(self.vec.len), item);
+                    self.vec.len += 1;
+                },
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Moves the tail up by `additional` spots, asking the storage for more
+    // room first. Returns `false` if it has none.
+    fn widen_gap(&mut self, additional: usize) -> bool {
+        let used = self.tail_start + self.tail_len;
+        if self.vec.storage.try_reserve(used, additional).is_err() {
+            return false;
+        }
+        unsafe {
+            let ptr = self.vec.as_mut_ptr();
+            ptr::copy(ptr.add(self.tail_start),
+                      ptr.add(self.tail_start + additional),
+                      self.tail_len);
+        }
+        self.tail_start += additional;
+        true
+    }
+}
+
+impl <'a, T, S> iter::Iterator for Drain<'a, T, S>
+    where S: Storage<T>
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.start += 1;
+        unsafe {
+            Some(ptr::read(self.vec.as_ptr().add(self.start - 1)))
+        }
+    }
+}
+
+impl <'a, T, S> iter::DoubleEndedIterator for Drain<'a, T, S>
+    where S: Storage<T>
+{
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        unsafe {
+            Some(ptr::read(self.vec.as_ptr().add(self.end)))
+        }
+    }
+}
+
+impl <'a, T, S> iter::ExactSizeIterator for Drain<'a, T, S>
+    where S: Storage<T>
+{}
+
+impl <'a, T, S> iter::FusedIterator for Drain<'a, T, S>
+    where S: Storage<T>
+{}
+
+impl <'a, T, S> Drop for Drain<'a, T, S>
+    where S: Storage<T>
+{
+    fn drop(&mut self) {
+        // Moves the tail back even if dropping an item panics.
+        struct CloseGap<'r, 'a: 'r, T: 'a, S: 'a>(&'r mut Drain<'a, T, S>)
+            where S: Storage<T>;
+
+        impl <'r, 'a, T, S> Drop for CloseGap<'r, 'a, T, S>
+            where S: Storage<T>
+        {
+            fn drop(&mut self) {
+                self.0.close_gap();
+            }
+        }
+
+        let guard = CloseGap(self);
+        // Drop all remaining items
+        for _ in &mut *guard.0 {}
+    }
+}
+
+// ----- Splice & Traits --------------------------------------------------------
+
+// See `StorageVec::splice()`
+pub struct Splice<'a, I, S: 'a>
+    where I: Iterator + 'a,
+          I::Item: 'a,
+          S: Storage<I::Item>
+{
+    drain:        Drain<'a, I::Item, S>,
+    replace_with: I,
+}
+
+impl <'a, I, S> iter::Iterator for Splice<'a, I, S>
+    where I: Iterator,
+          S: Storage<I::Item>
+{
+    type Item = I::Item;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.drain.size_hint()
+    }
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.drain.next()
+    }
+}
+
+impl <'a, I, S> iter::DoubleEndedIterator for Splice<'a, I, S>
+    where I: Iterator,
+          S: Storage<I::Item>
+{
+    fn next_back(&mut self) -> Option<I::Item> {
+        self.drain.next_back()
+    }
+}
+
+impl <'a, I, S> iter::ExactSizeIterator for Splice<'a, I, S>
+    where I: Iterator,
+          S: Storage<I::Item>
+{}
+
+impl <'a, I, S> Drop for Splice<'a, I, S>
+    where I: Iterator,
+          S: Storage<I::Item>
+{
+    fn drop(&mut self) {
+        // Drop whatever is left of the replaced range.
+        for _ in &mut self.drain {}
+
+        // Room for `len()` items was made up front, so this normally stops on
+        // the first pass. If `replace_with` yields more than it claimed, the
+        // storage is asked for more room, and whatever doesn't fit is dropped.
+        while self.drain.fill(&mut self.replace_with) {
+            let (lower, _) = self.replace_with.size_hint();
+            if lower > 0 {
+                if !self.drain.widen_gap(lower) {
+                    break;
+                }
+                continue;
+            }
+
+            // Only make room for one more if there really is one.
+            match self.replace_with.next() {
+                Some(item) => {
+                    if !self.drain.widen_gap(1) {
+                        break;
+                    }
+                    self.drain.fill(&mut iter::once(item));
+                },
+                None => break,
+            }
+        }
+        // Dropping `drain` closes whatever gap is left.
+    }
+}
+
+// ----- ExtractIf & Traits -----------------------------------------------------
+
+// See `StorageVec::extract_if()`
+pub struct ExtractIf<'a, T: 'a, S: 'a, F>
+    where S: Storage<T>,
+          F: FnMut(&mut T) -> bool
+{
+    vec:     &'a mut StorageVec<T, S>,
+    idx:     usize, // Index of the next elem to visit.
+    end:     usize, // Index past the last elem to visit.
+    del:     usize, // Count of elems extracted so far.
+    old_len: usize, // The length of the vec before extraction.
+    filter:  F,
+}
+
+impl <'a, T, S, F> iter::Iterator for ExtractIf<'a, T, S, F>
+    where S: Storage<T>,
+          F: FnMut(&mut T) -> bool
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.end - self.idx))
+    }
+
+    fn next(&mut self) -> Option<T> {
+        while self.idx < self.end {
+            unsafe {
+                let cur = self.vec.as_mut_ptr().add(self.idx);
+                let extract = (self.filter)(&mut *cur);
+                // Step past `cur` only once `filter` has returned, so that a
+                // panic keeps it in the vec.
+                self.idx += 1;
+                if extract {
+                    self.del += 1;
+                    return Some(ptr::read(cur));
+                } else if self.del > 0 {
+                    ptr::copy_nonoverlapping(cur, cur.sub(self.del), 1);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl <'a, T, S, F> Drop for ExtractIf<'a, T, S, F>
+    where S: Storage<T>,
+          F: FnMut(&mut T) -> bool
+{
+    fn drop(&mut self) {
+        // Unvisited elems are kept, and move down over the extracted ones.
+        unsafe {
+            if self.del > 0 && self.idx < self.old_len {
+                let ptr = self.vec.as_mut_ptr();
+                ptr::copy(ptr.add(self.idx),
+                          ptr.add(self.idx - self.del),
+                          self.old_len - self.idx);
+            }
+        }
+        self.vec.len = self.old_len - self.del;
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use small_vec::SmallVec;
+    use test_support::Liar;
+
+    use std::vec;
+
+    #[test]
+    fn check_splice() {
+        // With no room to spill into, only the inline storage is there.
+        let mut buf = [0u8; 0];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 6, _>::try_from_iter_in([1, 2, 3, 4, 5], &alloc)
+                                          .expect("SmallVec::try_from_iter_in(..) failed.");
+
+        // Shorter than the range.
+        let removed: vec::Vec<_> = v.splice(1..4, [20])
+                                    .expect("v.splice(..) failed.")
+                                    .collect();
+        assert_eq!(removed, [2, 3, 4]);
+        assert_eq!(v, [1, 20, 5]);
+
+        // Longer than the range, and left unconsumed.
+        mem::drop(v.splice(1..2, [7, 8, 9]).expect("v.splice(..) failed."));
+        assert_eq!(v, [1, 7, 8, 9, 5]);
+
+        // More than the storage has room for.
+        match v.splice(..1, [0, 0, 0]) {
+            Err(Error::AllocErr(_)) => {},
+            Err(err) => panic!("expected AllocErr, got {:?}", err),
+            Ok(_) => panic!("expected AllocErr"),
+        }
+        assert_eq!(v, [1, 7, 8, 9, 5]);
+    }
+
+    #[test]
+    fn check_splice_stops_when_full() {
+        let mut buf = [0u8; 0];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 4, _>::try_from_iter_in([10, 20, 30], &alloc)
+                                          .expect("SmallVec::try_from_iter_in(..) failed.");
+
+        // Only two of the four items fit, and the rest are dropped.
+        mem::drop(v.splice(1..2, Liar(4)).expect("v.splice(..) failed."));
+        assert_eq!(v, [10, 3, 2, 30]);
+    }
+
+    #[test]
+    fn check_extract_if() {
+        let mut buf = [0u8; 0];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 8, _>::try_from_iter_in(1..9, &alloc)
+                                          .expect("SmallVec::try_from_iter_in(..) failed.");
+
+        let evens: vec::Vec<_> = v.extract_if(2..7, |x| *x % 2 == 0).collect();
+        assert_eq!(evens, [4, 6]);
+        assert_eq!(v, [1, 2, 3, 5, 7, 8]);
+
+        // Elems that weren't visited are kept.
+        {
+            let mut extract = v.extract_if(.., |x| {
+                *x *= 10;
+                *x > 20
+            });
+            assert_eq!(extract.next(), Some(30));
+        }
+        assert_eq!(v, [10, 20, 5, 7, 8]);
+
+        mem::forget(v.extract_if(.., |_| true));
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn check_comparisons() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = SmallVec::<u32, 4, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3]).expect("v.extend_from_slice(..) failed.");
+        let mut w = SmallVec::<u32, 2, _>::try_from_iter_in([1, 2, 3], &alloc)
+                                          .expect("SmallVec::try_from_iter_in(..) failed.");
+
+        // Any two storages compare by their elems.
+        assert!(v == w);
+        let slice: &[u32] = &[1, 2, 3];
+        assert!(v == [1, 2, 3]);
+        assert!(v == slice);
+        assert!(slice == v);
+        assert!(v[..] == w);
+
+        let x = vec_in!(&alloc; 1u32, 2, 3).expect("vec_in!(..) failed.");
+        assert!(v == x);
+        assert!(x == w);
+        #[cfg(feature = "std")]
+        {
+            assert!(v == vec![1, 2, 3]);
+            assert!(vec![1, 2, 3] == w);
+        }
+
+        w.push(0).expect("w.push(0) failed.");
+        assert!(v != w);
+        assert!(v < w);
+        v[2] = 4;
+        assert!(v > w);
+    }
+}
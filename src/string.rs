@@ -0,0 +1,575 @@
+use core::{
+    borrow,
+    cmp,
+    convert,
+    fmt,
+    hash,
+    ops,
+    ptr,
+    result,
+    str,
+};
+
+use alloc_api;
+use boxed::Box;
+use growth::{
+    Doubling,
+    GrowthPolicy,
+};
+use vec2::Vec;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- String Impl ------------------------------------------------------------
+
+/// A growable UTF-8 string with a customizable memory allocator.
+///
+/// It is a `vec2::Vec<u8>` that always holds valid UTF-8, and like `Vec`,
+/// every operation that may allocate returns allocation errors rather than
+/// panicking.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{linear_alloc::LinearAlloc, string::String};
+/// #
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut s = String::from_str_in("Hello", &alloc).unwrap();
+/// s.push_str(", world").unwrap();
+/// s.push('!').unwrap();
+/// assert_eq!(s, "Hello, world!");
+/// ```
+pub struct String<A, G = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    vec: Vec<u8, A, G>, // Always valid UTF-8.
+}
+
+impl <A: alloc_api::Allocator> String<A> {
+    /// Construct a new String using the allocator handle `alloc`.
+    pub fn new(alloc: A) -> Self {
+        String {
+            vec: Vec::new(alloc),
+        }
+    }
+
+    /// Construct a new String with room for at least `capacity` bytes, using
+    /// the allocator handle `alloc`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        Ok(String {
+            vec: Vec::with_capacity(alloc, capacity)?,
+        })
+    }
+
+    /// Construct a new String holding a copy of `s`, using the allocator
+    /// handle `alloc`.
+    pub fn from_str_in(s: &str, alloc: A) -> VecResult<Self> {
+        let mut string = String::with_capacity(alloc, s.len())?;
+        string.push_str(s)?;
+        Ok(string)
+    }
+}
+
+impl <A, G> String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Construct a new String using the allocator handle `alloc`, which grows
+    /// according to the policy `growth`.
+    pub fn with_growth(alloc: A, growth: G) -> Self {
+        String {
+            vec: Vec::with_growth(alloc, growth),
+        }
+    }
+
+    /// Converts a Vec of bytes into a String, if the bytes are valid UTF-8.
+    ///
+    /// On errors, the Vec is handed back along with the `Utf8Error`.
+    pub fn from_utf8(vec: Vec<u8, A, G>)
+        -> result::Result<Self, (Vec<u8, A, G>, str::Utf8Error)>
+    {
+        match str::from_utf8(&vec) {
+            Ok(_)    => Ok(String { vec }),
+            Err(err) => Err((vec, err)),
+        }
+    }
+
+    /// Converts a Vec of bytes into a String, without checking the bytes.
+    ///
+    /// # Safety
+    /// The bytes must be valid UTF-8.
+    pub unsafe fn from_utf8_unchecked(vec: Vec<u8, A, G>) -> Self {
+        String { vec }
+    }
+
+    /// Returns the allocator handle that the String is using.
+    pub fn alloc(&self) -> &A {
+        self.vec.alloc()
+    }
+
+    /// The number of bytes that the String can hold before resizing.
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// The length of the String, in bytes.
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// Returns `true` if the String holds no text.
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// Returns the String's text as a `str`.
+    pub fn as_str(&self) -> &str {
+        self
+    }
+
+    /// Returns the String's text as a mutable `str`.
+    pub fn as_mut_str(&mut self) -> &mut str {
+        self
+    }
+
+    /// Returns the String's UTF-8 bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.vec
+    }
+
+    /// Converts the String into its Vec of UTF-8 bytes.
+    pub fn into_bytes(self) -> Vec<u8, A, G> {
+        self.vec
+    }
+
+    /// Converts the String into a boxed `str`, shrinking its block to fit.
+    ///
+    /// On errors, the String is dropped.
+    pub fn into_boxed_str(self) -> VecResult<Box<str, A>> {
+        let bytes = self.vec.into_boxed_slice()?;
+        let (ptr, alloc) = Box::into_raw_with_alloc(bytes);
+        unsafe {
+            Ok(Box::from_raw_in(ptr as *mut str, alloc))
+        }
+    }
+
+    /// Reserve room for at least `additional` more bytes, returning any
+    /// allocation errors.
+    pub fn try_reserve(&mut self, additional: usize) -> VecResult<()> {
+        self.vec.try_reserve(additional)
+    }
+
+    /// Append `s` to the end of the String, returning any allocation errors.
+    pub fn push_str(&mut self, s: &str) -> VecResult<()> {
+        self.vec.extend_from_slice_copy(s.as_bytes())
+    }
+
+    /// Append `ch` to the end of the String, returning any allocation errors.
+    pub fn push(&mut self, ch: char) -> VecResult<()> {
+        self.push_str(ch.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Removes the last char from the String, and returns it.
+    ///
+    /// Returns `None` if the String is empty.
+    pub fn pop(&mut self) -> Option<char> {
+        let ch = self.chars().next_back()?;
+        let new_len = self.len() - ch.len_utf8();
+        self.vec.truncate(new_len);
+        Some(ch)
+    }
+
+    /// Insert `s` at the byte index `index`, moving any text after it, and
+    /// returning any allocation errors.
+    ///
+    /// # Panics
+    /// Panics if `index` is not on a char boundary.
+    pub fn insert_str(&mut self, index: usize, s: &str) -> VecResult<()> {
+        assert!(self.is_char_boundary(index));
+        self.vec.try_reserve(s.len())?;
+
+        let len = self.len();
+        unsafe {
+            let base = self.vec.ptr();
+            ptr::copy(base.add(index), base.add(index + s.len()), len - index);
+            ptr::copy_nonoverlapping(s.as_ptr(), base.add(index), s.len());
+            self.vec.set_len(len + s.len());
+        }
+        Ok(())
+    }
+
+    /// Insert `ch` at the byte index `index`, moving any text after it, and
+    /// returning any allocation errors.
+    ///
+    /// # Panics
+    /// Panics if `index` is not on a char boundary.
+    pub fn insert(&mut self, index: usize, ch: char) -> VecResult<()> {
+        self.insert_str(index, ch.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Shortens the String to `new_len` bytes, keeping the allocation.
+    ///
+    /// Does nothing if the String is already no longer than `new_len`.
+    ///
+    /// # Panics
+    /// Panics if `new_len` is not on a char boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len < self.len() {
+            assert!(self.is_char_boundary(new_len));
+            self.vec.truncate(new_len);
+        }
+    }
+
+    /// Removes all text, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.vec.clear()
+    }
+
+    /// Clone the String into a new one, which shares this String's allocator
+    /// and growth policy, returning any allocation errors.
+    pub fn try_clone(&self) -> VecResult<Self>
+        where A: Clone,
+              G: Clone
+    {
+        Ok(String {
+            vec: self.vec.try_clone()?,
+        })
+    }
+
+    /// Append formatted text to the String, returning any allocation errors.
+    ///
+    /// This is what `format_in!` uses. Unlike `fmt::Write::write_fmt`, it
+    /// reports which allocation error stopped the formatting.
+    ///
+    /// # Panics
+    /// Panics if a formatting trait implementation returns an error on its
+    /// own, as `std`'s `format!` does.
+    pub fn try_write_fmt(&mut self, args: fmt::Arguments) -> VecResult<()> {
+        // Keeps the allocation error, which `fmt::Error` can't carry.
+        struct Adapter<'s, A: 's, G: 's>
+            where A: alloc_api::Allocator,
+                  G: GrowthPolicy<A>
+        {
+            string: &'s mut String<A, G>,
+            error:  Option<Error>,
+        }
+
+        impl <'s, A, G> fmt::Write for Adapter<'s, A, G>
+            where A: alloc_api::Allocator,
+                  G: GrowthPolicy<A>
+        {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.string.push_str(s).map_err(|err| {
+                    self.error = Some(err);
+                    fmt::Error
+                })
+            }
+        }
+
+        let mut adapter = Adapter {
+            string: self,
+            error:  None,
+        };
+        match fmt::write(&mut adapter, args) {
+            Ok(()) => Ok(()),
+            Err(_) => match adapter.error {
+                Some(err) => Err(err),
+                None => panic!("a formatting trait implementation returned \
+                                an error"),
+            },
+        }
+    }
+}
+
+// ----- String Traits ----------------------------------------------------------
+
+impl <A, G> ops::Deref for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        unsafe {
+            str::from_utf8_unchecked(&self.vec)
+        }
+    }
+}
+
+impl <A, G> ops::DerefMut for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn deref_mut(&mut self) -> &mut str {
+        unsafe {
+            str::from_utf8_unchecked_mut(&mut self.vec)
+        }
+    }
+}
+
+// Allocation errors become `fmt::Error`. See `String::try_write_fmt` to keep
+// them.
+impl <A, G> fmt::Write for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s).map_err(|_| fmt::Error)
+    }
+
+    fn write_char(&mut self, ch: char) -> fmt::Result {
+        self.push(ch).map_err(|_| fmt::Error)
+    }
+}
+
+impl <A, G> fmt::Display for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl <A, G> fmt::Debug for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl <A, G, B, H> cmp::PartialEq<String<B, H>> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>,
+          B: alloc_api::Allocator,
+          H: GrowthPolicy<B>
+{
+    fn eq(&self, other: &String<B, H>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl <A, G> cmp::PartialEq<str> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl <'b, A, G> cmp::PartialEq<&'b str> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn eq(&self, other: &&'b str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl <A, G> cmp::PartialEq<String<A, G>> for str
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn eq(&self, other: &String<A, G>) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl <A, G> cmp::PartialEq<String<A, G>> for &str
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn eq(&self, other: &String<A, G>) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl <A, G> cmp::Eq for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{}
+
+impl <A, G, B, H> cmp::PartialOrd<String<B, H>> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>,
+          B: alloc_api::Allocator,
+          H: GrowthPolicy<B>
+{
+    fn partial_cmp(&self, other: &String<B, H>) -> Option<cmp::Ordering> {
+        self.as_str().partial_cmp(other.as_str())
+    }
+}
+
+impl <A, G> cmp::Ord for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+// This must hash the same as `str`, since we implement `Borrow<str>`.
+impl <A, G> hash::Hash for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl <A, G> convert::AsRef<str> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl <A, G> convert::AsRef<[u8]> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl <A, G> borrow::Borrow<str> for String<A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use alloc_api::DynAlloc;
+    use linear_alloc::LinearAlloc;
+
+    use std::fmt::Write;
+
+    #[test]
+    fn check_push_and_pop() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = String::new(&alloc);
+
+        s.push_str("ab").expect("s.push_str(..) failed.");
+        s.push('c').expect("s.push(..) failed.");
+        s.push('é').expect("s.push(..) failed.");
+        assert_eq!(s, "abcé");
+        assert_eq!(s.len(), 5);
+
+        assert_eq!(s.pop(), Some('é'));
+        assert_eq!(s.pop(), Some('c'));
+        assert_eq!(s, "ab");
+        s.clear();
+        assert_eq!(s.pop(), None);
+    }
+
+    #[test]
+    fn check_insert_and_truncate() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = String::from_str_in("héllo", &alloc)
+                        .expect("String::from_str_in(..) failed.");
+
+        s.insert_str(0, ">> ").expect("s.insert_str(..) failed.");
+        s.insert(s.len(), '!').expect("s.insert(..) failed.");
+        s.insert_str(4, "ö").expect("s.insert_str(..) failed.");
+        assert_eq!(s, ">> hö\u{e9}llo!");
+
+        s.truncate(100);
+        assert_eq!(s.len(), 12);
+        s.truncate(6);
+        assert_eq!(s, ">> hö");
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_truncate_inside_char() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = String::from_str_in("é", &alloc)
+                        .expect("String::from_str_in(..) failed.");
+        s.truncate(1);
+    }
+
+    #[test]
+    fn check_push_reports_errors() {
+        let mut buf = [0u8; 8];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = String::new(&alloc);
+
+        s.push_str("1234").expect("s.push_str(..) failed.");
+        match s.push_str("56789") {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert_eq!(s, "1234");
+    }
+
+    #[test]
+    fn check_fmt_write_and_display() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut s = String::new(&alloc);
+
+        write!(s, "id-{:02}", 7).expect("write!(..) failed.");
+        assert_eq!(s, "id-07");
+        assert_eq!(format!("[{}]", s), "[id-07]");
+        assert_eq!(format!("{:?}", s), "\"id-07\"");
+    }
+
+    #[test]
+    fn check_format_in() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let s = format_in!(&alloc, "{} + {} = {}", 1, 2, 1 + 2)
+                    .expect("format_in!(..) failed.");
+        assert_eq!(s, "1 + 2 = 3");
+
+        let t = format_in!(DynAlloc::new(&alloc), "{:?}", [1, 2])
+                    .expect("format_in!(..) failed.");
+        assert_eq!(t, "[1, 2]");
+        assert!(s != t);
+
+        let res = format_in!(&alloc, "{:100}", "too wide");
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn check_from_utf8() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let bytes = vec_in!(&alloc; b'o', b'k').expect("vec_in!(..) failed.");
+        let s = String::from_utf8(bytes).expect("String::from_utf8(..) failed.");
+        assert_eq!(s, "ok");
+        assert_eq!(s.into_bytes(), *b"ok");
+
+        let bytes = vec_in!(&alloc; 0xff, b'!').expect("vec_in!(..) failed.");
+        let (bytes, err) = String::from_utf8(bytes)
+                               .expect_err("String::from_utf8(..) succeeded.");
+        assert_eq!(bytes, [0xff, b'!']);
+        assert_eq!(err.valid_up_to(), 0);
+    }
+}
@@ -0,0 +1,709 @@
+//! Thread-safe reference-counted pointers, whose shared value lives in a
+//! customizable memory allocator. See `rc` for the single-threaded version.
+
+use core::{
+    borrow,
+    cmp,
+    convert,
+    fmt,
+    hash,
+    hint,
+    marker::PhantomData,
+    mem,
+    ops,
+    ptr::{self, NonNull},
+    result,
+    sync::atomic::{self, AtomicUsize, Ordering},
+};
+
+use alloc_api;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// Counts past this are treated as overflowed. Reaching it needs far more
+// clones than could fit in memory, unless they are being leaked.
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+// The block shared by every Arc and Weak to a value.
+//
+// While `Arc::get_mut` checks for uniqueness, `weak` is locked by setting it
+// to `usize::MAX`.
+struct ArcInner<T> {
+    strong: AtomicUsize, // Count of Arcs.
+    weak:   AtomicUsize, // Count of Weaks, plus one shared by all the Arcs.
+    value:  T,
+}
+
+// The counts of a block, borrowed without the value. A Weak must only use
+// these, since another thread may be dropping the value.
+struct WeakInner<'a> {
+    strong: &'a AtomicUsize,
+    weak:   &'a AtomicUsize,
+}
+
+// Allocates a block for an ArcInner, without initializing it.
+fn allocate_inner<T, A>(alloc: &A) -> VecResult<NonNull<ArcInner<T>>>
+    where A: alloc_api::Allocator
+{
+    Ok(alloc.allocate(alloc_api::Layout::new::<ArcInner<T>>())?.cast())
+}
+
+// Drops one weak reference to the block at `ptr`, freeing it if that was the
+// last.
+unsafe fn release_weak<T, A>(ptr: NonNull<ArcInner<T>>, alloc: &A)
+    where A: alloc_api::Allocator
+{
+    if (*ptr.as_ptr()).weak.fetch_sub(1, Ordering::Release) == 1 {
+        // Synchronize with every other release, so that their uses of the
+        // block happen before it is freed.
+        atomic::fence(Ordering::Acquire);
+        alloc.deallocate(ptr.cast(), alloc_api::Layout::new::<ArcInner<T>>());
+    }
+}
+
+// Adds one to a strong or weak count, which must already be non-zero.
+fn increment(count: &AtomicUsize) {
+    // Relaxed is enough, since a new reference can only be made from an
+    // existing one, which keeps the block alive.
+    if count.fetch_add(1, Ordering::Relaxed) > MAX_REFCOUNT {
+        count.fetch_sub(1, Ordering::Relaxed);
+        panic!("Arc count overflowed");
+    }
+}
+
+// ----- Arc Impl ---------------------------------------------------------------
+
+/// A thread-safe reference-counted pointer to a value that lives in a
+/// customizable memory allocator.
+///
+/// It differs from `std::sync::Arc` by storing its own allocator handle, like
+/// `boxed::Box` does. Every clone holds a clone of the handle, and the last
+/// one to go frees the value's block through it. Methods are associated
+/// functions, such as `Arc::strong_count(&arc)`, so that they don't shadow
+/// methods of `T`.
+///
+/// An Arc can only be sent to another thread if its allocator handle can.
+/// `&LinearAlloc` can't, but `alloc_api::Global` and `alloc_api::System` can.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{linear_alloc::LinearAlloc, sync::Arc};
+/// #
+/// let mut buf = [0u8; 128];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let a = Arc::try_new_in([1u32, 2, 3], &alloc).unwrap();
+/// let b = a.clone();
+/// assert_eq!(Arc::strong_count(&a), 2);
+/// assert_eq!(b[1], 2);
+///
+/// let weak = Arc::downgrade(&a);
+/// drop((a, b));
+/// assert!(weak.upgrade().is_none());
+/// ```
+pub struct Arc<T, A: alloc_api::Allocator> {
+    ptr:   NonNull<ArcInner<T>>, // The shared block, from `alloc`.
+    alloc: A,
+    _own:  PhantomData<ArcInner<T>>,
+}
+
+unsafe impl <T, A> Send for Arc<T, A>
+    where T: Send + Sync,
+          A: alloc_api::Allocator + Send
+{}
+
+unsafe impl <T, A> Sync for Arc<T, A>
+    where T: Send + Sync,
+          A: alloc_api::Allocator + Sync
+{}
+
+impl <T, A: alloc_api::Allocator> Arc<T, A> {
+    /// Moves `value` into a block from the allocator handle `alloc`,
+    /// returning any allocation errors.
+    pub fn try_new_in(value: T, alloc: A) -> VecResult<Self> {
+        let ptr = allocate_inner(&alloc)?;
+        unsafe {
+            ptr.as_ptr().write(ArcInner {
+                strong: AtomicUsize::new(1),
+                weak:   AtomicUsize::new(1),
+                value,
+            });
+        }
+        Ok(Arc {
+            ptr,
+            alloc,
+            _own:  PhantomData,
+        })
+    }
+
+    /// Returns the allocator handle that the Arc is using.
+    pub fn alloc(this: &Self) -> &A {
+        &this.alloc
+    }
+
+    /// Returns a pointer to the value, which stays valid while any Arc to it
+    /// is alive.
+    pub fn as_ptr(this: &Self) -> *const T {
+        unsafe {
+            ptr::addr_of!((*this.ptr.as_ptr()).value)
+        }
+    }
+
+    /// The number of Arcs to the value. Other threads may change it at any
+    /// time.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Relaxed)
+    }
+
+    /// The number of Weaks to the value. Other threads may change it at any
+    /// time.
+    pub fn weak_count(this: &Self) -> usize {
+        let weak = this.inner().weak.load(Ordering::Relaxed);
+        // Locked by `get_mut`, which only happens while there are no Weaks.
+        if weak == usize::MAX {
+            0
+        } else {
+            weak - 1
+        }
+    }
+
+    /// Returns `true` if both Arcs point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Creates a Weak to the value, which doesn't keep it alive.
+    pub fn downgrade(this: &Self) -> Weak<T, A>
+        where A: Clone
+    {
+        let weak = &this.inner().weak;
+        let mut cur = weak.load(Ordering::Relaxed);
+        loop {
+            // Wait out `get_mut`'s lock.
+            if cur == usize::MAX {
+                hint::spin_loop();
+                cur = weak.load(Ordering::Relaxed);
+                continue;
+            }
+            assert!(cur <= MAX_REFCOUNT, "Arc count overflowed");
+
+            // Acquire synchronizes with `get_mut`'s unlock.
+            match weak.compare_exchange_weak(cur,
+                                             cur + 1,
+                                             Ordering::Acquire,
+                                             Ordering::Relaxed) {
+                Ok(_)    => break,
+                Err(old) => cur = old,
+            }
+        }
+        Weak {
+            ptr:   this.ptr,
+            alloc: this.alloc.clone(),
+        }
+    }
+
+    /// Returns a mutable reference to the value, if this is the only Arc or
+    /// Weak to it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Arc::is_unique(this) {
+            unsafe {
+                Some(&mut (*this.ptr.as_ptr()).value)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the value, cloning it into a new block
+    /// first if other Arcs share it, and returning any allocation errors.
+    ///
+    /// If only Weaks share it, the value is moved into a new block instead,
+    /// and they can no longer upgrade. On allocation errors, the Arc is left
+    /// pointing at the old value, though Weaks may have failed to upgrade
+    /// in the meantime.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, sync::Arc};
+    /// #
+    /// let mut buf = [0u8; 128];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    ///
+    /// let mut a = Arc::try_new_in(5u32, &alloc).unwrap();
+    /// let b = a.clone();
+    /// *Arc::make_mut(&mut a).unwrap() += 1;
+    /// assert_eq!((*a, *b), (6, 5));
+    /// ```
+    pub fn make_mut(this: &mut Self) -> VecResult<&mut T>
+        where T: Clone,
+              A: Clone
+    {
+        // Acquire, to see any writes made through Arcs that were dropped. A
+        // strong count of 0 also keeps Weaks from upgrading while we look.
+        let sole = this.inner()
+                       .strong
+                       .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+                       .is_ok();
+        if !sole {
+            *this = Arc::try_new_in((**this).clone(), this.alloc.clone())?;
+        } else if this.inner().weak.load(Ordering::Relaxed) != 1 {
+            let fresh = match allocate_inner(&this.alloc) {
+                Ok(fresh) => fresh,
+                Err(err)  => {
+                    this.inner().strong.store(1, Ordering::Release);
+                    return Err(err);
+                },
+            };
+            unsafe {
+                let old = this.ptr;
+                fresh.as_ptr().write(ArcInner {
+                    strong: AtomicUsize::new(1),
+                    weak:   AtomicUsize::new(1),
+                    value:  ptr::read(&(*old.as_ptr()).value),
+                });
+                // The old strong count stays at 0, so the Weaks never see the
+                // moved value again.
+                release_weak(old, &this.alloc);
+                this.ptr = fresh;
+            }
+        } else {
+            // Nothing else refers to the value, so put the count back.
+            this.inner().strong.store(1, Ordering::Release);
+        }
+        unsafe {
+            Ok(&mut (*this.ptr.as_ptr()).value)
+        }
+    }
+
+    /// Moves the value out, if this is the only Arc to it. Otherwise, the Arc
+    /// is handed back.
+    ///
+    /// Any Weaks can no longer upgrade.
+    pub fn try_unwrap(this: Self) -> result::Result<T, Self> {
+        if this.inner()
+               .strong
+               .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+               .is_err()
+        {
+            return Err(this);
+        }
+        // Synchronize with the other Arcs' drops, like `Drop` does.
+        atomic::fence(Ordering::Acquire);
+
+        unsafe {
+            let ptr = this.ptr;
+            // We need to use ptr::read to move the alloc out, since Arc
+            // implements Drop (and so we can't destructure it)
+            let alloc = ptr::read(&this.alloc);
+            mem::forget(this);
+
+            let value = ptr::read(&(*ptr.as_ptr()).value);
+            release_weak(ptr, &alloc);
+            Ok(value)
+        }
+    }
+
+    fn inner(&self) -> &ArcInner<T> {
+        unsafe {
+            self.ptr.as_ref()
+        }
+    }
+
+    fn is_unique(this: &Self) -> bool {
+        // Lock the weak count, so no Weak can be upgraded or made while we
+        // check the strong count. It can only be locked while it is 1, which
+        // means there are no Weaks.
+        let inner = this.inner();
+        if inner.weak
+                .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        // Acquire, to see any writes made through Arcs that were dropped.
+        let unique = inner.strong.load(Ordering::Acquire) == 1;
+        // Release, so `downgrade` sees our writes once it takes the count.
+        inner.weak.store(1, Ordering::Release);
+        unique
+    }
+}
+
+// ----- Arc Traits -------------------------------------------------------------
+
+impl <T, A: alloc_api::Allocator> Drop for Arc<T, A> {
+    fn drop(&mut self) {
+        // Release, so this Arc's uses of the value happen before whichever
+        // thread drops it.
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        unsafe {
+            ptr::drop_in_place(ptr::addr_of_mut!((*self.ptr.as_ptr()).value));
+            // Drop the weak reference shared by the Arcs.
+            release_weak(self.ptr, &self.alloc);
+        }
+    }
+}
+
+impl <T, A> Clone for Arc<T, A>
+    where A: alloc_api::Allocator + Clone
+{
+    fn clone(&self) -> Self {
+        increment(&self.inner().strong);
+        Arc {
+            ptr:   self.ptr,
+            alloc: self.alloc.clone(),
+            _own:  PhantomData,
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> ops::Deref for Arc<T, A> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl <T, A> fmt::Debug for Arc<T, A>
+    where T: fmt::Debug,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl <T, A> fmt::Display for Arc<T, A>
+    where T: fmt::Display,
+          A: alloc_api::Allocator
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl <T, A, U, B> cmp::PartialEq<Arc<U, B>> for Arc<T, A>
+    where T: cmp::PartialEq<U>,
+          A: alloc_api::Allocator,
+          B: alloc_api::Allocator
+{
+    fn eq(&self, other: &Arc<U, B>) -> bool {
+        **self == **other
+    }
+}
+
+impl <T, A> cmp::Eq for Arc<T, A>
+    where T: cmp::Eq,
+          A: alloc_api::Allocator
+{}
+
+impl <T, A, U, B> cmp::PartialOrd<Arc<U, B>> for Arc<T, A>
+    where T: cmp::PartialOrd<U>,
+          A: alloc_api::Allocator,
+          B: alloc_api::Allocator
+{
+    fn partial_cmp(&self, other: &Arc<U, B>) -> Option<cmp::Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl <T, A> cmp::Ord for Arc<T, A>
+    where T: cmp::Ord,
+          A: alloc_api::Allocator
+{
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl <T, A> hash::Hash for Arc<T, A>
+    where T: hash::Hash,
+          A: alloc_api::Allocator
+{
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl <T, A: alloc_api::Allocator> convert::AsRef<T> for Arc<T, A> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl <T, A: alloc_api::Allocator> borrow::Borrow<T> for Arc<T, A> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+// ----- Weak & Traits ----------------------------------------------------------
+
+/// A reference to an `Arc`'s value that doesn't keep it alive. See
+/// `Arc::downgrade()`.
+///
+/// It keeps the value's block allocated, but not the value itself.
+pub struct Weak<T, A: alloc_api::Allocator> {
+    ptr:   NonNull<ArcInner<T>>, // The shared block, from `alloc`.
+    alloc: A,
+}
+
+unsafe impl <T, A> Send for Weak<T, A>
+    where T: Send + Sync,
+          A: alloc_api::Allocator + Send
+{}
+
+unsafe impl <T, A> Sync for Weak<T, A>
+    where T: Send + Sync,
+          A: alloc_api::Allocator + Sync
+{}
+
+impl <T, A: alloc_api::Allocator> Weak<T, A> {
+    /// Returns an Arc to the value, or `None` if it has been dropped.
+    pub fn upgrade(&self) -> Option<Arc<T, A>>
+        where A: Clone
+    {
+        let strong = self.inner().strong;
+        let mut cur = strong.load(Ordering::Relaxed);
+        loop {
+            // Never bring a dropped value back.
+            if cur == 0 {
+                return None;
+            }
+            assert!(cur <= MAX_REFCOUNT, "Arc count overflowed");
+
+            // Acquire synchronizes with `make_mut` putting the count back.
+            match strong.compare_exchange_weak(cur,
+                                               cur + 1,
+                                               Ordering::Acquire,
+                                               Ordering::Relaxed) {
+                Ok(_)    => break,
+                Err(old) => cur = old,
+            }
+        }
+        Some(Arc {
+            ptr:   self.ptr,
+            alloc: self.alloc.clone(),
+            _own:  PhantomData,
+        })
+    }
+
+    /// The number of Arcs to the value. Other threads may change it at any
+    /// time.
+    pub fn strong_count(&self) -> usize {
+        self.inner().strong.load(Ordering::Relaxed)
+    }
+
+    /// The number of Weaks to the value, or 0 if it has been dropped. Other
+    /// threads may change it at any time.
+    pub fn weak_count(&self) -> usize {
+        let inner = self.inner();
+        let weak = inner.weak.load(Ordering::Acquire);
+        if inner.strong.load(Ordering::Relaxed) == 0 {
+            0
+        } else {
+            // The Arcs still hold their shared weak reference.
+            weak - 1
+        }
+    }
+
+    /// Returns `true` if both Weaks point to the same block.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+
+    // Only the counts are valid once the value has been dropped, so never
+    // borrow the whole block.
+    fn inner(&self) -> WeakInner<'_> {
+        let ptr = self.ptr.as_ptr();
+        unsafe {
+            WeakInner {
+                strong: &*ptr::addr_of!((*ptr).strong),
+                weak:   &*ptr::addr_of!((*ptr).weak),
+            }
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> Drop for Weak<T, A> {
+    fn drop(&mut self) {
+        unsafe {
+            release_weak(self.ptr, &self.alloc);
+        }
+    }
+}
+
+impl <T, A> Clone for Weak<T, A>
+    where A: alloc_api::Allocator + Clone
+{
+    fn clone(&self) -> Self {
+        increment(self.inner().weak);
+        Weak {
+            ptr:   self.ptr,
+            alloc: self.alloc.clone(),
+        }
+    }
+}
+
+impl <T, A: alloc_api::Allocator> fmt::Debug for Weak<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use linear_alloc::LinearAlloc;
+    use test_support::DropCounter;
+
+    use std::{
+        cell::Cell,
+        mem,
+        string::String,
+    };
+
+    #[test]
+    fn check_counts() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let a = Arc::try_new_in(String::from("hi"), &alloc).expect("Arc::try_new_in(..) failed.");
+        let b = a.clone();
+        let w = Arc::downgrade(&b);
+        assert_eq!((Arc::strong_count(&a), Arc::weak_count(&a)), (2, 1));
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(format!("{:?} {}", a, b), "\"hi\" hi");
+
+        mem::drop(a);
+        let c = w.upgrade().expect("w.upgrade() failed.");
+        assert_eq!(c, b);
+        mem::drop((b, c));
+        assert!(w.upgrade().is_none());
+        assert_eq!((w.strong_count(), w.weak_count()), (0, 0));
+    }
+
+    #[test]
+    fn check_frees_block() {
+        // Aligned, so that no padding is left behind.
+        #[repr(align(16))] struct Buffer { buf: [u8; 128] }
+        let mut buf = Buffer { buf: [0u8; 128] };
+        let alloc = LinearAlloc::new(&mut buf.buf);
+        let in_use = alloc.bytes_in_use();
+
+        let a = Arc::try_new_in(7u64, &alloc).expect("Arc::try_new_in(..) failed.");
+        let w = Arc::downgrade(&a);
+        mem::drop(a);
+        // The Weak still holds the block.
+        assert!(alloc.bytes_in_use() > in_use);
+        mem::drop(w);
+        assert_eq!(alloc.bytes_in_use(), in_use);
+    }
+
+    #[test]
+    fn check_get_mut_and_make_mut() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let mut a = Arc::try_new_in(vec![1u32], &alloc).expect("Arc::try_new_in(..) failed.");
+        Arc::get_mut(&mut a).expect("Arc::get_mut(..) failed.").push(2);
+
+        // Shared with another Arc, so it gets cloned.
+        let b = a.clone();
+        assert!(Arc::get_mut(&mut a).is_none());
+        Arc::make_mut(&mut a).expect("Arc::make_mut(..) failed.").push(3);
+        assert_eq!((&a[..], &b[..]), (&[1, 2, 3][..], &[1, 2][..]));
+
+        // Shared with a Weak, so it gets moved.
+        let w = Arc::downgrade(&a);
+        assert!(Arc::get_mut(&mut a).is_none());
+        Arc::make_mut(&mut a).expect("Arc::make_mut(..) failed.").push(4);
+        assert!(w.upgrade().is_none());
+        assert_eq!((Arc::strong_count(&a), Arc::weak_count(&a)), (1, 0));
+
+        // Unshared, so it stays put.
+        let ptr = Arc::as_ptr(&a);
+        Arc::make_mut(&mut a).expect("Arc::make_mut(..) failed.").push(5);
+        assert_eq!(Arc::as_ptr(&a), ptr);
+        assert_eq!(*a, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn check_make_mut_reports_errors() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let mut a = Arc::try_new_in([7u8; 24], &alloc).expect("Arc::try_new_in(..) failed.");
+        let w = Arc::downgrade(&a);
+        let res = Arc::make_mut(&mut a).map(|_| ());
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        // The count is put back, so the Weak still works.
+        assert_eq!(Arc::strong_count(&a), 1);
+        assert!(w.upgrade().is_some());
+    }
+
+    #[test]
+    fn check_try_unwrap_and_drops() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+
+        let a = Arc::try_new_in(DropCounter(&count), &alloc).expect("Arc::try_new_in(..) failed.");
+        let b = a.clone();
+        let a = match Arc::try_unwrap(a) {
+            Err(a) => a,
+            Ok(_)  => panic!("Arc::try_unwrap(..) should fail while shared."),
+        };
+        mem::drop(b);
+        assert_eq!(count.get(), 0);
+
+        let value = Arc::try_unwrap(a).ok().expect("Arc::try_unwrap(..) failed.");
+        mem::drop(value);
+        assert_eq!(count.get(), 1);
+
+        let a = Arc::try_new_in(DropCounter(&count), &alloc).expect("Arc::try_new_in(..) failed.");
+        let w = Arc::downgrade(&a);
+        mem::drop(a);
+        assert_eq!(count.get(), 2);
+        mem::drop(w);
+        assert_eq!(count.get(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_shares_across_threads() {
+        use alloc_api::System;
+        use std::{sync::atomic::AtomicU32, thread, vec::Vec};
+
+        let a = Arc::try_new_in(AtomicU32::new(0), System).expect("Arc::try_new_in(..) failed.");
+        let w = Arc::downgrade(&a);
+        let threads: Vec<_> = (0..4).map(|_| {
+            let a = a.clone();
+            let w = w.clone();
+            thread::spawn(move || {
+                for _ in 0..10 {
+                    a.fetch_add(1, Ordering::Relaxed);
+                    let up = w.upgrade().expect("w.upgrade() failed.");
+                    mem::drop(Arc::downgrade(&up));
+                }
+            })
+        }).collect();
+        for t in threads {
+            t.join().expect("thread panicked.");
+        }
+
+        assert_eq!(a.load(Ordering::Relaxed), 40);
+        mem::drop(w);
+        let mut a = a;
+        assert_eq!(*Arc::get_mut(&mut a).expect("Arc::get_mut(..) failed.").get_mut(), 40);
+    }
+}
@@ -0,0 +1,53 @@
+//! Fixtures shared by the tests of every module.
+
+use proptest::prelude::ProptestConfig;
+use std::{
+    cell::Cell,
+    collections::hash_map::DefaultHasher,
+    hash::BuildHasherDefault,
+};
+
+// Counts how many times it is dropped.
+pub struct DropCounter<'a>(pub &'a Cell<usize>);
+
+impl <'a> Drop for DropCounter<'a> {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+// Builds the same SipHash hasher each time. Without `std`, maps and sets have
+// no `new`, so tests build them with this instead.
+pub type SipState = BuildHasherDefault<DefaultHasher>;
+
+// An iterator that yields more items than its `len()` claims.
+pub struct Liar(pub u32);
+
+impl Iterator for Liar {
+    type Item = u32;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(0))
+    }
+
+    fn next(&mut self) -> Option<u32> {
+        if self.0 == 0 {
+            None
+        } else {
+            self.0 -= 1;
+            Some(self.0)
+        }
+    }
+}
+
+impl ExactSizeIterator for Liar {}
+
+// The config for property tests. Miri is slow, so only spot check there, with
+// `miri_cases` cases.
+pub fn prop_config(miri_cases: u32) -> ProptestConfig {
+    ProptestConfig {
+        cases:               if cfg!(miri) { miri_cases } else { 256 },
+        failure_persistence: None,
+        .. ProptestConfig::default()
+    }
+}
@@ -1,15 +1,18 @@
-use std::{
-    alloc,
-    iter,
-    marker,
+use core::{
     mem,
-    ops,
+    ops::{self, Bound, RangeBounds},
     ptr,
     result,
-    slice,
 };
 
+use alloc_api;
+use growth::{
+    Doubling,
+    GrowthPolicy,
+};
+use boxed::Box;
 use raw_vec::RawVec;
+use storage_vec::{self, StorageVec};
 use Error;
 
 // TODO: Failure crate
@@ -21,389 +24,447 @@ type VecResult<T> = result::Result<T, Error>;
 ///
 /// It differs from `std::vec::Vec` by storing its own allocator instead of
 /// using the global or system allocators.
+/// The allocator is held through the handle type `A`, such as `&LinearAlloc`
+/// or the type-erased `DynAlloc`.
+/// How far it grows when full is picked by the policy `G`, which doubles by
+/// default. See the `growth` module for others.
+/// Every operation that may allocate returns allocation errors rather than
+/// panicking, and Vecs compare by their elems alone, whatever allocators they
+/// use.
+///
+/// It is a `StorageVec` over a `RawVec`. The methods it shares with
+/// `SmallVec` are on `StorageVec`, and those that deal with the allocator
+/// are here.
+///
 /// # Examples
 /// ```rust
-/// # use alloc_utils::vec2::Vec;
+/// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
 /// #
-/// let mut v = Vec::with_system_alloc();
+/// let mut buf = [0u8; 256];
+/// let alloc = LinearAlloc::new(&mut buf);
+/// let mut v = Vec::new(&alloc);
 /// v.extend_from_slice(&[1, 2, 3, 4, 5]);
 /// assert_eq!(v.as_slice(), &[1, 2, 3, 4, 5]);
 ///
-/// let mut drain = v.drain();
+/// let mut drain = v.drain(..);
 /// assert_eq!(drain.next(), Some(1));
 /// assert_eq!(drain.next(), Some(2));
 /// assert_eq!(drain.next(), Some(3));
 /// assert_eq!(drain.next(), Some(4));
 /// assert_eq!(drain.next(), Some(5));
 /// ```
-pub struct Vec<'v, T: 'v> {
-    buf:   RawVec<'v, T>, // Resizeable memory buffer.
-    len:   usize,         // Count of Ts stored.
-}
-
-impl <'v, T> Vec<'v, T> {
-    /// Construct a new Vec
-    pub fn new(alloc: &mut (dyn alloc::Alloc + 'v)) -> Self {
-        Vec {
-            buf: RawVec::new(alloc),
-            len: 0,
-        }
-    }
+pub type Vec<T, A, G = Doubling> = StorageVec<T, RawVec<T, A, G>>;
 
-    /// Construct a new Vec using the system allocator
-    pub fn with_system_alloc() -> Self {
-        Vec {
-            buf: RawVec::with_system_alloc(),
-            len: 0,
-        }
-    }
-
-    /// Returns a pointer to the array of  elements.
-    pub fn ptr(&self) -> *mut T {
-        self.buf.ptr()
+impl <T, A: alloc_api::Allocator> Vec<T, A> {
+    /// Construct a new Vec using the allocator handle `alloc`
+    pub fn new(alloc: A) -> Self {
+        StorageVec::from_storage(RawVec::new(alloc))
     }
 
-    /// The number of items that the Vec can hold before resizing.
-    pub fn capacity(&self) -> usize {
-        self.buf.capacity()
+    /// Construct a new Vec with room for at least `capacity` items, using the
+    /// allocator handle `alloc`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        Ok(StorageVec::from_storage(RawVec::with_capacity(alloc, capacity)?))
     }
 
-    /// The number of items currently in the Vec.
-    pub fn len(&self) -> usize {
-        self.len
+    /// Construct a Vec from its parts, such as those returned by
+    /// `into_raw_parts_with_alloc`.
+    ///
+    /// # Safety
+    ///
+    /// Unless `T` is zero sized or `capacity` is zero, `ptr` must point to a
+    /// block allocated by `alloc`, which `Layout::array::<T>(capacity)` fits.
+    /// Otherwise, it must be non-null and aligned. The first `len` Ts must be
+    /// initialized, and `len` must be no more than `capacity`.
+    pub unsafe fn from_raw_parts_in(ptr:      *mut T,
+                                    len:      usize,
+                                    capacity: usize,
+                                    alloc:    A)
+        -> Self
+    {
+        let buf = RawVec::from_raw_parts_in(ptr, capacity, alloc, Doubling);
+        Vec::from_raw_vec(buf, len)
     }
 
-    /// Move `elem` into the Vec, returning any allocation errors.
+    /// Construct a new Vec holding the items of `iter`, using the allocator
+    /// handle `alloc`, and returning any allocation errors.
     ///
     /// # Examples
     /// ```rust
-    /// # use alloc_utils::{vec2::Vec, Error};
-    /// let mut v = Vec::with_system_alloc();
-    ///
-    /// match v.push(2) {
-    ///     // Inserstion worked: Either no allocation happened, or it worked.
-    ///     Ok(()) => {},
-    ///     // If (re)allocation fails...
-    ///     Err(err) => match err {
-    ///         // Operations with `alloc::Alloc` and `alloc::Layout`
-    ///         // can generate a `alloc::LayoutErr` error.
-    ///         Error::LayoutErr(layout_err) => {
-    ///             println!("layout error: {:?}", layout_err);
-    ///         },
-    ///         // Allocation errors propgate from `alloc::Alloc` as
-    ///         // `alloc::AllocErr`.
-    ///         Error::AllocErr(alloc_err) => {
-    ///             println!("alloc error: {:?}", alloc_err);
-    ///         },
-    ///         // If layout or size calculations overflow, the resize fails.
-    ///         Error::SizeOverflowErr => {
-    ///             println!("Size overflowed trying to allocate");
-    ///         },
-    ///     }
-    /// }
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
     ///
-    /// assert_eq!(v.as_slice(), &[2]);
+    /// let v = Vec::try_from_iter_in((1..5).map(|x| x * x), &alloc).unwrap();
+    /// assert_eq!(v.as_slice(), &[1, 4, 9, 16]);
     /// ```
-    pub fn push(&mut self, elem: T) -> VecResult<()> {
-        if self.len == self.capacity() {
-            self.buf.grow()?;
-        }
-        unsafe {
-            ptr::write(self.ptr().offset(self.len as isize), elem);
-        }
-        self.len += 1;
-        Ok(())
+    pub fn try_from_iter_in<I>(iter: I, alloc: A) -> VecResult<Self>
+        where I: IntoIterator<Item = T>
+    {
+        let mut v = Vec::new(alloc);
+        v.try_extend(iter)?;
+        Ok(v)
     }
+}
 
-    /// Move the elem at the end of the Vec out, returning it.
+impl <T, A, G> Vec<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Construct a new Vec using the allocator handle `alloc`, which grows
+    /// according to the policy `growth`.
     ///
-    /// Returns `None` if the Vec is empty.
-    pub fn pop(&mut self) -> Option<T> {
-        if self.len == 0 {
-            None
-        } else {
-            Some(unsafe {
-                self.len -= 1;
-                ptr::read(self.ptr().offset(self.len as isize))
-            })
-        }
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{growth::Increment, linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 64];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::with_growth(&alloc, Increment(16));
+    ///
+    /// v.push(1u8).unwrap();
+    /// assert_eq!(v.capacity(), 16);
+    /// ```
+    pub fn with_growth(alloc: A, growth: G) -> Self {
+        StorageVec::from_storage(RawVec::with_growth(alloc, growth))
     }
 
-    /// Move `elem` into the Vec at index `index`, moving any elements if needed
-    /// and returning any allocation errors.
-    pub fn insert(&mut self, index: usize, elem: T) -> VecResult<()> {
-        assert!(index <= self.len);
-        if self.len == self.capacity() {
-            self.buf.grow()?;
-        }
-
-        unsafe {
-            if index < self.len {
-                ptr::copy(self.ptr().offset(index as isize),
-                          self.ptr().offset(index as isize + 1),
-                          self.len - index);
-            }
-            ptr::write(self.ptr().offset(index as isize), elem);
-            self.len += 1;
-        }
+    /// Construct a new Vec with room for at least `capacity` items, which
+    /// grows according to the policy `growth`.
+    pub fn with_capacity_and_growth(alloc: A, capacity: usize, growth: G)
+        -> VecResult<Self>
+    {
+        let buf = RawVec::with_capacity_and_growth(alloc, capacity, growth)?;
+        Ok(StorageVec::from_storage(buf))
+    }
 
-        Ok(())
+    // Wraps `buf`, whose first `len` items must be initialized.
+    pub(crate) unsafe fn from_raw_vec(buf: RawVec<T, A, G>, len: usize) -> Self {
+        let mut v = StorageVec::from_storage(buf);
+        v.set_len(len);
+        v
     }
 
-    /// Moves `elem` out of the Vec and shifts all elements over to fill its spot.
-    pub fn remove(&mut self, index: usize) -> T {
-        assert!(index < self.len);
-        let corpse;
-        unsafe {
-            self.len -= 1;
-            corpse = ptr::read(self.ptr().offset(index as isize));
-            ptr::copy(self.ptr().offset(index as isize + 1),
-                      self.ptr().offset(index as isize),
-                      self.len - index);
-        }
-        corpse
+    /// Returns the growth policy that the Vec is using.
+    pub fn growth(&self) -> &G {
+        self.storage.growth()
     }
 
-    /// Returns a slice of the Vec's elements
-    pub fn as_slice(&self) -> &[T] {
-        self
+    /// Returns the allocator handle that the Vec is using.
+    pub fn alloc(&self) -> &A {
+        self.storage.alloc()
     }
 
-    /// Returns the mutable slice of the Vec's elements
-    pub fn as_mut_slice(&mut self) -> &mut [T] {
-        self
+    /// Returns a pointer to the array of  elements.
+    pub fn ptr(&self) -> *mut T {
+        self.storage.ptr()
     }
 
-    /// Creates a draining iterator that removes elements from the Vec, and then
-    /// yields them.
-    pub fn drain(&mut self) -> Drain<T> {
+    /// Moves the elems from `at` onwards into a new Vec, which shares this
+    /// Vec's allocator and growth policy.
+    ///
+    /// On allocation errors, both Vecs are left untouched.
+    pub fn split_off(&mut self, at: usize) -> VecResult<Self>
+        where A: Clone,
+              G: Clone
+    {
+        assert!(at <= self.len, "`at` out of bounds");
+
+        let count = self.len - at;
+        let mut other = Vec::with_capacity_and_growth(self.alloc().clone(),
+                                                      count,
+                                                      self.growth().clone())?;
         unsafe {
-            let iter = RawValIter::new(&self);
-            self.len = 0;
-            Drain {
-                _vec: marker::PhantomData,
-                iter: iter,
-            }
+            ptr::copy_nonoverlapping(self.ptr().add(at), other.ptr(), count);
         }
+        self.len = at;
+        other.len = count;
+        Ok(other)
     }
-}
 
-impl <'v, T> Vec<'v, T>
-    where T: Clone
-{
-    /// Append items to the vector until `push` fails, or `iter` is exhausted.
+    /// Shrinks the allocation to hold at least `min_capacity` elems, and no
+    /// fewer than the Vec holds.
+    ///
+    /// Allocators may not be able to give memory back, in which case the
+    /// capacity is unchanged.
+    pub fn shrink_to(&mut self, min_capacity: usize) -> VecResult<()> {
+        let len = self.len;
+        self.storage.shrink_to(min_capacity.max(len))
+    }
+
+    /// Shrinks the allocation to hold only as many elems as the Vec holds.
+    ///
+    /// Allocators may not be able to give memory back, in which case the
+    /// capacity is unchanged.
     ///
     /// # Examples
     /// ```rust
-    /// # use alloc_utils::vec2::Vec;
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
     /// #
-    /// let mut v = Vec::<u32>::with_system_alloc();
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    /// v.try_reserve(10).unwrap();
+    /// v.push(1).unwrap();
     ///
-    /// v.extend_from_slice(&[1, 2, 3, 4]).unwrap();
-    /// assert_eq!(v.as_slice(), &[1, 2, 3, 4]);
+    /// v.shrink_to_fit().unwrap();
+    /// assert!(v.capacity() >= 1);
     /// ```
-    pub fn extend_from_slice(&mut self, slice: &[T]) -> VecResult<()>
-    {
-        for elem in slice {
-            self.push(elem.clone())?;
-        }
-        Ok(())
+    pub fn shrink_to_fit(&mut self) -> VecResult<()> {
+        self.shrink_to(0)
     }
 
-}
-
-// ----- Vec Traits -------------------------------------------------------------
-
-impl <'v, T> Drop for Vec<'v, T> {
-    fn drop(&mut self) {
-        if self.capacity() != 0 {
-            // We must call each destructor.
-            // If T doesn't impl Drop, this loop is optimized out.
-            while let Some(_) = self.pop() {};
+    /// Gives up ownership of the elems, returning a pointer to them, the
+    /// length, the capacity and the allocator handle.
+    ///
+    /// `Vec::from_raw_parts_in` turns these back into a Vec.
+    pub fn into_raw_parts_with_alloc(self) -> (*mut T, usize, usize, A) {
+        let len = self.len;
+        unsafe {
+            // We need to use ptr::read to move the buf out, since it's not
+            // Copy and Vec implements Drop (and so we can't destructure it)
+            let buf = ptr::read(&self.storage);
+            mem::forget(self);
+            let (ptr, cap, alloc) = buf.into_raw_parts();
+            (ptr, len, cap, alloc)
         }
     }
-}
 
-impl <'v, T> ops::Deref for Vec<'v, T> {
-    type Target = [T];
-
-    fn deref(&self) -> &[T] {
+    /// Converts the Vec into a boxed slice in the same allocator, shrinking
+    /// the allocation to fit first.
+    ///
+    /// If the allocator can't shrink the block in place, the elems move to a
+    /// new block of exactly the right size. If that fails, the error is
+    /// returned and the Vec is dropped.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    /// v.extend_from_slice(&[1, 2, 3]).unwrap();
+    ///
+    /// let b = v.into_boxed_slice().unwrap();
+    /// assert_eq!(&*b, &[1, 2, 3]);
+    /// ```
+    pub fn into_boxed_slice(mut self) -> VecResult<Box<[T], A>> {
+        // The Box frees with a layout for exactly `len` elems, so the block
+        // has to be that size, even where the allocator can't shrink it.
+        let len = self.len;
+        self.storage.shrink_to_exact(len)?;
+        let (ptr, len, _, alloc) = self.into_raw_parts_with_alloc();
         unsafe {
-            slice::from_raw_parts(self.ptr(), self.len)
+            Ok(Box::from_raw_in(ptr::slice_from_raw_parts_mut(ptr, len), alloc))
         }
     }
 }
 
-impl <'v, T> ops::DerefMut for Vec<'v, T> {
-    fn deref_mut(&mut self) -> &mut [T] {
+#[cfg(feature = "std")]
+impl <T> Vec<T, alloc_api::Global> {
+    /// Takes over the allocation of a `std::vec::Vec`, without copying.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::vec2::Vec;
+    /// #
+    /// let mut v = Vec::from_std_vec(vec![1, 2, 3]);
+    /// v.push(4).unwrap();
+    ///
+    /// assert_eq!(v.into_std_vec(), vec![1, 2, 3, 4]);
+    /// ```
+    pub fn from_std_vec(vec: ::std::vec::Vec<T>) -> Self {
+        let mut vec = mem::ManuallyDrop::new(vec);
         unsafe {
-            slice::from_raw_parts_mut(self.ptr(), self.len)
+            // std Vecs allocate from `Global`, with an array layout.
+            Vec::from_raw_parts_in(vec.as_mut_ptr(),
+                                   vec.len(),
+                                   vec.capacity(),
+                                   alloc_api::Global)
         }
     }
 }
 
-impl <'v, T> iter::IntoIterator for Vec<'v, T> {
-    type Item = T;
-    type IntoIter = IntoIter<'v, T>;
-
-    fn into_iter(self) -> Self::IntoIter {
+#[cfg(feature = "std")]
+impl <T, G> Vec<T, alloc_api::Global, G>
+    where G: GrowthPolicy<alloc_api::Global>
+{
+    /// Hands the allocation over to a `std::vec::Vec`, without copying.
+    ///
+    /// This is only possible for Vecs using `Global`, since that is what
+    /// `std::vec::Vec` frees its allocation with.
+    pub fn into_std_vec(self) -> ::std::vec::Vec<T> {
+        let (ptr, len, cap, _) = self.into_raw_parts_with_alloc();
         unsafe {
-            // We need to use ptr::read to move the buf out, since it's not Copy
-            // and Vec implements Drop (and so we can't destructure it)
-            let buf  = ptr::read(&self.buf);
-            let iter = RawValIter::new(&self);
-            mem::forget(self);
-
-            IntoIter {
-                _buf: buf,
-                iter: iter
-            }
+            ::std::vec::Vec::from_raw_parts(ptr, len, cap)
         }
     }
 }
 
-// ----- RawValIter & Traits ----------------------------------------------------
-
-// Raw iterator base
-pub struct RawValIter<T> {
-    start:  *const T, // The next item in the iterator
-    end:    *const T, // The next_back item in the iterator
-}
-
-impl <T> RawValIter<T> {
-    // This is unsafe because it has no associated lifetimes.
-    unsafe fn new(slice: &[T]) -> Self {
-        RawValIter {
-            start: slice.as_ptr(),
-            end:   slice.as_ptr().offset(slice.len() as isize),
-        }
+#[cfg(feature = "std")]
+impl <T, A, G> Vec<T, A, G>
+    where T: Clone,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Clones the elems into a new `std::vec::Vec`.
+    ///
+    /// Like `std`'s collections, this panics if allocation fails.
+    pub fn to_std_vec(&self) -> ::std::vec::Vec<T> {
+        self.as_slice().to_vec()
     }
 }
 
-impl <T> iter::Iterator for RawValIter<T> {
-    type Item = T;
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let start = self.start as usize;
-        let end   = self.end   as usize;
-        let len   = (end - start) / mem::size_of::<T>();
-        (len, Some(len))
+#[cfg(feature = "std")]
+impl <T> Vec<T, alloc_api::System> {
+    /// Construct a new Vec using the system allocator
+    pub fn with_system_alloc() -> Self {
+        StorageVec::from_storage(RawVec::with_system_alloc())
     }
+}
 
-    fn next(&mut self) -> Option<T> {
-        if self.start == self.end {
-            None
-        } else {
-            unsafe {
-                let item = ptr::read(self.start);
-                self.start = self.start.offset(1);
-                Some(item)
-            }
-        }
+impl <T, A, G> Vec<T, A, G>
+    where T: Clone,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Clone the Vec into a new one, which shares this Vec's allocator and
+    /// growth policy, returning any allocation errors.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::Vec};
+    /// #
+    /// let mut buf = [0u8; 256];
+    /// let alloc = LinearAlloc::new(&mut buf);
+    /// let mut v = Vec::<u32, _>::new(&alloc);
+    /// v.extend_from_slice(&[1, 2, 3]).unwrap();
+    ///
+    /// let w = v.try_clone().unwrap();
+    /// assert_eq!(v, w);
+    /// ```
+    pub fn try_clone(&self) -> VecResult<Self>
+        where A: Clone,
+              G: Clone
+    {
+        let mut clone = Vec::with_capacity_and_growth(self.alloc().clone(),
+                                                      self.len,
+                                                      self.growth().clone())?;
+        clone.extend_from_slice(self)?;
+        Ok(clone)
     }
 }
 
-impl <T> iter::DoubleEndedIterator for RawValIter<T> {
-    fn next_back(&mut self) -> Option<T> {
-        if self.start == self.end {
-            None
-        } else {
-            unsafe {
-                self.end = self.end.offset(-1);
-                Some(ptr::read(self.end))
-            }
-        }
+/// Collects an iterator into a `Vec` that uses a given allocator.
+///
+/// This is implemented for every iterator.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{linear_alloc::LinearAlloc, vec2::TryCollectIn};
+/// #
+/// let mut buf = [0u8; 256];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let v = "a,b,c".split(',').try_collect_in(&alloc).unwrap();
+/// assert_eq!(v.as_slice(), &["a", "b", "c"]);
+/// ```
+pub trait TryCollectIn: Iterator + Sized {
+    /// Moves every item into a new `Vec` using the allocator handle `alloc`,
+    /// returning any allocation errors.
+    fn try_collect_in<A>(self, alloc: A) -> VecResult<Vec<Self::Item, A>>
+        where A: alloc_api::Allocator
+    {
+        Vec::try_from_iter_in(self, alloc)
     }
 }
 
-impl <T> iter::ExactSizeIterator for RawValIter<T> {}
-
-impl <T> iter::FusedIterator for RawValIter<T> {}
-
-// ----- IntoIter & Traits ------------------------------------------------------
-
-// See `Vec::into_iter()`
-pub struct IntoIter<'v, T: 'v> {
-    _buf: RawVec<'v, T>, // This is unused; we just need it to live.
-    iter: RawValIter<T>,
-}
+impl <I: Iterator> TryCollectIn for I {}
 
-impl <'v, T> Drop for IntoIter<'v, T> {
-    fn drop(&mut self) {
-        // Drop all remaining items
-        for _ in &mut *self {}
-    }
+// Converts `range` into indices, and checks that they fit in `len`.
+pub(crate) fn to_range<R>(range: R, len: usize) -> ops::Range<usize>
+    where R: RangeBounds<usize>
+{
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.checked_add(1)
+                                        .expect("range start overflowed"),
+        Bound::Unbounded        => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.checked_add(1)
+                                    .expect("range end overflowed"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded      => len,
+    };
+    assert!(start <= end, "range starts at {} but ends at {}", start, end);
+    assert!(end <= len, "range end {} is out of bounds for length {}", end, len);
+    start..end
 }
 
-impl <'v, T> iter::Iterator for IntoIter<'v, T> {
-    type Item = T;
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
-    }
+// ----- Vec Traits -------------------------------------------------------------
 
-    fn next(&mut self) -> Option<T> {
-        self.iter.next()
+#[cfg(feature = "std")]
+impl <T> From<::std::vec::Vec<T>> for Vec<T, alloc_api::Global> {
+    fn from(vec: ::std::vec::Vec<T>) -> Self {
+        Vec::from_std_vec(vec)
     }
 }
 
-impl <'v, T> iter::DoubleEndedIterator for IntoIter<'v, T> {
-    fn next_back(&mut self) -> Option<T> {
-        self.iter.next_back()
+#[cfg(feature = "std")]
+impl <T, G> From<Vec<T, alloc_api::Global, G>> for ::std::vec::Vec<T>
+    where G: GrowthPolicy<alloc_api::Global>
+{
+    fn from(vec: Vec<T, alloc_api::Global, G>) -> Self {
+        vec.into_std_vec()
     }
 }
 
-impl <'v, T> iter::ExactSizeIterator for IntoIter<'v, T> {}
-
-impl <'v, T> iter::FusedIterator for IntoIter<'v, T> {}
+// ----- Iterators --------------------------------------------------------------
 
-// ----- Drain & Traits ---------------------------------------------------------
+// See `Vec::into_iter()`
+pub type IntoIter<T, A, G = Doubling> = storage_vec::IntoIter<T, RawVec<T, A, G>>;
 
 // See `Vec::drain()`
-pub struct Drain<'a, 'v: 'a, T: 'v> {
-    _vec: marker::PhantomData<&'a mut Vec<'v, T>>,
-    iter: RawValIter<T>,
-}
-
-impl <'a, 'v, T> iter::Iterator for Drain<'a, 'v, T> {
-    type Item = T;
+pub type Drain<'a, T, A, G = Doubling> = storage_vec::Drain<'a, T, RawVec<T, A, G>>;
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
-    }
-
-    fn next(&mut self) -> Option<T> {
-        self.iter.next()
-    }
-}
-
-impl <'a, 'v, T> iter::DoubleEndedIterator for Drain<'a, 'v, T> {
-    fn next_back(&mut self) -> Option<T> {
-        self.iter.next_back()
-    }
-}
+// See `Vec::splice()`
+pub type Splice<'a, I, A, G = Doubling> =
+    storage_vec::Splice<'a, I, RawVec<<I as Iterator>::Item, A, G>>;
 
-impl <'a, 'v, T> iter::ExactSizeIterator for Drain<'a, 'v, T> {}
-
-impl <'a, 'v, T> iter::FusedIterator for Drain<'a, 'v, T> {}
-
-impl <'a, 'v, T> Drop for Drain<'a, 'v, T> {
-    fn drop(&mut self) {
-        // Drop all remaining items
-        for _ in &mut *self {}
-    }
-}
+// See `Vec::extract_if()`
+pub type ExtractIf<'a, T, A, G, F> =
+    storage_vec::ExtractIf<'a, T, RawVec<T, A, G>, F>;
 
 // ----- Tests ------------------------------------------------------------------
 
 #[cfg(test)]
 mod t {
     use super::*;
+    use boxed;
+    use alloc_api::{
+        Allocator,
+        AllocError,
+        AvailableBytes,
+        DynAlloc,
+        Layout,
+    };
+    use growth::{
+        FillAvailable,
+        Increment,
+        OneAndAHalf,
+    };
     use linear_alloc::LinearAlloc;
+    use test_support::Liar;
 
     use std::{
+        borrow,
         cell,
+        cmp,
         mem,
+        ptr::NonNull,
     };
 
     // A helper type that increments shared data when it is dropped.
@@ -423,8 +484,8 @@ mod t {
     #[test]
     fn check_one_push_works() {
         let mut buf = [0u8; 43]; // Room for 10 u32s, and extra space.
-        let mut alloc = LinearAlloc::new(&mut buf);
-        let mut v = Vec::<u32>::new(&mut alloc);
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
         v.push(1).expect("v.push(1) failed.");
 
         assert_eq!(v.as_slice(), &[1]);
@@ -433,8 +494,8 @@ mod t {
     #[test]
     fn check_many_pushes_all_work() {
         let mut buf = [0u8; 43]; // Room for 10 u32s, and extra space.
-        let mut alloc = LinearAlloc::new(&mut buf);
-        let mut v = Vec::<u32>::new(&mut alloc);
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
         v.push(1).expect("v.push(1) failed.");
         v.push(2).expect("v.push(2) failed.");
         v.push(3).expect("v.push(3) failed.");
@@ -446,49 +507,74 @@ mod t {
 
     #[test]
     fn check_two_vectors_one_alloc() {
-        let mut buf = [0u8; 56];
-        let mut alloc = LinearAlloc::new(&mut buf);
-        let mut v = Vec::<u32>::new(&mut alloc);
-        let mut w = Vec::<u32>::new(&mut alloc);
-
-        println!("[]   {:?}",  alloc.buf());
-
+        // Aligned, so that the blocks start right at the front.
+        #[repr(align(4))] struct Buffer { buf: [u8; 56] }
+        let mut buf = Buffer { buf: [0u8; 56] };
+        let alloc = LinearAlloc::new(&mut buf.buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        let mut w = Vec::<u32, _>::new(&alloc);
+
+        // The buffer, read as u32s.
+        let words = |alloc: &LinearAlloc| -> std::vec::Vec<u32> {
+            let buf = unsafe { alloc.buf() };
+            buf.chunks(4)
+               .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+               .collect()
+        };
+        assert_eq!(words(&alloc), [0; 14]);
+
+        // Each growth leaves the old block behind, since the other vec's
+        // block is on top of it.
         v.push(1).expect("v.push(1) failed.");
-        println!("[1]  {:?}",  alloc.buf());
+        assert_eq!(words(&alloc), [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
         w.push(11).expect("w.push(11) failed.");
-        println!("[11] {:?}",  alloc.buf());
-        println!("");
+        assert_eq!(words(&alloc), [1, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
 
         v.push(2).expect("v.push(2) failed.");
-        println!("[2]  {:?}",  alloc.buf());
+        assert_eq!(words(&alloc), [1, 11, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
         w.push(22).expect("w.push(22) failed.");
-        println!("[22] {:?}",  alloc.buf());
-        println!("");
+        assert_eq!(words(&alloc), [1, 11, 1, 2, 11, 22, 0, 0, 0, 0, 0, 0, 0, 0]);
 
         v.push(3).expect("v.push(3) failed.");
-        println!("[3]  {:?}",  alloc.buf());
+        assert_eq!(words(&alloc), [1, 11, 1, 2, 11, 22, 1, 2, 3, 0, 0, 0, 0, 0]);
         w.push(33).expect("w.push(33) failed.");
-        println!("[33] {:?}",  alloc.buf());
-        println!("");
+        assert_eq!(words(&alloc), [1, 11, 1, 2, 11, 22, 1, 2, 3, 0, 11, 22, 33, 0]);
 
+        // Both have room for a fourth item.
         v.push(4).expect("v.push(4) failed.");
-        println!("[4]  {:?}",  alloc.buf());
+        assert_eq!(words(&alloc), [1, 11, 1, 2, 11, 22, 1, 2, 3, 4, 11, 22, 33, 0]);
         w.push(44).expect("w.push(44) failed.");
-        println!("[44] {:?}",  alloc.buf());
-        println!("");
+        assert_eq!(words(&alloc), [1, 11, 1, 2, 11, 22, 1, 2, 3, 4, 11, 22, 33, 44]);
 
         assert_eq!(&[1, 2, 3, 4],     v.as_slice());
         assert_eq!(&[11, 22, 33, 44], w.as_slice());
     }
 
+    #[test]
+    fn check_two_vectors_dyn_alloc() {
+        let mut buf = [0u8; 56 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(DynAlloc::new(&alloc));
+        let mut w = Vec::<u32, _>::new(DynAlloc::new(&alloc));
+
+        for i in 1..5 {
+            v.push(i).expect("v.push(i) failed.");
+            w.push(11 * i).expect("w.push(11 * i) failed.");
+        }
+
+        assert_eq!(&[1, 2, 3, 4],     v.as_slice());
+        assert_eq!(&[11, 22, 33, 44], w.as_slice());
+        assert!(alloc.bytes_in_use() > 0);
+    }
+
     #[test]
     fn check_drop_called() {
-        let mut buf = [0u8; 128];
-        let mut alloc = LinearAlloc::new(&mut buf);
+        let mut buf = [0u8; 128 + 7]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
 
         let data = &cell::RefCell::new(0);
 
-        let mut v = Vec::<DropMe>::new(&mut alloc);
+        let mut v = Vec::<DropMe, _>::new(&alloc);
         v.push(DropMe { data }).expect("push(..) failed.");
         v.push(DropMe { data }).expect("push(..) failed.");
         v.push(DropMe { data }).expect("push(..) failed.");
@@ -504,19 +590,82 @@ mod t {
         assert_eq!(*data.borrow(), 9);
     }
 
+    // A zero sized helper type that counts its drops.
+    struct DropZst;
+
+    thread_local! {
+        static ZST_DROPS: cell::Cell<usize> = const { cell::Cell::new(0) };
+    }
+
+    impl Drop for DropZst {
+        fn drop(&mut self) {
+            ZST_DROPS.with(|drops| drops.set(drops.get() + 1));
+        }
+    }
+
+    #[test]
+    fn check_zst_never_allocates() {
+        let mut buf = [0u8; 1];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<(), _>::new(&alloc);
+
+        assert_eq!(v.capacity(), usize::MAX);
+        for _ in 0..1000 {
+            v.push(()).expect("v.push(()) failed.");
+        }
+        v.insert(10, ()).expect("v.insert(10, ()) failed.");
+        assert_eq!(v.len(), 1001);
+        assert_eq!(v.remove(0), ());
+        assert_eq!(v.pop(), Some(()));
+        assert_eq!(v.len(), 999);
+
+        assert_eq!(alloc.high_water_mark(), 0);
+    }
+
+    #[test]
+    fn check_zst_iteration() {
+        let mut buf = [0u8; 1];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<DropZst, _>::new(&alloc);
+        for _ in 0..10 {
+            v.push(DropZst).expect("v.push(DropZst) failed.");
+        }
+
+        {
+            let mut drain = v.drain(..);
+            assert_eq!(drain.size_hint(), (10, Some(10)));
+            assert!(drain.next().is_some());
+            assert!(drain.next_back().is_some());
+            assert_eq!(drain.len(), 8);
+        }
+        assert_eq!(ZST_DROPS.with(|drops| drops.get()), 10);
+        assert_eq!(v.len(), 0);
+
+        for _ in 0..5 {
+            v.push(DropZst).expect("v.push(DropZst) failed.");
+        }
+        let mut iter = v.into_iter();
+        assert_eq!(iter.len(), 5);
+        assert!(iter.next().is_some());
+        mem::drop(iter);
+        assert_eq!(ZST_DROPS.with(|drops| drops.get()), 15);
+
+        assert_eq!(alloc.high_water_mark(), 0);
+    }
+
     #[test]
     fn check_insert_and_remove() {
         let mut buf = [0u8; 128];
-        let mut alloc = LinearAlloc::new(&mut buf);
-        let mut v = Vec::<u64>::new(&mut alloc);
-
-        v.push(2*1).expect("push(2*1) failed.");
-        v.push(2*2).expect("push(2*2) failed.");
-        v.push(2*3).expect("push(2*3) failed.");
-        v.push(2*4).expect("push(2*4) failed.");
-        v.push(2*5).expect("push(2*5) failed.");
-        v.push(2*6).expect("push(2*6) failed.");
-        v.push(2*7).expect("push(2*7) failed.");
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u64, _>::new(&alloc);
+
+        v.push(2).expect("push(2) failed.");
+        v.push(4).expect("push(4) failed.");
+        v.push(6).expect("push(6) failed.");
+        v.push(8).expect("push(8) failed.");
+        v.push(10).expect("push(10) failed.");
+        v.push(12).expect("push(12) failed.");
+        v.push(14).expect("push(14) failed.");
         assert_eq!(&[2, 4, 6, 8, 10, 12, 14], v.as_slice());
 
         v.insert(3, 1001).expect("v.insert(3, 1001) failed.");
@@ -526,4 +675,891 @@ mod t {
         assert_eq!(&[2, 4, 6, 1001, 10, 12, 14], v.as_slice());
         assert_eq!(corpse, 8);
     }
+
+    // An allocator that rounds every block up to a multiple of 32 bytes, and
+    // reports the extra room to its caller.
+    struct RoundUp<'a>(&'a LinearAlloc<'a>);
+
+    impl <'a> RoundUp<'a> {
+        fn round(layout: Layout) -> Layout {
+            let size = (layout.size() + 31) & !31;
+            Layout::from_size_align(size, layout.align()).unwrap()
+        }
+    }
+
+    unsafe impl <'a> Allocator for RoundUp<'a> {
+        fn allocate(&self, layout: Layout)
+            -> Result<NonNull<[u8]>, AllocError>
+        {
+            self.0.allocate(Self::round(layout))
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.deallocate(ptr, Self::round(layout))
+        }
+
+        unsafe fn grow(&self,
+                       ptr: NonNull<u8>,
+                       old_layout: Layout,
+                       new_layout: Layout)
+            -> Result<NonNull<[u8]>, AllocError>
+        {
+            self.0.grow(ptr, Self::round(old_layout), Self::round(new_layout))
+        }
+    }
+
+    #[test]
+    fn check_reserve_records_capacity() {
+        let mut buf = [0u8; 64 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+
+        v.try_reserve(10).expect("v.try_reserve(10) failed.");
+        assert_eq!(v.capacity(), 10);
+        let in_use = alloc.bytes_in_use();
+
+        // All ten pushes fit in the reservation, so nothing is reallocated.
+        for i in 0..10 {
+            v.push(i).expect("v.push(i) failed.");
+        }
+        assert_eq!(v.capacity(), 10);
+        assert_eq!(alloc.bytes_in_use(), in_use);
+    }
+
+    #[test]
+    fn check_reserve_is_amortized() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 4)
+                        .expect("Vec::with_capacity(&alloc, 4) failed.");
+        v.extend_from_slice(&[1, 2, 3, 4]).expect("extend_from_slice failed.");
+
+        // Already enough room, so this is a no-op.
+        v.reserve(0);
+        assert_eq!(v.capacity(), 4);
+
+        // Growing by one doubles the buffer...
+        v.reserve(1);
+        assert_eq!(v.capacity(), 8);
+
+        // ...unless the request is bigger than that.
+        v.reserve(20);
+        assert_eq!(v.capacity(), 24);
+        assert_eq!(v.as_slice(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn check_reserve_exact() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 4)
+                        .expect("Vec::with_capacity(&alloc, 4) failed.");
+        v.extend_from_slice(&[1, 2, 3]).expect("extend_from_slice failed.");
+
+        v.reserve_exact(1);
+        assert_eq!(v.capacity(), 4);
+        v.reserve_exact(2);
+        assert_eq!(v.capacity(), 5);
+        v.try_reserve_exact(7).expect("v.try_reserve_exact(7) failed.");
+        assert_eq!(v.capacity(), 10);
+        assert_eq!(v.as_slice(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn check_reserve_uses_usable_size() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let round_up = RoundUp(&alloc);
+        let mut v = Vec::<u32, _>::new(&round_up);
+
+        v.push(1).expect("v.push(1) failed.");
+        assert_eq!(v.capacity(), 8);
+        v.try_reserve_exact(9).expect("v.try_reserve_exact(9) failed.");
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(v.as_slice(), &[1]);
+    }
+
+    #[test]
+    fn check_try_reserve_reports_errors() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.push(1).expect("v.push(1) failed.");
+
+        match v.try_reserve(100) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        match v.try_reserve_exact(usize::MAX) {
+            Err(Error::SizeOverflowErr) => {},
+            res => panic!("expected SizeOverflowErr, got {:?}", res),
+        }
+        match v.try_reserve_exact(usize::MAX / 2) {
+            Err(Error::LayoutErr(_)) => {},
+            res => panic!("expected LayoutErr, got {:?}", res),
+        }
+
+        // Failures leave the Vec untouched.
+        assert_eq!(v.capacity(), 1);
+        assert_eq!(v.as_slice(), &[1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_reserve_panics_on_failure() {
+        let mut buf = [0u8; 16];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.reserve(100);
+    }
+
+    #[test]
+    fn check_growth_policies() {
+        let mut buf = [0u8; 1024];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let mut v = Vec::<u32, _, _>::with_growth(&alloc, OneAndAHalf);
+        let mut caps = std::vec::Vec::new();
+        for i in 0..20 {
+            v.push(i).expect("v.push(i) failed.");
+            if caps.last() != Some(&v.capacity()) {
+                caps.push(v.capacity());
+            }
+        }
+        assert_eq!(caps, [1, 2, 3, 4, 6, 9, 13, 19, 28]);
+
+        let mut w = Vec::<u32, _, _>::with_growth(&alloc, Increment(5));
+        let mut caps = std::vec::Vec::new();
+        for i in 0..12 {
+            w.push(i).expect("w.push(i) failed.");
+            if caps.last() != Some(&w.capacity()) {
+                caps.push(w.capacity());
+            }
+        }
+        assert_eq!(caps, [5, 10, 15]);
+    }
+
+    #[test]
+    fn check_fill_available_grows_in_place() {
+        let mut buf = [0u8; 64 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _, _>::with_growth(&alloc, FillAvailable);
+
+        // The first push takes every u32 that fits.
+        v.push(1).expect("v.push(1) failed.");
+        let cap = v.capacity();
+        assert!(cap >= 16);
+        assert!(alloc.available_bytes() < 4);
+
+        for i in 1..cap as u32 {
+            v.push(i + 1).expect("v.push(i + 1) failed.");
+        }
+        assert_eq!(v.capacity(), cap);
+        assert!(v.push(0).is_err());
+    }
+
+    #[test]
+    fn check_growth_falls_back_to_required() {
+        let mut buf = [0u8; 40 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 6)
+                        .expect("Vec::with_capacity(&alloc, 6) failed.");
+        v.extend_from_slice(&[1, 2, 3, 4, 5, 6]).expect("extend failed.");
+
+        // Doubling to 12 won't fit, but 10 still does.
+        v.try_reserve(4).expect("v.try_reserve(4) failed.");
+        assert_eq!(v.capacity(), 10);
+        assert_eq!(v.as_slice(), &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn check_truncate_and_clear() {
+        let mut buf = [0u8; 128 + 7]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let data = &cell::RefCell::new(0);
+
+        let mut v = Vec::<DropMe, _>::new(&alloc);
+        for _ in 0..6 {
+            v.push(DropMe { data }).expect("push(..) failed.");
+        }
+        let cap = v.capacity();
+
+        v.truncate(10);
+        assert_eq!(*data.borrow(), 0);
+        v.truncate(4);
+        assert_eq!(*data.borrow(), 2);
+        assert_eq!(v.len(), 4);
+        v.clear();
+        assert_eq!(*data.borrow(), 6);
+        assert!(v.is_empty());
+        assert_eq!(v.capacity(), cap);
+    }
+
+    // Panics when dropped, if asked to.
+    struct PanicOnDrop(bool);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            if self.0 {
+                panic!("PanicOnDrop");
+            }
+        }
+    }
+
+    #[test]
+    fn check_truncate_panic_drops_rest_once() {
+        let mut buf = [0u8; 128 + 7]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let data = &cell::RefCell::new(0);
+
+        let mut v = Vec::new(&alloc);
+        for i in 0..5 {
+            v.push((PanicOnDrop(i == 2), DropMe { data })).expect("push(..) failed.");
+        }
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.truncate(1);
+        }));
+        assert!(res.is_err());
+        // The elems after the panicking one were still dropped, once each.
+        assert_eq!(*data.borrow(), 4);
+        assert_eq!(v.len(), 1);
+
+        mem::drop(v);
+        assert_eq!(*data.borrow(), 5);
+    }
+
+    #[test]
+    fn check_swap_remove() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4]).expect("extend failed.");
+
+        assert_eq!(v.swap_remove(1), 2);
+        assert_eq!(v.as_slice(), &[1, 4, 3]);
+        assert_eq!(v.swap_remove(2), 3);
+        assert_eq!(v.as_slice(), &[1, 4]);
+    }
+
+    #[test]
+    fn check_retain() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7]).expect("extend failed.");
+
+        v.retain(|&x| x % 2 == 1);
+        assert_eq!(v.as_slice(), &[1, 3, 5, 7]);
+
+        v.retain_mut(|x| {
+            *x *= 10;
+            *x != 30
+        });
+        assert_eq!(v.as_slice(), &[10, 50, 70]);
+    }
+
+    #[test]
+    fn check_retain_panic_leaves_no_holes() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4, 5, 6]).expect("extend failed.");
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            v.retain(|&x| {
+                assert!(x != 4);
+                x % 2 == 1
+            });
+        }));
+        assert!(res.is_err());
+        // Elems before the panic were filtered, the rest are kept as is.
+        assert_eq!(v.as_slice(), &[1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn check_dedup() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 1, 2, 2, 2, 3, 1, 1]).expect("extend failed.");
+
+        v.dedup();
+        assert_eq!(v.as_slice(), &[1, 2, 3, 1]);
+
+        v.extend_from_slice(&[11, 21, 22]).expect("extend failed.");
+        v.dedup_by_key(|x| *x % 10);
+        assert_eq!(v.as_slice(), &[1, 2, 3, 1, 22]);
+    }
+
+    #[test]
+    fn check_resize() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+
+        v.resize(3, 7).expect("v.resize(3, 7) failed.");
+        assert_eq!(v.as_slice(), &[7, 7, 7]);
+        v.resize(1, 0).expect("v.resize(1, 0) failed.");
+        assert_eq!(v.as_slice(), &[7]);
+
+        let mut next = 0;
+        v.resize_with(4, || { next += 1; next })
+         .expect("v.resize_with(4, ..) failed.");
+        assert_eq!(v.as_slice(), &[7, 1, 2, 3]);
+
+        match v.resize(1000, 0) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert_eq!(v.as_slice(), &[7, 1, 2, 3]);
+    }
+
+    #[test]
+    fn check_split_off_and_append() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4, 5]).expect("extend failed.");
+
+        let mut w = v.split_off(2).expect("v.split_off(2) failed.");
+        assert_eq!(v.as_slice(), &[1, 2]);
+        assert_eq!(w.as_slice(), &[3, 4, 5]);
+
+        // Appending from a Vec that uses a different handle type is fine.
+        let mut x = Vec::<u32, _>::new(DynAlloc::new(&alloc));
+        x.extend_from_slice(&[6, 7]).expect("extend failed.");
+        w.append(&mut x).expect("w.append(&mut x) failed.");
+        v.append(&mut w).expect("v.append(&mut w) failed.");
+        assert_eq!(v.as_slice(), &[1, 2, 3, 4, 5, 6, 7]);
+        assert!(w.is_empty());
+        assert!(x.is_empty());
+    }
+
+    #[test]
+    fn check_extend_from_within() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+
+        v.extend_from_within(..2).expect("v.extend_from_within(..2) failed.");
+        assert_eq!(v.as_slice(), &[1, 2, 3, 1, 2]);
+        v.extend_from_within(2..=3).expect("v.extend_from_within(2..=3) failed.");
+        assert_eq!(v.as_slice(), &[1, 2, 3, 1, 2, 3, 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_extend_from_within_out_of_bounds() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        let _ = v.extend_from_within(2..4);
+    }
+
+    #[test]
+    fn check_shrink_to_fit() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 16)
+                        .expect("Vec::with_capacity(&alloc, 16) failed.");
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        let in_use = alloc.bytes_in_use();
+
+        // The block is on top of the stack, so the space is given back.
+        v.shrink_to(8).expect("v.shrink_to(8) failed.");
+        assert_eq!(v.capacity(), 8);
+        v.shrink_to_fit().expect("v.shrink_to_fit() failed.");
+        assert_eq!(v.capacity(), 3);
+        assert_eq!(alloc.bytes_in_use(), in_use - 13 * 4);
+        assert_eq!(v.as_slice(), &[1, 2, 3]);
+
+        v.clear();
+        v.shrink_to_fit().expect("v.shrink_to_fit() failed.");
+        assert_eq!(v.capacity(), 0);
+        // Only the padding that aligned the block is left.
+        assert_eq!(alloc.bytes_in_use(), in_use - 16 * 4);
+    }
+
+    #[test]
+    fn check_drain_range() {
+        let mut buf = [0u8; 256 + 7]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4, 5, 6]).expect("extend failed.");
+
+        {
+            let mut drain = v.drain(1..4);
+            assert_eq!(drain.len(), 3);
+            assert_eq!(drain.next(), Some(2));
+            assert_eq!(drain.next_back(), Some(4));
+        }
+        assert_eq!(v.as_slice(), &[1, 5, 6]);
+
+        assert_eq!(v.drain(2..).collect::<std::vec::Vec<_>>(), [6]);
+        assert_eq!(v.drain(..0).count(), 0);
+        assert_eq!(v.as_slice(), &[1, 5]);
+
+        // The unconsumed part of a drained range is dropped with the Drain.
+        let data = &cell::RefCell::new(0);
+        let mut w = Vec::<DropMe, _>::new(&alloc);
+        for _ in 0..5 {
+            w.push(DropMe { data }).expect("push(..) failed.");
+        }
+        mem::drop(w.drain(1..=3));
+        assert_eq!(*data.borrow(), 3);
+        assert_eq!(w.len(), 2);
+    }
+
+    #[test]
+    fn check_leaked_drain_drops_nothing_twice() {
+        let mut buf = [0u8; 128 + 7]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let data = &cell::RefCell::new(0);
+
+        let mut v = Vec::<DropMe, _>::new(&alloc);
+        for _ in 0..5 {
+            v.push(DropMe { data }).expect("push(..) failed.");
+        }
+        {
+            let mut drain = v.drain(1..3);
+            mem::drop(drain.next());
+            mem::forget(drain);
+        }
+        assert_eq!(*data.borrow(), 1);
+        // Everything from the start of the range on is leaked.
+        assert_eq!(v.len(), 1);
+        mem::drop(v);
+        assert_eq!(*data.borrow(), 2);
+    }
+
+    #[test]
+    fn check_drain_panic_keeps_tail() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<(PanicOnDrop, u32), _>::new(&alloc);
+        for i in 0..6 {
+            v.push((PanicOnDrop(i == 2), i)).expect("push(..) failed.");
+        }
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            mem::drop(v.drain(1..4));
+        }));
+        assert!(res.is_err());
+        // The rest of the range is leaked, but the tail is moved back.
+        let ids: std::vec::Vec<_> = v.iter().map(|&(_, i)| i).collect();
+        assert_eq!(ids, [0, 4, 5]);
+    }
+
+    #[test]
+    fn check_splice() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4, 5]).expect("extend failed.");
+
+        // Shorter than the range.
+        let removed: std::vec::Vec<_> = v.splice(1..4, [20])
+                                         .expect("v.splice(..) failed.")
+                                         .collect();
+        assert_eq!(removed, [2, 3, 4]);
+        assert_eq!(v.as_slice(), &[1, 20, 5]);
+
+        // Longer than the range, and left unconsumed.
+        mem::drop(v.splice(1..2, [7, 8, 9]).expect("v.splice(..) failed."));
+        assert_eq!(v.as_slice(), &[1, 7, 8, 9, 5]);
+
+        // Inserting at the end.
+        mem::drop(v.splice(5.., 10..12).expect("v.splice(..) failed."));
+        assert_eq!(v.as_slice(), &[1, 7, 8, 9, 5, 10, 11]);
+
+        // Removing without replacement.
+        mem::drop(v.splice(..3, None).expect("v.splice(..) failed."));
+        assert_eq!(v.as_slice(), &[9, 5, 10, 11]);
+    }
+
+    #[test]
+    fn check_splice_with_lying_iterator() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[10, 20, 30]).expect("extend failed.");
+
+        mem::drop(v.splice(1..2, Liar(4)).expect("v.splice(..) failed."));
+        assert_eq!(v.as_slice(), &[10, 3, 2, 1, 0, 30]);
+    }
+
+    #[test]
+    fn check_splice_reports_errors() {
+        let mut buf = [0u8; 32];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+
+        match v.splice(1..2, 0..100) {
+            Err(Error::AllocErr(_)) => {},
+            Err(err) => panic!("expected AllocErr, got {:?}", err),
+            Ok(_) => panic!("expected AllocErr"),
+        }
+        assert_eq!(v.as_slice(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn check_extract_if() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).expect("extend failed.");
+
+        let evens: std::vec::Vec<_> = v.extract_if(2..7, |x| *x % 2 == 0)
+                                       .collect();
+        assert_eq!(evens, [4, 6]);
+        assert_eq!(v.as_slice(), &[1, 2, 3, 5, 7, 8]);
+
+        // Elems that weren't visited are kept.
+        {
+            let mut extract = v.extract_if(.., |x| {
+                *x *= 10;
+                *x > 20
+            });
+            assert_eq!(extract.next(), Some(30));
+        }
+        assert_eq!(v.as_slice(), &[10, 20, 5, 7, 8]);
+
+        mem::forget(v.extract_if(.., |_| true));
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn check_try_extend_reserves_once() {
+        let mut buf = [0u8; 64 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+
+        // An exact size hint fills a 16 item buffer with no doubling.
+        v.try_extend(0..16).expect("v.try_extend(0..16) failed.");
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(v.len(), 16);
+        assert_eq!(v[15], 15);
+    }
+
+    #[test]
+    fn check_try_extend_without_size_hint() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.push(1).expect("v.push(1) failed.");
+
+        v.try_extend((2..10).filter(|x| x % 2 == 0))
+         .expect("v.try_extend(..) failed.");
+        assert_eq!(v.as_slice(), &[1, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn check_try_extend_keeps_items_on_error() {
+        let mut buf = [0u8; 16 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+
+        match v.try_extend((0..10).filter(|_| true)) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert_eq!(v.as_slice(), &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn check_try_collect_in() {
+        let mut buf = [0u8; 128];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        let v = (1..4u32).map(|x| x * 10)
+                         .try_collect_in(&alloc)
+                         .expect("try_collect_in(&alloc) failed.");
+        assert_eq!(v.as_slice(), &[10, 20, 30]);
+
+        let w = Vec::try_from_iter_in(v.iter().rev().cloned(), &alloc)
+                    .expect("Vec::try_from_iter_in(..) failed.");
+        assert_eq!(w.as_slice(), &[30, 20, 10]);
+    }
+
+    #[test]
+    fn check_extend_from_slice_copy() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u16, _>::new(&alloc);
+
+        v.extend_from_slice_copy(&[1, 2, 3]).expect("extend failed.");
+        v.extend_from_slice_copy(&[]).expect("extend failed.");
+        v.extend_from_slice_copy(&[4, 5]).expect("extend failed.");
+        assert_eq!(v.as_slice(), &[1, 2, 3, 4, 5]);
+
+        match v.extend_from_slice_copy(&[0; 64]) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        assert_eq!(v.as_slice(), &[1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn check_debug() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        assert_eq!(format!("{:?}", v), "[]");
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        assert_eq!(format!("{:?}", v), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn check_comparisons() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        let mut w = Vec::<u32, _>::new(DynAlloc::new(&alloc));
+        w.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+
+        assert!(v == w);
+        let slice: &[u32] = &[1, 2, 3];
+        assert!(v == [1, 2, 3]);
+        assert!(v == slice);
+        assert!(v[..] == v);
+        assert!(slice == v);
+        #[cfg(feature = "std")]
+        {
+            assert!(v == std::vec![1, 2, 3]);
+            assert!(std::vec![1, 2, 3] == v);
+        }
+
+        w.push(0).expect("w.push(0) failed.");
+        assert!(v != w);
+        assert!(v < w);
+        v[2] = 4;
+        assert!(v > w);
+
+        let mut x = Vec::<u32, _>::new(&alloc);
+        x.extend_from_slice(&[1, 2, 4]).expect("extend failed.");
+        assert_eq!(v.cmp(&x), cmp::Ordering::Equal);
+        x.pop();
+        assert_eq!(v.cmp(&x), cmp::Ordering::Greater);
+    }
+
+    #[test]
+    fn check_hash_matches_slice() {
+        use std::hash::{Hash, Hasher};
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of<H: Hash + ?Sized>(value: &H) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(DynAlloc::new(&alloc));
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+
+        assert_eq!(hash_of(&v), hash_of(&[1u32, 2, 3][..]));
+
+        // Vecs can be looked up in a set by slice.
+        let mut set = std::collections::HashSet::new();
+        set.insert(v);
+        assert!(set.contains(&[1u32, 2, 3][..]));
+    }
+
+    #[test]
+    fn check_index_and_conversions() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3, 4]).expect("extend failed.");
+
+        assert_eq!(v[1], 2);
+        assert_eq!(&v[1..3], &[2, 3]);
+        assert_eq!(&v[..=1], &[1, 2]);
+        v[2..].copy_from_slice(&[30, 40]);
+        assert_eq!(v, [1, 2, 30, 40]);
+
+        let as_ref: &[u32] = v.as_ref();
+        assert_eq!(as_ref, &[1, 2, 30, 40]);
+        v.as_mut()[0] = 10;
+        let borrowed: &[u32] = borrow::Borrow::borrow(&v);
+        assert_eq!(borrowed, &[10, 2, 30, 40]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_index_out_of_bounds() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2]).expect("extend failed.");
+        let _ = &v[1..3];
+    }
+
+    #[test]
+    fn check_iterate_by_reference() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::new(&alloc);
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+
+        for x in &mut v {
+            *x *= 2;
+        }
+        let mut sum = 0;
+        for x in &v {
+            sum += *x;
+        }
+        assert_eq!(sum, 12);
+    }
+
+    #[test]
+    fn check_try_clone() {
+        let mut buf = [0u8; 32 + 3]; // Extra room to align the first block.
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 3)
+                        .expect("Vec::with_capacity(&alloc, 3) failed.");
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        let in_use = alloc.bytes_in_use();
+
+        let w = v.try_clone().expect("v.try_clone() failed.");
+        assert_eq!(v, w);
+        assert_eq!(w.capacity(), 3);
+        assert_eq!(alloc.bytes_in_use(), in_use + 12);
+
+        // There's no room for a third copy.
+        let res = w.try_clone();
+        match res {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn check_into_raw_parts_drops_growth() {
+        // A growth policy that counts how many times it is dropped.
+        struct CountedGrowth<'a> {
+            data: &'a cell::RefCell<u32>,
+        }
+
+        impl <'a, A> GrowthPolicy<A> for CountedGrowth<'a> {
+            fn next_capacity(&self, cap: usize, required: usize, _: usize, _: &A)
+                -> usize
+            {
+                required.max(cap.saturating_mul(2))
+            }
+        }
+
+        impl <'a> Drop for CountedGrowth<'a> {
+            fn drop(&mut self) {
+                *self.data.borrow_mut() += 1;
+            }
+        }
+
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let data = &cell::RefCell::new(0);
+
+        let mut v = Vec::with_growth(&alloc, CountedGrowth { data });
+        v.extend_from_slice(&[1u32, 2, 3]).expect("extend failed.");
+        let (ptr, len, cap, alloc) = v.into_raw_parts_with_alloc();
+        assert_eq!(*data.borrow(), 1);
+
+        let v = unsafe { Vec::from_raw_parts_in(ptr, len, cap, alloc) };
+        assert_eq!(v, [1, 2, 3]);
+    }
+
+    #[test]
+    fn check_into_boxed_slice() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 8)
+                        .expect("Vec::with_capacity(&alloc, 8) failed.");
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        let in_use = alloc.bytes_in_use();
+
+        // The block is on top of the stack, so shrinking gives space back.
+        let b = v.into_boxed_slice().expect("v.into_boxed_slice() failed.");
+        assert_eq!(&*b, &[1, 2, 3]);
+        assert_eq!(alloc.bytes_in_use(), in_use - 5 * 4);
+
+        let v = boxed::Box::into_vec(b);
+        assert_eq!(v, [1, 2, 3]);
+        assert_eq!(v.capacity(), 3);
+        mem::drop(v);
+        assert_eq!(alloc.bytes_in_use(), in_use - 8 * 4);
+    }
+
+    #[test]
+    fn check_into_boxed_slice_buried() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut v = Vec::<u32, _>::with_capacity(&alloc, 8)
+                        .expect("Vec::with_capacity(&alloc, 8) failed.");
+        v.extend_from_slice(&[1, 2, 3]).expect("extend failed.");
+        // Another block on top keeps v's block from shrinking in place.
+        let w = Vec::<u32, _>::with_capacity(&alloc, 2)
+                    .expect("Vec::with_capacity(&alloc, 2) failed.");
+        let in_use = alloc.bytes_in_use();
+
+        // So the elems move to a new block of exactly their size.
+        let b = v.into_boxed_slice().expect("v.into_boxed_slice() failed.");
+        assert_eq!(&*b, &[1, 2, 3]);
+        assert_eq!(alloc.bytes_in_use(), in_use + 3 * 4);
+
+        // Which the Box frees with the right size, so it's reclaimed.
+        mem::drop(b);
+        assert_eq!(alloc.bytes_in_use(), in_use);
+        mem::drop(w);
+    }
+
+    #[test]
+    fn check_zst_into_boxed_slice() {
+        let mut buf = [0u8; 1];
+        let alloc = LinearAlloc::new(&mut buf);
+        let v = vec_in!(&alloc; (); 5).expect("vec_in!(..) failed.");
+
+        let b = v.into_boxed_slice().expect("v.into_boxed_slice() failed.");
+        assert_eq!(b.len(), 5);
+        mem::drop(b);
+        assert_eq!(alloc.high_water_mark(), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_std_vec_round_trip() {
+        let mut v = Vec::from_std_vec(std::vec![1u32, 2, 3]);
+        for i in 4..100 {
+            v.push(i).expect("v.push(i) failed.");
+        }
+        v.shrink_to_fit().expect("v.shrink_to_fit() failed.");
+
+        let w: std::vec::Vec<u32> = v.into();
+        assert_eq!(w, (1..100).collect::<std::vec::Vec<_>>());
+
+        let empty = Vec::<u64, _>::from(std::vec::Vec::new());
+        assert_eq!(empty.capacity(), 0);
+        assert!(empty.into_std_vec().is_empty());
+
+        let zsts = Vec::from_std_vec(std::vec![(); 7]);
+        assert_eq!(zsts.len(), 7);
+        assert_eq!(zsts.into_std_vec().len(), 7);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn check_to_std_vec() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let v = vec_in!(&alloc; 1u32, 2, 3).expect("vec_in!(..) failed.");
+
+        let w = v.to_std_vec();
+        assert_eq!(w, std::vec![1, 2, 3]);
+        assert_eq!(v, w);
+    }
 }
@@ -0,0 +1,1061 @@
+use core::{
+    cmp,
+    fmt,
+    iter,
+    ops::{self, RangeBounds},
+    ptr,
+    result,
+    slice,
+};
+
+use alloc_api;
+use growth::{
+    Doubling,
+    GrowthPolicy,
+};
+use raw_vec::RawVec;
+use vec2::to_range;
+use Error;
+
+type VecResult<T> = result::Result<T, Error>;
+
+// ----- VecDeque Impl ----------------------------------------------------------
+
+/// A double-ended queue, in a growable ring buffer with a customizable memory
+/// allocator.
+///
+/// The items may wrap around the end of the buffer, so they are seen as two
+/// slices. See `as_slices` and `make_contiguous`.
+///
+/// # Examples
+/// ```rust
+/// # use alloc_utils::{linear_alloc::LinearAlloc, vec_deque::VecDeque};
+/// #
+/// let mut buf = [0u8; 64];
+/// let alloc = LinearAlloc::new(&mut buf);
+///
+/// let mut d = VecDeque::new(&alloc);
+/// d.push_back(2u32).unwrap();
+/// d.push_back(3).unwrap();
+/// d.push_front(1).unwrap();
+/// assert_eq!(d.make_contiguous(), &[1, 2, 3]);
+///
+/// assert_eq!(d.pop_front(), Some(1));
+/// assert_eq!(d.pop_back(), Some(3));
+/// assert_eq!(d[0], 2);
+/// ```
+pub struct VecDeque<T, A, G = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    buf:  RawVec<T, A, G>, // Resizeable ring buffer.
+    head: usize,           // Buffer index of the front item.
+    len:  usize,           // Count of Ts stored.
+}
+
+impl <T, A: alloc_api::Allocator> VecDeque<T, A> {
+    /// Construct a new VecDeque using the allocator handle `alloc`.
+    pub fn new(alloc: A) -> Self {
+        VecDeque {
+            buf:  RawVec::new(alloc),
+            head: 0,
+            len:  0,
+        }
+    }
+
+    /// Construct a new VecDeque with room for at least `capacity` items,
+    /// using the allocator handle `alloc`.
+    pub fn with_capacity(alloc: A, capacity: usize) -> VecResult<Self> {
+        Ok(VecDeque {
+            buf:  RawVec::with_capacity(alloc, capacity)?,
+            head: 0,
+            len:  0,
+        })
+    }
+}
+
+impl <T, A, G> VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    /// Construct a new VecDeque using the allocator handle `alloc`, which
+    /// grows according to the policy `growth`.
+    pub fn with_growth(alloc: A, growth: G) -> Self {
+        VecDeque {
+            buf:  RawVec::with_growth(alloc, growth),
+            head: 0,
+            len:  0,
+        }
+    }
+
+    /// Construct a new VecDeque with room for at least `capacity` items,
+    /// using the allocator handle `alloc`, which grows according to the
+    /// policy `growth`.
+    pub fn with_capacity_and_growth(alloc: A, capacity: usize, growth: G)
+        -> VecResult<Self>
+    {
+        Ok(VecDeque {
+            buf:  RawVec::with_capacity_and_growth(alloc, capacity, growth)?,
+            head: 0,
+            len:  0,
+        })
+    }
+
+    /// Returns the allocator handle that the VecDeque is using.
+    pub fn alloc(&self) -> &A {
+        self.buf.alloc()
+    }
+
+    /// The number of items that the VecDeque can hold before resizing.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// The number of items currently in the VecDeque.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the VecDeque holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reserve room for at least `additional` more items, returning any
+    /// allocation errors. May reserve more to avoid frequent reallocations.
+    pub fn try_reserve(&mut self, additional: usize) -> VecResult<()> {
+        let old_cap = self.capacity();
+        self.buf.reserve(self.len, additional)?;
+        self.handle_capacity_increase(old_cap);
+        Ok(())
+    }
+
+    /// Reserve room for exactly `additional` more items, returning any
+    /// allocation errors.
+    pub fn try_reserve_exact(&mut self, additional: usize) -> VecResult<()> {
+        let old_cap = self.capacity();
+        self.buf.reserve_exact(self.len, additional)?;
+        self.handle_capacity_increase(old_cap);
+        Ok(())
+    }
+
+    /// Append `value` to the back of the VecDeque, returning any allocation
+    /// errors.
+    pub fn push_back(&mut self, value: T) -> VecResult<()> {
+        if self.len == self.capacity() {
+            self.try_reserve(1)?;
+        }
+        unsafe {
+            ptr::write(self.slot(self.len), value);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Prepend `value` to the front of the VecDeque, returning any allocation
+    /// errors.
+    pub fn push_front(&mut self, value: T) -> VecResult<()> {
+        if self.len == self.capacity() {
+            self.try_reserve(1)?;
+        }
+        // One spot before the head, wrapping around to the end.
+        self.head = self.to_physical(self.capacity() - 1);
+        unsafe {
+            ptr::write(self.slot(0), value);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the front item and returns it, or `None` if the VecDeque is
+    /// empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = unsafe { ptr::read(self.slot(0)) };
+        self.head = self.to_physical(1);
+        self.len -= 1;
+        Some(item)
+    }
+
+    /// Removes the back item and returns it, or `None` if the VecDeque is
+    /// empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        unsafe {
+            Some(ptr::read(self.slot(self.len)))
+        }
+    }
+
+    /// Returns a reference to the item at `index`, counting from the front,
+    /// or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            unsafe {
+                Some(&*self.slot(index))
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the item at `index`, counting from the
+    /// front, or `None` if it is out of bounds.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            unsafe {
+                Some(&mut *self.slot(index))
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Returns the front item, or `None` if the VecDeque is empty.
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns the front item mutably, or `None` if the VecDeque is empty.
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(0)
+    }
+
+    /// Returns the back item, or `None` if the VecDeque is empty.
+    pub fn back(&self) -> Option<&T> {
+        self.get(self.len.wrapping_sub(1))
+    }
+
+    /// Returns the back item mutably, or `None` if the VecDeque is empty.
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        let index = self.len.wrapping_sub(1);
+        self.get_mut(index)
+    }
+
+    /// Drops the items after the first `len`, keeping the allocation.
+    ///
+    /// Does nothing if the VecDeque holds no more than `len` items.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        unsafe {
+            let (front, back) = self.as_mut_slices();
+            let front_len = front.len();
+            let (front, back) = (front as *mut [T], back as *mut [T]);
+            // Set the length first, in case dropping an item panics.
+            self.len = len;
+            if len > front_len {
+                ptr::drop_in_place(&mut (&mut *back)[len - front_len..]);
+            } else {
+                let _back_guard = DropSlice(back);
+                ptr::drop_in_place(&mut (&mut *front)[len..]);
+            }
+        }
+    }
+
+    /// Drops all items, keeping the allocation.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Returns the items, in order, as the two runs they are stored in.
+    ///
+    /// The second slice is empty unless the items wrap around the end of the
+    /// buffer.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (front, back) = self.slice_lens();
+        unsafe {
+            let ptr = self.buf.ptr();
+            (slice::from_raw_parts(ptr.add(self.head), front),
+             slice::from_raw_parts(ptr, back))
+        }
+    }
+
+    /// Returns the items, in order, as the two mutable runs they are stored
+    /// in.
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        let (front, back) = self.slice_lens();
+        unsafe {
+            let ptr = self.buf.ptr();
+            (slice::from_raw_parts_mut(ptr.add(self.head), front),
+             slice::from_raw_parts_mut(ptr, back))
+        }
+    }
+
+    /// Moves the items so that they are stored in one run, and returns them
+    /// as a slice. This never allocates.
+    pub fn make_contiguous(&mut self) -> &mut [T] {
+        let (front, back) = self.slice_lens();
+        if back != 0 {
+            unsafe {
+                let ptr = self.buf.ptr();
+                // Move the front run down to just after the back run, then
+                // rotate the two runs into order.
+                ptr::copy(ptr.add(self.head), ptr.add(back), front);
+                slice::from_raw_parts_mut(ptr, self.len).rotate_left(back);
+            }
+            self.head = 0;
+        }
+        self.as_mut_slices().0
+    }
+
+    /// Returns an iterator over the items, from front to back.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (front, back) = self.as_slices();
+        Iter {
+            front: front.iter(),
+            back:  back.iter(),
+        }
+    }
+
+    /// Returns an iterator over the items mutably, from front to back.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let (front, back) = self.as_mut_slices();
+        IterMut {
+            front: front.iter_mut(),
+            back:  back.iter_mut(),
+        }
+    }
+
+    /// Creates a draining iterator that removes the items in `range` from the
+    /// VecDeque, and then yields them.
+    ///
+    /// The gap is closed once the `Drain` is dropped, by moving whichever of
+    /// the items before or after the range are fewer. If the `Drain` is
+    /// leaked instead, the drained items and everything after them are leaked
+    /// too, but never dropped twice.
+    ///
+    /// # Panics
+    /// Panics if the range is decreasing, or ends after the last item.
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, T, A, G>
+        where R: RangeBounds<usize>
+    {
+        let ops::Range { start, end } = to_range(range, self.len);
+        let tail_len = self.len - end;
+        // Only the items before the range are safe to use until the gap is
+        // closed.
+        self.len = start;
+        Drain {
+            deque:      self,
+            idx:        start,
+            end,
+            tail_start: end,
+            tail_len,
+        }
+    }
+
+    // Maps `index`, counting from the front, to an index in the buffer.
+    // `index` must be at most the capacity.
+    fn to_physical(&self, index: usize) -> usize {
+        let to_end = self.capacity() - self.head;
+        if index >= to_end {
+            index - to_end
+        } else {
+            self.head + index
+        }
+    }
+
+    // Returns a pointer to the spot for the item at `index`, counting from the
+    // front.
+    unsafe fn slot(&self, index: usize) -> *mut T {
+        self.buf.ptr().add(self.to_physical(index))
+    }
+
+    // The lengths of the runs returned by `as_slices`.
+    fn slice_lens(&self) -> (usize, usize) {
+        let to_end = self.capacity() - self.head;
+        if self.len <= to_end {
+            (self.len, 0)
+        } else {
+            (to_end, self.len - to_end)
+        }
+    }
+
+    // Copies `count` items from the front-relative index `src` to `dst`. The
+    // ranges may overlap, and may wrap around the end of the buffer.
+    unsafe fn copy_items(&mut self, src: usize, dst: usize, count: usize) {
+        if dst > src {
+            for i in (0..count).rev() {
+                ptr::copy_nonoverlapping(self.slot(src + i), self.slot(dst + i), 1);
+            }
+        } else if dst < src {
+            for i in 0..count {
+                ptr::copy_nonoverlapping(self.slot(src + i), self.slot(dst + i), 1);
+            }
+        }
+    }
+
+    // Fixes up a wrapped-around ring after the buffer grew from `old_cap`,
+    // since the new spots sit between the back run and the front run.
+    fn handle_capacity_increase(&mut self, old_cap: usize) {
+        let new_cap = self.capacity();
+        let to_end = old_cap - self.head;
+        if new_cap == old_cap || self.len <= to_end {
+            return;
+        }
+        let back_len = self.len - to_end;
+        unsafe {
+            let ptr = self.buf.ptr();
+            if back_len < to_end && back_len <= new_cap - old_cap {
+                // Move the back run up to just after the front run.
+                ptr::copy_nonoverlapping(ptr, ptr.add(old_cap), back_len);
+            } else {
+                // Move the front run up to the end of the buffer.
+                let new_head = new_cap - to_end;
+                ptr::copy(ptr.add(self.head), ptr.add(new_head), to_end);
+                self.head = new_head;
+            }
+        }
+    }
+}
+
+// Drops the items of a slice when it goes out of scope, so that one panicking
+// `drop_in_place` still drops the other run.
+struct DropSlice<T>(*mut [T]);
+
+impl <T> Drop for DropSlice<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.0);
+        }
+    }
+}
+
+// ----- VecDeque Traits --------------------------------------------------------
+
+impl <T, A, G> Drop for VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn drop(&mut self) {
+        // The RawVec frees the buffer.
+        self.clear();
+    }
+}
+
+impl <T, A, G> ops::Index<usize> for VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len;
+        self.get(index).unwrap_or_else(|| {
+            panic!("index {} is out of bounds for length {}", index, len)
+        })
+    }
+}
+
+impl <T, A, G> ops::IndexMut<usize> for VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len;
+        self.get_mut(index).unwrap_or_else(|| {
+            panic!("index {} is out of bounds for length {}", index, len)
+        })
+    }
+}
+
+impl <T, A, G> iter::IntoIterator for VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, A, G>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            deque: self,
+        }
+    }
+}
+
+impl <'a, T, A, G> iter::IntoIterator for &'a VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl <'a, T, A, G> iter::IntoIterator for &'a mut VecDeque<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl <T, A, G> fmt::Debug for VecDeque<T, A, G>
+    where T: fmt::Debug,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+// VecDeques compare by their items alone, however they are laid out.
+impl <T, A, G, U, B, H> cmp::PartialEq<VecDeque<U, B, H>> for VecDeque<T, A, G>
+    where T: cmp::PartialEq<U>,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>,
+          B: alloc_api::Allocator,
+          H: GrowthPolicy<B>
+{
+    fn eq(&self, other: &VecDeque<U, B, H>) -> bool {
+        self.len == other.len && self.iter().zip(other).all(|(a, b)| a == b)
+    }
+}
+
+impl <T, A, G> cmp::Eq for VecDeque<T, A, G>
+    where T: cmp::Eq,
+          A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{}
+
+// ----- Iter & Traits ----------------------------------------------------------
+
+// See `VecDeque::iter()`
+pub struct Iter<'a, T: 'a> {
+    front: slice::Iter<'a, T>,
+    back:  slice::Iter<'a, T>,
+}
+
+impl <'a, T> iter::Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.front.next().or_else(|| self.back.next())
+    }
+}
+
+impl <'a, T> iter::DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.back.next_back().or_else(|| self.front.next_back())
+    }
+}
+
+impl <'a, T> iter::ExactSizeIterator for Iter<'a, T> {}
+
+impl <'a, T> iter::FusedIterator for Iter<'a, T> {}
+
+// See `VecDeque::iter_mut()`
+pub struct IterMut<'a, T: 'a> {
+    front: slice::IterMut<'a, T>,
+    back:  slice::IterMut<'a, T>,
+}
+
+impl <'a, T> iter::Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.back.len();
+        (len, Some(len))
+    }
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        match self.front.next() {
+            Some(item) => Some(item),
+            None       => self.back.next(),
+        }
+    }
+}
+
+impl <'a, T> iter::DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<&'a mut T> {
+        match self.back.next_back() {
+            Some(item) => Some(item),
+            None       => self.front.next_back(),
+        }
+    }
+}
+
+impl <'a, T> iter::ExactSizeIterator for IterMut<'a, T> {}
+
+impl <'a, T> iter::FusedIterator for IterMut<'a, T> {}
+
+// ----- IntoIter & Traits ------------------------------------------------------
+
+pub struct IntoIter<T, A, G = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    deque: VecDeque<T, A, G>, // Drops the items that are left.
+}
+
+impl <T, A, G> iter::Iterator for IntoIter<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.deque.len, Some(self.deque.len))
+    }
+
+    fn next(&mut self) -> Option<T> {
+        self.deque.pop_front()
+    }
+}
+
+impl <T, A, G> iter::DoubleEndedIterator for IntoIter<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn next_back(&mut self) -> Option<T> {
+        self.deque.pop_back()
+    }
+}
+
+impl <T, A, G> iter::ExactSizeIterator for IntoIter<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{}
+
+impl <T, A, G> iter::FusedIterator for IntoIter<T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{}
+
+// ----- Drain & Traits ---------------------------------------------------------
+
+// See `VecDeque::drain()`
+pub struct Drain<'a, T: 'a, A: 'a, G: 'a = Doubling>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    deque:      &'a mut VecDeque<T, A, G>,
+    idx:        usize, // Index of the next item in the drained range.
+    end:        usize, // Index after the next_back item in the range.
+    tail_start: usize, // Index of the first item after the range.
+    tail_len:   usize, // Count of items after the range.
+}
+
+impl <'a, T, A, G> Drain<'a, T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    // Closes the gap left by the drained range, by moving whichever side of
+    // it is shorter. Only called once the drained range is empty.
+    fn close_gap(&mut self) {
+        let head_len = self.deque.len;
+        let gap = self.tail_start - head_len;
+        unsafe {
+            if head_len < self.tail_len {
+                self.deque.copy_items(0, gap, head_len);
+                self.deque.head = self.deque.to_physical(gap);
+            } else {
+                self.deque.copy_items(self.tail_start, head_len, self.tail_len);
+            }
+        }
+        self.tail_start = head_len;
+        self.deque.len = head_len + self.tail_len;
+    }
+}
+
+impl <'a, T, A, G> iter::Iterator for Drain<'a, T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    type Item = T;
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.idx;
+        (len, Some(len))
+    }
+
+    fn next(&mut self) -> Option<T> {
+        if self.idx == self.end {
+            return None;
+        }
+        let item = unsafe { ptr::read(self.deque.slot(self.idx)) };
+        self.idx += 1;
+        Some(item)
+    }
+}
+
+impl <'a, T, A, G> iter::DoubleEndedIterator for Drain<'a, T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn next_back(&mut self) -> Option<T> {
+        if self.idx == self.end {
+            return None;
+        }
+        self.end -= 1;
+        unsafe {
+            Some(ptr::read(self.deque.slot(self.end)))
+        }
+    }
+}
+
+impl <'a, T, A, G> iter::ExactSizeIterator for Drain<'a, T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{}
+
+impl <'a, T, A, G> iter::FusedIterator for Drain<'a, T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{}
+
+impl <'a, T, A, G> Drop for Drain<'a, T, A, G>
+    where A: alloc_api::Allocator,
+          G: GrowthPolicy<A>
+{
+    fn drop(&mut self) {
+        // Closes the gap even if dropping an item panics.
+        struct CloseGap<'r, 'a: 'r, T: 'a, A: 'a, G: 'a>(&'r mut Drain<'a, T, A, G>)
+            where A: alloc_api::Allocator,
+                  G: GrowthPolicy<A>;
+
+        impl <'r, 'a, T, A, G> Drop for CloseGap<'r, 'a, T, A, G>
+            where A: alloc_api::Allocator,
+                  G: GrowthPolicy<A>
+        {
+            fn drop(&mut self) {
+                self.0.close_gap();
+            }
+        }
+
+        let guard = CloseGap(self);
+        // Drop all remaining items
+        for _ in &mut *guard.0 {}
+    }
+}
+
+// ----- Tests ------------------------------------------------------------------
+
+#[cfg(test)]
+mod t {
+    use super::*;
+    use growth::Increment;
+    use linear_alloc::LinearAlloc;
+    use test_support::{DropCounter, prop_config};
+
+    use proptest::prelude::*;
+    use std::{
+        cell::Cell,
+        collections,
+        mem,
+        vec,
+    };
+
+    // Builds a deque of capacity `cap` holding `items`, with the front item
+    // at buffer index `head`.
+    fn rotated<'a, 'b>(alloc: &'a LinearAlloc<'b>,
+                       cap:   usize,
+                       head:  usize,
+                       items: &[u32])
+        -> VecDeque<u32, &'a LinearAlloc<'b>, Increment>
+    {
+        let mut d = VecDeque::with_capacity_and_growth(alloc, cap, Increment(1))
+                        .expect("VecDeque::with_capacity_and_growth(..) failed.");
+        assert_eq!(d.capacity(), cap);
+        for _ in 0..head {
+            d.push_back(0).expect("d.push_back(..) failed.");
+            d.pop_front();
+        }
+        for &item in items {
+            d.push_back(item).expect("d.push_back(..) failed.");
+        }
+        assert_eq!(d.head, head % cap);
+        d
+    }
+
+    fn to_vec<T: Clone, A, G>(d: &VecDeque<T, A, G>) -> vec::Vec<T>
+        where A: alloc_api::Allocator,
+              G: GrowthPolicy<A>
+    {
+        d.iter().cloned().collect()
+    }
+
+    #[test]
+    fn check_push_and_pop() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut d = VecDeque::new(&alloc);
+        assert_eq!(d.pop_front(), None);
+        assert_eq!(d.pop_back(), None);
+        assert_eq!(d.front(), None);
+        assert_eq!(d.back(), None);
+
+        for i in 0..4u32 {
+            d.push_back(i).expect("d.push_back(..) failed.");
+            d.push_front(10 + i).expect("d.push_front(..) failed.");
+        }
+        assert_eq!(to_vec(&d), [13, 12, 11, 10, 0, 1, 2, 3]);
+        assert_eq!(d.front(), Some(&13));
+        assert_eq!(d.back(), Some(&3));
+
+        *d.front_mut().expect("d.front_mut() failed.") += 1;
+        *d.back_mut().expect("d.back_mut() failed.") += 1;
+        d[4] = 7;
+        assert_eq!(d.pop_front(), Some(14));
+        assert_eq!(d.pop_back(), Some(4));
+        assert_eq!(to_vec(&d), [12, 11, 10, 7, 1, 2]);
+        assert_eq!(d.len(), 6);
+        assert_eq!(format!("{:?}", d), "[12, 11, 10, 7, 1, 2]");
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_index_out_of_bounds() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let d = rotated(&alloc, 4, 3, &[1, 2]);
+        let _ = d[2];
+    }
+
+    #[test]
+    fn check_grow_while_wrapped() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // The back run is shorter, so it moves after the front run.
+        let mut d = rotated(&alloc, 4, 1, &[1, 2, 3, 4]);
+        assert_eq!(d.as_slices(), (&[1, 2, 3][..], &[4][..]));
+        d.try_reserve_exact(4).expect("d.try_reserve_exact(..) failed.");
+        assert_eq!(d.capacity(), 8);
+        assert_eq!(d.as_slices(), (&[1, 2, 3, 4][..], &[][..]));
+        d.push_back(5).expect("d.push_back(..) failed.");
+        assert_eq!(to_vec(&d), [1, 2, 3, 4, 5]);
+
+        // The front run is shorter, so it moves to the end of the buffer.
+        let mut d = rotated(&alloc, 4, 3, &[1, 2, 3, 4]);
+        d.try_reserve_exact(1).expect("d.try_reserve_exact(..) failed.");
+        assert_eq!(d.capacity(), 5);
+        assert_eq!(d.as_slices(), (&[1][..], &[2, 3, 4][..]));
+        d.push_front(0).expect("d.push_front(..) failed.");
+        assert_eq!(to_vec(&d), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn check_make_contiguous() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        for head in 0..5 {
+            for len in 0..6 {
+                let items: vec::Vec<u32> = (0..len).collect();
+                let mut d = rotated(&alloc, 5, head, &items);
+                assert_eq!(d.make_contiguous(), &items[..]);
+                assert_eq!(d.as_slices(), (&items[..], &[][..]));
+            }
+        }
+    }
+
+    #[test]
+    fn check_iters() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut d = rotated(&alloc, 4, 2, &[1, 2, 3, 4]);
+
+        for item in &mut d {
+            *item *= 10;
+        }
+        assert_eq!(d.iter().len(), 4);
+        assert_eq!(d.iter().rev().cloned().collect::<vec::Vec<_>>(),
+                   [40, 30, 20, 10]);
+
+        let mut iter = d.into_iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next_back(), Some(40));
+        assert_eq!(iter.next(), Some(10));
+        assert_eq!(iter.collect::<vec::Vec<_>>(), [20, 30]);
+    }
+
+    #[test]
+    fn check_drain() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+
+        // Fewer items before the range, so they move up.
+        let mut d = rotated(&alloc, 6, 4, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(d.drain(1..3).collect::<vec::Vec<_>>(), [2, 3]);
+        assert_eq!(to_vec(&d), [1, 4, 5, 6]);
+
+        // Fewer items after the range, so they move down.
+        let mut d = rotated(&alloc, 6, 4, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(d.drain(3..5).rev().collect::<vec::Vec<_>>(), [5, 4]);
+        assert_eq!(to_vec(&d), [1, 2, 3, 6]);
+
+        let mut d = rotated(&alloc, 6, 2, &[1, 2, 3]);
+        mem::drop(d.drain(..));
+        assert!(d.is_empty());
+        d.push_back(7).expect("d.push_back(..) failed.");
+        assert_eq!(to_vec(&d), [7]);
+    }
+
+    #[test]
+    fn check_drops() {
+        let mut buf = [0u8; 256];
+        let alloc = LinearAlloc::new(&mut buf);
+        let count = Cell::new(0);
+
+        let mut d = VecDeque::new(&alloc);
+        for _ in 0..3 {
+            d.push_back(DropCounter(&count)).expect("d.push_back(..) failed.");
+            d.push_front(DropCounter(&count)).expect("d.push_front(..) failed.");
+        }
+
+        // Unyielded items are dropped along with the Drain.
+        let mut drain = d.drain(1..4);
+        mem::drop(drain.next());
+        mem::drop(drain);
+        assert_eq!(count.get(), 3);
+        assert_eq!(d.len(), 3);
+
+        d.truncate(1);
+        assert_eq!(count.get(), 5);
+        mem::drop(d);
+        assert_eq!(count.get(), 6);
+    }
+
+    #[test]
+    fn check_leaked_drain() {
+        let mut buf = [0u8; 64];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut d = rotated(&alloc, 4, 2, &[1, 2, 3, 4]);
+
+        mem::forget(d.drain(1..2));
+        assert_eq!(to_vec(&d), [1]);
+    }
+
+    #[test]
+    fn check_zero_sized() {
+        let mut buf = [0u8; 0];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut d = VecDeque::new(&alloc);
+
+        for _ in 0..10 {
+            d.push_back(()).expect("d.push_back(..) failed.");
+            d.push_front(()).expect("d.push_front(..) failed.");
+        }
+        assert_eq!(d.len(), 20);
+        assert_eq!(d.make_contiguous().len(), 20);
+        assert_eq!(d.drain(5..15).count(), 10);
+        assert_eq!(d.iter().count(), 10);
+        assert_eq!(d.pop_front(), Some(()));
+        assert_eq!(d.pop_back(), Some(()));
+        assert_eq!(d.len(), 8);
+    }
+
+    #[test]
+    fn check_growth_failure_keeps_wrapped_ring() {
+        // Room for four u32s, however the buffer is aligned, but no more.
+        let mut buf = [0u8; 19];
+        let alloc = LinearAlloc::new(&mut buf);
+        let mut d = VecDeque::with_capacity(&alloc, 4)
+                        .expect("VecDeque::with_capacity(..) failed.");
+
+        // Wrap the items around the end of the buffer, so growing would have
+        // to move the front part.
+        d.push_back(2).expect("d.push_back(..) failed.");
+        d.push_back(3).expect("d.push_back(..) failed.");
+        d.push_front(1).expect("d.push_front(..) failed.");
+        d.push_front(0).expect("d.push_front(..) failed.");
+        assert_eq!(d.as_slices(), (&[0, 1][..], &[2, 3][..]));
+
+        match d.push_back(4) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        match d.push_front(4) {
+            Err(Error::AllocErr(_)) => {},
+            res => panic!("expected AllocErr, got {:?}", res),
+        }
+        // Nothing moved, and the ring can still be used in place.
+        assert_eq!(d.as_slices(), (&[0, 1][..], &[2, 3][..]));
+        assert_eq!(d.pop_front(), Some(0));
+        d.push_back(4).expect("d.push_back(..) failed.");
+        assert_eq!(d.make_contiguous(), &[1, 2, 3, 4]);
+    }
+
+    // A step of the model test below.
+    #[derive(Clone, Debug)]
+    enum Op {
+        PushBack(u32),
+        PushFront(u32),
+        PopBack,
+        PopFront,
+        Drain(usize, usize),
+        MakeContiguous,
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            any::<u32>().prop_map(Op::PushBack),
+            any::<u32>().prop_map(Op::PushFront),
+            Just(Op::PopBack),
+            Just(Op::PopFront),
+            (any::<usize>(), any::<usize>()).prop_map(|(a, b)| Op::Drain(a, b)),
+            Just(Op::MakeContiguous),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(prop_config(4))]
+
+        #[test]
+        fn prop_matches_std_vec_deque(ops in proptest::collection::vec(arb_op(), 0..64)) {
+            let mut buf = vec![0u8; 4096];
+            let alloc = LinearAlloc::new(&mut buf);
+            let mut d = VecDeque::with_growth(&alloc, Increment(3));
+            let mut model = collections::VecDeque::new();
+
+            for op in ops {
+                match op {
+                    Op::PushBack(x)  => {
+                        d.push_back(x).expect("d.push_back(..) failed.");
+                        model.push_back(x);
+                    },
+                    Op::PushFront(x) => {
+                        d.push_front(x).expect("d.push_front(..) failed.");
+                        model.push_front(x);
+                    },
+                    Op::PopBack      => prop_assert_eq!(d.pop_back(), model.pop_back()),
+                    Op::PopFront     => prop_assert_eq!(d.pop_front(), model.pop_front()),
+                    Op::Drain(a, b)  => {
+                        let len = model.len() + 1;
+                        let (a, b) = (a % len, b % len);
+                        let range = a.min(b)..a.max(b);
+                        let drained: vec::Vec<_> = d.drain(range.clone()).collect();
+                        let expected: vec::Vec<_> = model.drain(range).collect();
+                        prop_assert_eq!(drained, expected);
+                    },
+                    Op::MakeContiguous => {
+                        prop_assert_eq!(&d.make_contiguous()[..],
+                                        &model.make_contiguous()[..]);
+                    },
+                }
+                let model_items: vec::Vec<_> = model.iter().cloned().collect();
+                prop_assert_eq!(to_vec(&d), model_items);
+                prop_assert!(d.len() <= d.capacity());
+            }
+        }
+    }
+}